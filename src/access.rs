@@ -0,0 +1,78 @@
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use axum::{
+    body::HttpBody,
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use tracing::info;
+
+/// Layout of the access log lines
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum AccessLogFormat {
+    /// `<client IP> "<method> <path> <version>" <status> <size> <latency>ms`
+    Common,
+    /// One JSON object per request
+    Json,
+}
+
+#[derive(Serialize)]
+struct AccessLogEntry<'a> {
+    client_ip: Option<String>,
+    method: &'a str,
+    path: &'a str,
+    status: u16,
+    size: Option<u64>,
+    latency_ms: f64,
+}
+
+/// Middleware logging every request once its response is ready.
+pub async fn access_log(
+    State(format): State<AccessLogFormat>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string());
+    let method = request.method().clone();
+    let version = request.version();
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or_else(|| request.uri().path().to_string(), |path| path.to_string());
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let entry = AccessLogEntry {
+        client_ip,
+        method: method.as_str(),
+        path: &path,
+        status: response.status().as_u16(),
+        size: response.body().size_hint().exact(),
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+    };
+
+    match format {
+        AccessLogFormat::Common => info!(
+            target: "access",
+            "{} \"{} {} {:?}\" {} {} {:.1}ms",
+            entry.client_ip.as_deref().unwrap_or("-"),
+            entry.method,
+            entry.path,
+            version,
+            entry.status,
+            entry.size.map_or_else(|| "-".to_string(), |size| size.to_string()),
+            entry.latency_ms
+        ),
+        AccessLogFormat::Json => match serde_json::to_string(&entry) {
+            Ok(line) => info!(target: "access", "{}", line),
+            Err(_) => info!(target: "access", "{} {} {}", entry.method, entry.path, entry.status),
+        },
+    }
+    response
+}
@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use flate2::{write::GzEncoder, Compression};
+use http::header::{AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE, WWW_AUTHENTICATE};
+use serde::{Deserialize, Serialize};
+use tendermint_light_client::types::Hash;
+use tendermint_light_client_detector::Trace;
+use tracing::{info, warn};
+
+use crate::{
+    api::{lock_providers, parse_height, ApiError, ApiState},
+    audit::AuditEvent,
+    cli::{parse_trust_threshold, List},
+    provider::{make_providers, Providers},
+    state::{
+        Agreement, DetectResponse, SnapshotEntry, SnapshotResponse, TrustOptionsResponse,
+        WitnessDetection,
+    },
+    sync::{check_witness, record_divergences, WitnessCheck},
+};
+
+/// Bearer token required by the admin API
+pub struct AdminToken(String);
+
+pub type SharedAdminToken = Arc<AdminToken>;
+
+impl AdminToken {
+    /// Loads the token from a file, ignoring surrounding whitespace.
+    pub fn load(path: &Path) -> Result<SharedAdminToken> {
+        let contents = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read admin token {}", path.display()))?;
+        let token = contents.trim();
+        if token.is_empty() {
+            return Err(eyre!("admin token {} is empty", path.display()));
+        }
+        Ok(Arc::new(Self(token.to_string())))
+    }
+
+    /// Compares in constant time, so that the token cannot be guessed from response times.
+    fn matches(&self, candidate: &str) -> bool {
+        let (token, candidate) = (self.0.as_bytes(), candidate.as_bytes());
+        token.len() == candidate.len()
+            && token
+                .iter()
+                .zip(candidate)
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+/// Middleware rejecting requests without an `Authorization: Bearer <token>` header matching
+/// the admin token.
+pub async fn require_admin_token(
+    State(token): State<SharedAdminToken>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let provided = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match provided {
+        Some(provided) if token.matches(provided.trim()) => next.run(request).await,
+        _ => (
+            http::StatusCode::UNAUTHORIZED,
+            [(WWW_AUTHENTICATE, "Bearer")],
+            "missing or invalid admin token",
+        )
+            .into_response(),
+    }
+}
+
+/// Trust options to change, the others are left as they are
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrustOptionsRequest {
+    /// Trust threshold, as `X/Y`
+    pub trust_threshold: Option<String>,
+    /// Trusting period, in seconds
+    pub trusting_period: Option<u64>,
+    /// Maximum clock drift, in seconds
+    pub max_clock_drift: Option<u64>,
+}
+
+pub async fn trust_options_handler(State(api): State<ApiState>) -> Json<TrustOptionsResponse> {
+    Json(TrustOptionsResponse::from(&api.state.read().await.client))
+}
+
+/// Applies new trust options and rebuilds the providers with them, starting from the latest
+/// trusted block so that no trusted state is lost.
+///
+/// The current providers are kept if the new ones cannot be initialized.
+pub async fn update_trust_options_handler(
+    State(api): State<ApiState>,
+    Json(request): Json<TrustOptionsRequest>,
+) -> Result<Json<TrustOptionsResponse>, ApiError> {
+    let mut client = api.state.read().await.client.clone();
+    if let Some(trust_threshold) = &request.trust_threshold {
+        client.trust_threshold = parse_trust_threshold(trust_threshold)
+            .map_err(|e| (http::StatusCode::BAD_REQUEST, e.to_string()))?;
+    }
+    if let Some(trusting_period) = request.trusting_period {
+        if trusting_period == 0 {
+            return Err((
+                http::StatusCode::BAD_REQUEST,
+                "trusting period must be positive".to_string(),
+            ));
+        }
+        client.trusting_period = trusting_period;
+    }
+    if let Some(max_clock_drift) = request.max_clock_drift {
+        client.max_clock_drift = max_clock_drift;
+    }
+
+    let mut providers = lock_providers(&api.providers, api.api_timeout().await).await?;
+    let trusted_block = providers.primary.provider.latest_trusted().ok_or_else(|| {
+        (
+            http::StatusCode::SERVICE_UNAVAILABLE,
+            "no trusted block yet".to_string(),
+        )
+    })?;
+
+    // Keep the current roles, which may differ from the command line after a failover
+    client.trusted_height = Some(trusted_block.height());
+    client.trusted_hash = Some(trusted_block.signed_header.header.hash());
+    client.primary = providers.primary.url.clone();
+    client.witnesses = List(providers.witnesses.iter().map(|w| w.url.clone()).collect());
+
+    let stats = api.state.read().await.providers.clone();
+    let (primary_stats, witness_stats) = stats
+        .split_first()
+        .expect("provider stats are registered before the providers are initialized");
+    *providers =
+        make_providers(&client, &api.validator_sets, primary_stats.clone(), witness_stats)
+            .await
+            .map_err(|e| (http::StatusCode::BAD_GATEWAY, format!("{:#}", e)))?;
+    drop(providers);
+
+    info!(
+        "Rebuilt providers at height {} with trust threshold {}, trusting period {}s and maximum clock drift {}s",
+        trusted_block.height(), client.trust_threshold, client.trusting_period, client.max_clock_drift
+    );
+    api.audit.record(AuditEvent::TrustOptionsChanged {
+        trust_threshold: client.trust_threshold.to_string(),
+        trusting_period: client.trusting_period,
+        max_clock_drift: client.max_clock_drift,
+        trusted_height: trusted_block.height(),
+    });
+
+    let response = TrustOptionsResponse::from(&client);
+    api.state.write().await.client = client;
+    Ok(Json(response))
+}
+
+/// New root of trust, replacing the trusted state
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResetTrustRequest {
+    pub trusted_height: u64,
+    pub trusted_hash: Hash,
+}
+
+/// Rebuilds the providers trusting a new header, e.g. once the latest trusted block is outside the
+/// trusting period, discarding the trusted state and ending any halt.
+///
+/// Unlike other admin routes, it is also available while the providers are not initialized, since
+/// an expired trusted header of the command line makes their initialization fail. The current
+/// providers are kept if the new ones cannot be initialized.
+pub async fn reset_trust_handler(
+    State(api): State<ApiState>,
+    Json(request): Json<ResetTrustRequest>,
+) -> Result<Json<TrustOptionsResponse>, ApiError> {
+    let trusted_height = parse_height(request.trusted_height)?;
+    let mut client = api.state.read().await.client.clone();
+    client.trusted_height = Some(trusted_height);
+    client.trusted_hash = Some(request.trusted_hash);
+
+    let timeout_duration = { api.state.read().await.config.api_timeout };
+    let mut guard = tokio::time::timeout(timeout_duration, api.providers.lock())
+        .await
+        .map_err(|_| {
+            (
+                http::StatusCode::GATEWAY_TIMEOUT,
+                "timed out waiting for the sync in progress".to_string(),
+            )
+        })?;
+
+    let mut previous_height = None;
+    if let Some(providers) = guard.as_ref() {
+        previous_height = providers.primary.provider.latest_trusted().map(|block| block.height());
+        // Keep the current roles, which may differ from the command line after a failover
+        client.primary = providers.primary.url.clone();
+        client.witnesses = List(providers.witnesses.iter().map(|w| w.url.clone()).collect());
+    }
+
+    let stats = api.state.read().await.providers.clone();
+    let Some((primary_stats, witness_stats)) = stats.split_first() else {
+        return Err((
+            http::StatusCode::SERVICE_UNAVAILABLE,
+            "providers are not registered yet".to_string(),
+        ));
+    };
+    let providers = make_providers(&client, &api.validator_sets, primary_stats.clone(), witness_stats)
+        .await
+        .map_err(|e| (http::StatusCode::BAD_GATEWAY, format!("{:#}", e)))?;
+    let trusted_block = providers.primary.provider.latest_trusted();
+    *guard = Some(providers);
+
+    // Blocks verified from the previous root of trust are not served anymore
+    api.cache.clear();
+    {
+        let mut lock = api.state.write().await;
+        lock.client = client.clone();
+        lock.light_block = trusted_block;
+        lock.trace = Vec::new();
+        lock.halted = false;
+        lock.initialized = true;
+        lock.init_error = None;
+        lock.witness_check = None;
+        lock.resumed_from = None;
+    }
+    // Before the providers are released, so that the sync loop drops any halt before using them
+    api.trust_reset.send_replace(());
+    drop(guard);
+
+    warn!(
+        "Reset the trusted state to height {} and hash {} (previously trusted height: {})",
+        trusted_height,
+        request.trusted_hash,
+        previous_height.map_or_else(|| "none".to_string(), |height| height.to_string())
+    );
+    api.audit.record(AuditEvent::TrustedStateReset {
+        trusted_height,
+        trusted_hash: request.trusted_hash,
+        previous_height,
+    });
+    let _ = api.sync_trigger.send(());
+
+    Ok(Json(TrustOptionsResponse::from(&client)))
+}
+
+/// Dumps all blocks in the light store of the primary with their verification status, as JSON
+/// or, with `?format=gzip`, as a gzip-compressed JSON file.
+pub async fn snapshot_handler(
+    State(api): State<ApiState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, ApiError> {
+    let gzip = match params.get("format").map(String::as_str) {
+        None | Some("json") => false,
+        Some("gzip") => true,
+        Some(format) => {
+            return Err((
+                http::StatusCode::BAD_REQUEST,
+                format!("unsupported snapshot format {}, expected json or gzip", format),
+            ))
+        }
+    };
+
+    let (peer, store) = {
+        let providers = lock_providers(&api.providers, api.api_timeout().await).await?;
+        ((&providers.primary).into(), providers.primary.store.clone())
+    };
+    let blocks: Vec<_> = store
+        .entries()
+        .into_iter()
+        .map(|(light_block, status)| SnapshotEntry { status, light_block })
+        .collect();
+    let highest = blocks.last().map(|entry| entry.light_block.height());
+
+    let snapshot = SnapshotResponse {
+        chain_id: api.state.read().await.client.chain_id.clone(),
+        peer,
+        blocks,
+    };
+    if !gzip {
+        return Ok(Json(snapshot).into_response());
+    }
+
+    let compressed = gzip_json(&snapshot).map_err(|e| {
+        warn!("failed to compress snapshot: {}", e);
+        (
+            http::StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to compress snapshot".to_string(),
+        )
+    })?;
+
+    let file_name = format!(
+        "snapshot-{}-{}.json.gz",
+        snapshot.chain_id,
+        highest.map_or_else(|| "empty".to_string(), |height| height.to_string())
+    );
+    Ok((
+        [
+            (CONTENT_TYPE, "application/gzip".to_string()),
+            (CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+        ],
+        compressed,
+    )
+        .into_response())
+}
+
+/// Runs fork detection of the trace of the latest trusted block against every witness right away,
+/// reporting whether each of them agrees with the primary.
+///
+/// Divergences are recorded and reported like those found while syncing, and trigger a sync
+/// round, whose own fork detection halts syncing as usual.
+pub async fn detect_handler(State(api): State<ApiState>) -> Result<Json<DetectResponse>, ApiError> {
+    let client = api.state.read().await.client.clone();
+    if client.unsafe_no_detector() {
+        return Err((
+            http::StatusCode::CONFLICT,
+            "fork detection is disabled".to_string(),
+        ));
+    }
+
+    let mut providers = lock_providers(&api.providers, api.api_timeout().await).await?;
+    let Providers { primary, witnesses } = &mut *providers;
+    if witnesses.is_empty() {
+        return Err((
+            http::StatusCode::CONFLICT,
+            "no witnesses to run fork detection against".to_string(),
+        ));
+    }
+
+    let trusted_block = primary.provider.latest_trusted().ok_or_else(|| {
+        (
+            http::StatusCode::SERVICE_UNAVAILABLE,
+            "no trusted block yet".to_string(),
+        )
+    })?;
+    let trace = Trace::new(primary.get_trace(trusted_block.height())).map_err(|_| {
+        (
+            http::StatusCode::SERVICE_UNAVAILABLE,
+            "nothing was verified beyond the trusted block yet".to_string(),
+        )
+    })?;
+
+    info!(
+        "Performing fork detection with {} witnesses against primary at block height {} on request",
+        witnesses.len(),
+        trusted_block.height()
+    );
+    let mut reports = Vec::new();
+    let mut divergences = Vec::new();
+    for witness in witnesses.iter_mut() {
+        let check = check_witness(primary, witness, &trace, &client, &api.audit).await;
+        let (agreement, conflicting_height, error) = match check {
+            WitnessCheck::Agreed => (Agreement::Agreed, None, None),
+            WitnessCheck::Diverged(record) => {
+                let conflicting_height = record.conflicting_height;
+                divergences.push(*record);
+                (Agreement::Diverged, Some(conflicting_height), None)
+            }
+            WitnessCheck::Failed(e) => (Agreement::Failed, None, Some(e)),
+        };
+        reports.push(WitnessDetection {
+            peer: (&*witness).into(),
+            agreement,
+            conflicting_height,
+            error,
+        });
+    }
+    let primary = (&*primary).into();
+    drop(providers);
+
+    if !divergences.is_empty() {
+        record_divergences(&api.state, &api.audit, divergences).await;
+        let _ = api.sync_trigger.send(());
+    }
+
+    Ok(Json(DetectResponse {
+        height: trusted_block.height(),
+        hash: trusted_block.signed_header.header.hash(),
+        primary,
+        witnesses: reports,
+    }))
+}
+
+fn gzip_json(value: &impl Serialize) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    serde_json::to_writer(&mut encoder, value)?;
+    encoder.finish()
+}
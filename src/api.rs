@@ -1,32 +1,118 @@
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
 
 use axum::{
-    extract::{Query, State},
+    body::Bytes,
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
     Json,
 };
-use tokio::sync::{broadcast, watch};
-use tracing::info;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use color_eyre::eyre::eyre;
+use http::{
+    header::{AGE, CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+    HeaderMap, HeaderName, HeaderValue,
+};
+use serde::{Deserialize, Serialize};
+use prost::Message;
+use tendermint::{block::signed_header::SignedHeader, validator, Time};
+use tendermint_light_client::{
+    store::LightStore,
+    types::{Hash, Height, LightBlock, Status},
+    verifier::{options::Options, types::UntrustedBlockState, Verdict, Verifier},
+};
+use tendermint_proto::v0_38::types::{Header as RawHeader, LightBlock as RawLightBlock};
+use tendermint_rpc::{endpoint::block_results, Client};
+use tokio::sync::{broadcast, watch, MappedMutexGuard, MutexGuard};
+use tracing::{info, warn};
 
-use crate::state::{RootResponse, SharedState, StatusResponse};
+use crate::{
+    attest::{SharedAttestor, SIGNATURE_HEADER},
+    audit::SharedAuditLog,
+    bundle::TrustBundle,
+    cache::{SharedCache, SharedValidatorSetCache},
+    cli::DegradationPolicy,
+    evidence::DivergenceRecord,
+    ibc::{encode_header, HEADER_TYPE_URL},
+    ics23::verify_membership,
+    persist::time_from_instant,
+    provider::{error_message, offload, Providers, SharedProviders},
+    results::{self, last_results_hash},
+    signatures::BatchVerifier,
+    state::{
+        AppHashResponse, AppState, AttestationKeyResponse, BlockEventsResponse, BlockResultsResponse, Config,
+        DivergenceSummary,
+        EvidenceResponse, HeaderError, HeaderResponse, HeadersResponse, HealthResponse,
+        IbcHeaderResponse, LightBlockResponse, PeerResponse, PeersResponse, ProveResponse, RootResponse,
+        SharedState, StatusResponse, StatusUnavailableResponse, SyncState, TraceStep, ValidatorsDiffResponse,
+        VerifyResponse,
+    },
+    sync::{record_divergences, run_fork_detector},
+    validators,
+};
 
-pub type AppStateType = (
-    SharedState,
-    broadcast::Sender<()>,
-    watch::Receiver<()>,
-);
+/// State shared by all API handlers
+#[derive(Clone)]
+pub struct ApiState {
+    pub state: SharedState,
+    pub sync_trigger: broadcast::Sender<()>,
+    pub sync_done: watch::Receiver<()>,
+    /// Notifies the sync loop of a reset of the trusted state, ending any halt
+    pub trust_reset: Arc<watch::Sender<()>>,
+    pub providers: SharedProviders,
+    pub cache: SharedCache,
+    pub validator_sets: SharedValidatorSetCache,
+    pub attestor: Option<SharedAttestor>,
+    pub audit: SharedAuditLog,
+}
 
-pub async fn root_handler() -> Json<RootResponse> {
+impl ApiState {
+    /// How long requests that cannot override it with `?timeout` wait on the sync loop
+    pub async fn api_timeout(&self) -> Duration {
+        self.state.read().await.config.api_timeout
+    }
+}
+
+pub type ApiError = (http::StatusCode, String);
+
+pub async fn root_handler(State(ApiState { state, .. }): State<ApiState>) -> Json<RootResponse> {
     let response = RootResponse {
         name: "helios-light-client",
         version: env!("CARGO_PKG_VERSION"),
+        warning: state.read().await.config.warning(),
     };
     Json(response)
 }
 
-pub async fn status_handler(
-    State((state, sync_trigger, mut sync_done)): State<AppStateType>,
-    Query(params): Query<std::collections::HashMap<String, String>>,
-) -> Result<Json<StatusResponse>, http::StatusCode> {
+/// Returns the latest trusted light block along with its verification trace, syncing first
+/// if it is older than the freshness threshold (overridable with `?freshness=<seconds>`), and
+/// whether it is stale, i.e. served without being refreshed although older than the threshold.
+///
+/// The sync is waited for up to the API timeout (overridable with `?timeout=<seconds>`), unless
+/// stale blocks are served while they are refreshed in the background.
+///
+/// Under [`DegradationPolicy::ServeLastVerified`], the sync is not waited for after a failed one,
+/// and a sync that times out serves the stale block rather than `504 Gateway Timeout`, as long as
+/// the block is within its trusting period.
+pub async fn fresh_light_block(
+    api: &ApiState,
+    params: &HashMap<String, String>,
+) -> Result<(LightBlock, Vec<TraceStep>, bool), http::StatusCode> {
+    let ApiState {
+        state,
+        sync_trigger,
+        sync_done,
+        ..
+    } = api;
+    let mut sync_done = sync_done.clone();
+    // Only a sync completing from now on is waited for, not one that completed earlier
+    sync_done.borrow_and_update();
+
+    if state.read().await.clock_refusal().is_some() {
+        return Err(http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+
     let freshness_threshold = {
         let lock = state.read().await;
         params
@@ -36,45 +122,1287 @@ pub async fn status_handler(
             .unwrap_or(lock.config.freshness_threshold)
     };
 
-    let needs_sync = {
+    let (needs_sync, mut stale, serve_last_verified) = {
         let mut lock = state.write().await;
-        let elapsed = lock.last_sync.elapsed();
+        lock.api_requests += 1;
+        let is_stale = lock
+            .last_sync
+            .is_none_or(|last_sync| last_sync.elapsed() > freshness_threshold);
+        // Without a trusted block, there is nothing to serve in the meantime
+        let serve_last_verified = lock.config.degradation_policy == DegradationPolicy::ServeLastVerified
+            && lock.light_block.is_some();
+        let revalidate = (lock.config.stale_while_revalidate && lock.light_block.is_some())
+            || (serve_last_verified && !lock.last_sync_success);
 
-        if elapsed > freshness_threshold {
+        if lock.halted || !lock.initialized {
+            // No sync will complete until the halt is over or the providers are initialized,
+            // serve the last trusted block if any
+            (false, is_stale, serve_last_verified)
+        } else if is_stale || lock.light_block.is_none() {
             if !lock.syncing {
                 lock.syncing = true;
                 // Send a sync request, ignore error if no receivers
                 let _ = sync_trigger.send(());
-                true
-            } else {
+            } else if !revalidate {
                 info!("Sync already in progress, waiting for it to complete...");
-                true // A sync is in progress, so we need to wait
             }
+            // Wait for the sync, unless the stale block is served while it runs
+            (!revalidate, revalidate, serve_last_verified)
         } else {
-            false // Data is fresh enough
+            (false, false, serve_last_verified) // Data is fresh enough
         }
     };
 
     if needs_sync {
         // Wait for the sync to complete with timeout
-        let timeout_duration = { state.read().await.config.api_timeout };
+        let timeout_duration = request_timeout(&state.read().await.config, params);
         let res = tokio::time::timeout(timeout_duration, sync_done.changed()).await;
         match res {
             Ok(Ok(_)) => {},
             Ok(Err(_)) => return Err(http::StatusCode::INTERNAL_SERVER_ERROR),
+            Err(_) if serve_last_verified => stale = true,
             Err(_) => return Err(http::StatusCode::GATEWAY_TIMEOUT),
         }
     }
 
     let lock = state.read().await;
-    if let Some(light_block) = &lock.light_block {
-        let response = StatusResponse {
-            block_height: light_block.height(),
-            block_hash: light_block.signed_header.header.hash(),
-            block_timestamp: light_block.signed_header.header.time,
-        };
-        Ok(Json(response))
+    if lock.config.degradation_policy == DegradationPolicy::ServeLastVerified && lock.trust_expired() {
+        return Err(http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+    lock.light_block
+        .clone()
+        .map(|light_block| (light_block, lock.trace.clone(), stale))
+        .ok_or(http::StatusCode::SERVICE_UNAVAILABLE)
+}
+
+/// How long a request waits on the sync loop: the `?timeout=<seconds>` it asks for, up to the
+/// maximum API timeout, or the API timeout.
+fn request_timeout(config: &Config, params: &HashMap<String, String>) -> Duration {
+    params
+        .get("timeout")
+        .and_then(|s| s.parse::<u64>().ok())
+        .map(|secs| Duration::from_secs(secs).min(config.max_api_timeout))
+        .unwrap_or(config.api_timeout)
+}
+
+/// Whether the verification trace is requested with `?trace=true`
+fn trace_requested(params: &HashMap<String, String>) -> bool {
+    params.get("trace").is_some_and(|trace| trace == "true")
+}
+
+/// Encoding of light blocks and headers, selected with `?format=`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BlockFormat {
+    /// Tendermint RPC JSON
+    #[default]
+    Json,
+    /// Canonical protobuf bytes, as `application/x-protobuf`
+    Proto,
+    /// Canonical protobuf bytes, base64-encoded in the JSON response
+    Base64,
+}
+
+/// Format requested with `?format=json|proto|base64`, JSON by default
+fn block_format(params: &HashMap<String, String>) -> Result<BlockFormat, ApiError> {
+    match params.get("format").map(String::as_str) {
+        None | Some("json") => Ok(BlockFormat::Json),
+        Some("proto") => Ok(BlockFormat::Proto),
+        Some("base64") => Ok(BlockFormat::Base64),
+        Some(other) => Err((
+            http::StatusCode::BAD_REQUEST,
+            format!("unknown format {}, expected json, proto or base64", other),
+        )),
+    }
+}
+
+/// Entity tag of a response describing the block with the given hash
+fn etag(hash: &Hash, with_trace: bool, format: BlockFormat) -> HeaderValue {
+    let trace = if with_trace { "-trace" } else { "" };
+    let format = match format {
+        BlockFormat::Json => "",
+        BlockFormat::Proto => "-proto",
+        BlockFormat::Base64 => "-base64",
+    };
+    HeaderValue::from_str(&format!("\"{}{}{}\"", hash, trace, format))
+        .expect("hex hash is a valid header value")
+}
+
+/// ETag of a status response, which unlike the light block changes between syncs of the same
+/// block, derived from the block hash and the sync fields. The ages and check times, which change
+/// on every request or sync, are left out: they are in the `Age` header.
+fn status_etag(status: &StatusResponse) -> HeaderValue {
+    let state = match status.state {
+        SyncState::Initializing => "initializing",
+        SyncState::Synced => "synced",
+        SyncState::Degraded => "degraded",
+        SyncState::Halted => "halted",
+    };
+    let network_height = status.network_height.map_or(0, |height| height.value());
+    let witnesses = status.witness_check.map_or(String::new(), |check| {
+        format!("-w{}.{}.{}.{}", check.checked, check.agreed, check.diverged, check.errored)
+    });
+    let flags = [(status.halted, "-halted"), (status.stale, "-stale"), (status.trace.is_some(), "-trace")]
+        .iter()
+        .filter_map(|(set, flag)| set.then_some(*flag))
+        .collect::<String>();
+    HeaderValue::from_str(&format!(
+        "\"{}-{}-f{}-n{}{}{}\"",
+        status.block_hash, state, status.consecutive_sync_failures, network_height, witnesses, flags
+    ))
+    .expect("hex hash and numbers are a valid header value")
+}
+
+/// `Age` and `Cache-Control` headers of responses describing the latest trusted block.
+///
+/// The age is the time since the last successful sync, and the block is considered fresh
+/// for the freshness threshold.
+fn staleness_headers(state: &AppState) -> [(HeaderName, HeaderValue); 2] {
+    let age = state.last_sync.map_or(0, |last_sync| last_sync.elapsed().as_secs());
+    let max_age = state.config.freshness_threshold.as_secs();
+    [
+        (AGE, HeaderValue::from(age)),
+        (
+            CACHE_CONTROL,
+            HeaderValue::from_str(&format!("max-age={}", max_age)).expect("valid header value"),
+        ),
+    ]
+}
+
+/// Whether the `If-None-Match` request header matches the given entity tag
+fn not_modified(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Some(Ok(if_none_match)) = headers.get(IF_NONE_MATCH).map(HeaderValue::to_str) else {
+        return false;
+    };
+    let Ok(etag) = etag.to_str() else {
+        return false;
+    };
+
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+/// Serializes `body` as JSON tagged with `etag`, or answers `304 Not Modified` if the
+/// client already holds it.
+///
+/// If an attestation key is configured, the serialized body is signed and the signature
+/// returned in the [`SIGNATURE_HEADER`] header.
+fn block_response<T: Serialize>(
+    api: &ApiState,
+    headers: &HeaderMap,
+    etag: HeaderValue,
+    body: T,
+) -> Response {
+    if not_modified(headers, &etag) {
+        return (http::StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response();
+    }
+
+    let Some(attestor) = &api.attestor else {
+        return ([(ETAG, etag)], Json(body)).into_response();
+    };
+
+    let body = match serde_json::to_vec(&body) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("failed to serialize response: {}", e);
+            return http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    signed_response(attestor, etag, "application/json", body)
+}
+
+/// Counterpart of [`block_response`] for bodies that are already encoded, e.g. as protobuf for
+/// `?format=proto`
+fn encoded_response(
+    api: &ApiState,
+    headers: &HeaderMap,
+    etag: HeaderValue,
+    content_type: &'static str,
+    body: Vec<u8>,
+) -> Response {
+    if not_modified(headers, &etag) {
+        return (http::StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response();
+    }
+
+    match &api.attestor {
+        Some(attestor) => signed_response(attestor, etag, content_type, body),
+        None => (
+            [
+                (ETAG, etag),
+                (CONTENT_TYPE, HeaderValue::from_static(content_type)),
+            ],
+            body,
+        )
+            .into_response(),
+    }
+}
+
+fn signed_response(
+    attestor: &SharedAttestor,
+    etag: HeaderValue,
+    content_type: &'static str,
+    body: Vec<u8>,
+) -> Response {
+    let signature = attestor.sign(&body);
+    (
+        [
+            (ETAG, etag),
+            (CONTENT_TYPE, HeaderValue::from_static(content_type)),
+            (
+                HeaderName::from_static(SIGNATURE_HEADER),
+                HeaderValue::from_str(&signature).expect("base64 is a valid header value"),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// Responds with a light block in the requested format.
+///
+/// The protobuf encoding is a `tendermint.types.LightBlock`, which has no next validator set;
+/// the trace and warning are only part of the JSON responses.
+fn light_block_response(
+    api: &ApiState,
+    headers: &HeaderMap,
+    etag: HeaderValue,
+    format: BlockFormat,
+    light_block: LightBlock,
+    trace: Option<Vec<TraceStep>>,
+    warning: Option<&'static str>,
+) -> Response {
+    match format {
+        BlockFormat::Json => {
+            block_response(api, headers, etag, LightBlockResponse { light_block, trace, warning })
+        }
+        BlockFormat::Base64 => {
+            let response = LightBlockResponse {
+                light_block: BASE64_STANDARD.encode(encode_light_block(&light_block)),
+                trace,
+                warning,
+            };
+            block_response(api, headers, etag, response)
+        }
+        BlockFormat::Proto => {
+            encoded_response(api, headers, etag, "application/x-protobuf", encode_light_block(&light_block))
+        }
+    }
+}
+
+pub async fn attestation_key_handler(
+    State(api): State<ApiState>,
+) -> Result<Json<AttestationKeyResponse>, ApiError> {
+    let attestor = api.attestor.as_ref().ok_or_else(|| {
+        (
+            http::StatusCode::NOT_FOUND,
+            "no attestation key is configured".to_string(),
+        )
+    })?;
+
+    Ok(Json(AttestationKeyResponse {
+        algorithm: "ed25519",
+        public_key: attestor.public_key(),
+    }))
+}
+
+/// Exports the latest trusted block as a bundle from which other instances can start syncing,
+/// signed with the attestation key if one is configured.
+pub async fn trust_bundle_handler(State(api): State<ApiState>) -> Result<Json<TrustBundle>, ApiError> {
+    let lock = api.state.read().await;
+    check_halted(&lock)?;
+    check_clock(&lock)?;
+    let light_block = lock.light_block.as_ref().ok_or_else(|| {
+        (
+            http::StatusCode::SERVICE_UNAVAILABLE,
+            "no block has been verified yet".to_string(),
+        )
+    })?;
+
+    Ok(Json(TrustBundle::new(
+        &lock.client.chain_id,
+        light_block,
+        api.attestor.as_deref(),
+        lock.config.warning(),
+    )))
+}
+
+pub async fn status_handler(
+    State(api): State<ApiState>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, http::StatusCode> {
+    let (light_block, trace, stale) = match fresh_light_block(&api, &params).await {
+        Ok(found) => found,
+        Err(http::StatusCode::SERVICE_UNAVAILABLE) => {
+            let lock = api.state.read().await;
+            let response = StatusUnavailableResponse {
+                state: lock.sync_state(),
+                error: lock
+                    .clock_refusal()
+                    .or_else(|| {
+                        lock.trust_expired()
+                            .then(|| "the last verified block is past its trusting period".to_string())
+                    })
+                    .or_else(|| lock.init_error.clone()),
+            };
+            return Ok((http::StatusCode::SERVICE_UNAVAILABLE, Json(response)).into_response());
+        }
+        Err(status) => return Err(status),
+    };
+    let block_hash = light_block.signed_header.header.hash();
+    let with_trace = trace_requested(&params);
+
+    let lock = api.state.read().await;
+    let header = &light_block.signed_header.header;
+    let network_tip = lock.network_tip;
+    let response = StatusResponse {
+        block_height: light_block.height(),
+        block_hash,
+        block_timestamp: header.time,
+        app_hash: header.app_hash.to_string(),
+        halted: lock.halted,
+        stale,
+        state: lock.sync_state(),
+        last_sync_success: lock.last_sync_success,
+        consecutive_sync_failures: lock.sync_failures,
+        secs_since_last_success: lock.last_sync.map(|last_sync| last_sync.elapsed().as_secs()),
+        network_height: network_tip.map(|tip| tip.height),
+        lag_blocks: network_tip.map(|tip| tip.height.value().saturating_sub(header.height.value())),
+        lag_secs: network_tip.map(|tip| {
+            tip.time.duration_since(header.time).unwrap_or_default().as_secs()
+        }),
+        witness_check: lock.witness_check,
+        trace: with_trace.then_some(trace),
+        warning: lock.config.warning(),
+    };
+    let staleness = staleness_headers(&lock);
+    drop(lock);
+
+    let etag = status_etag(&response);
+    Ok((staleness, block_response(&api, &headers, etag, response)).into_response())
+}
+
+/// Rejects `?trace=true` for protobuf responses, which have no room for the trace
+fn check_trace_format(with_trace: bool, format: BlockFormat) -> Result<(), ApiError> {
+    if with_trace && format == BlockFormat::Proto {
+        return Err((
+            http::StatusCode::BAD_REQUEST,
+            "the verification trace is not available with format=proto".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+pub async fn latest_light_block_handler(
+    State(api): State<ApiState>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let format = block_format(&params)?;
+    let with_trace = trace_requested(&params);
+    check_trace_format(with_trace, format)?;
+
+    let (light_block, trace, _) = fresh_light_block(&api, &params)
+        .await
+        .map_err(|status| (status, String::new()))?;
+    let etag = etag(&light_block.signed_header.header.hash(), with_trace, format);
+
+    let lock = api.state.read().await;
+    let warning = lock.config.warning();
+    let staleness = staleness_headers(&lock);
+    drop(lock);
+
+    let trace = with_trace.then_some(trace);
+    let response = light_block_response(&api, &headers, etag, format, light_block, trace, warning);
+    Ok((staleness, response).into_response())
+}
+
+pub async fn light_block_handler(
+    State(api): State<ApiState>,
+    Path(height): Path<u64>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let height = parse_height(height)?;
+    let format = block_format(&params)?;
+    let with_trace = trace_requested(&params);
+    check_trace_format(with_trace, format)?;
+
+    let timeout = request_timeout(&api.state.read().await.config, &params);
+    let light_block = verified_block(&api, height, timeout).await?;
+    let etag = etag(&light_block.signed_header.header.hash(), with_trace, format);
+
+    let trace = if with_trace {
+        let providers = lock_providers(&api.providers, timeout).await?;
+        let trace = providers.primary.get_trace(height);
+        Some(trace.iter().map(TraceStep::from).collect())
+    } else {
+        None
+    };
+
+    let warning = api.state.read().await.config.warning();
+    Ok(light_block_response(&api, &headers, etag, format, light_block, trace, warning))
+}
+
+pub async fn peers_handler(State(ApiState { state, .. }): State<ApiState>) -> Json<PeersResponse> {
+    let lock = state.read().await;
+    let peers = lock
+        .providers
+        .iter()
+        .filter_map(|stats| stats.lock().ok())
+        .map(|stats| PeerResponse {
+            role: stats.role,
+            url: stats.url.clone(),
+            peer_id: stats.peer_id,
+            health: stats.health(),
+            wrong_chain: stats.wrong_chain.clone(),
+            last_contact: stats.last_contact().and_then(time_from_instant),
+            success_rate: stats.success_rate(),
+            mean_latency_ms: stats.mean_latency().map(|d| d.as_millis()),
+            last_error: stats.last_error.clone(),
+        })
+        .collect();
+
+    Json(PeersResponse { peers })
+}
+
+/// Reports whether the daemon is healthy, with `503 Service Unavailable` if it is not.
+pub async fn health_handler(
+    State(api): State<ApiState>,
+) -> (http::StatusCode, Json<HealthResponse>) {
+    let lock = api.state.read().await;
+    let mut issues = Vec::new();
+
+    if !lock.initialized {
+        issues.push(match &lock.init_error {
+            Some(e) => format!("providers are not initialized yet: {}", e),
+            None => "providers are not initialized yet".to_string(),
+        });
+    }
+
+    for stats in lock.providers.iter().filter_map(|stats| stats.lock().ok()) {
+        if let Some(chain) = &stats.wrong_chain {
+            issues.push(format!(
+                "{} {} serves chain {}, not {}",
+                stats.role, stats.url, chain, lock.client.chain_id
+            ));
+        }
+    }
+
+    issues.extend(lock.clock_offset_issue());
+
+    if let Some(skew) = lock.clock_skew {
+        if skew > lock.client.max_clock_drift as f64 {
+            issues.push(format!(
+                "local clock is {:.1} seconds behind the latest block time (maximum clock drift: {} seconds)",
+                skew, lock.client.max_clock_drift
+            ));
+        }
+    }
+
+    let status = if issues.is_empty() {
+        http::StatusCode::OK
+    } else {
+        http::StatusCode::SERVICE_UNAVAILABLE
+    };
+    let response = HealthResponse {
+        healthy: issues.is_empty(),
+        clock_skew_secs: lock.clock_skew,
+        clock_offset: lock.clock_offset,
+        issues,
+    };
+    (status, Json(response))
+}
+
+pub async fn evidence_handler(State(api): State<ApiState>) -> Json<EvidenceResponse> {
+    let lock = api.state.read().await;
+    let divergences = lock
+        .divergences
+        .iter()
+        .enumerate()
+        .map(|(id, record)| DivergenceSummary::new(id, record))
+        .collect();
+
+    Json(EvidenceResponse { divergences })
+}
+
+pub async fn divergence_handler(
+    State(api): State<ApiState>,
+    Path(id): Path<usize>,
+) -> Result<Json<DivergenceRecord>, ApiError> {
+    let lock = api.state.read().await;
+    lock.divergences
+        .get(id)
+        .cloned()
+        .map(Json)
+        .ok_or_else(|| (http::StatusCode::NOT_FOUND, format!("no divergence with id {}", id)))
+}
+
+/// Locks the providers, waiting at most for `timeout` if a sync round is in progress.
+pub async fn lock_providers(
+    providers: &SharedProviders,
+    timeout: Duration,
+) -> Result<MappedMutexGuard<'_, Providers>, ApiError> {
+    let guard = tokio::time::timeout(timeout, providers.lock())
+        .await
+        .map_err(|_| {
+            (
+                http::StatusCode::GATEWAY_TIMEOUT,
+                "timed out waiting for the sync in progress".to_string(),
+            )
+        })?;
+
+    MutexGuard::try_map(guard, Option::as_mut).map_err(|_| {
+        (
+            http::StatusCode::SERVICE_UNAVAILABLE,
+            "providers are not initialized".to_string(),
+        )
+    })
+}
+
+/// Refuses to verify or serve blocks other than the last trusted one while syncing is halted,
+/// since the primary may be the provider that forked.
+fn check_halted(state: &AppState) -> Result<(), ApiError> {
+    if state.halted {
+        return Err((
+            http::StatusCode::SERVICE_UNAVAILABLE,
+            "syncing is halted after a detected fork".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Refuses to serve verified data while the local clock is deemed wrong, see
+/// [`AppState::clock_refusal`].
+fn check_clock(state: &AppState) -> Result<(), ApiError> {
+    match state.clock_refusal() {
+        Some(issue) => Err((http::StatusCode::SERVICE_UNAVAILABLE, issue)),
+        None => Ok(()),
+    }
+}
+
+/// Verifies the light block at `height` against the primary, from the closest trusted block.
+///
+/// As in a sync round, the verification trace is cross-checked against the witnesses before the
+/// blocks it verified are added to the light store of the primary, so that a block is only served
+/// once no witness diverges from it. Divergences are recorded like those of the sync rounds.
+async fn verify_at(api: &ApiState, providers: &mut Providers, height: Height) -> Result<LightBlock, ApiError> {
+    let Providers { primary, witnesses } = providers;
+    if let Some(light_block) = primary.store.get_trusted_or_verified(height) {
+        return Ok(light_block);
+    }
+
+    let (light_block, trace, store) = primary.verify_to_height_detached(height).map_err(|e| {
+        warn!("failed to verify block {} on primary ({}): {}", height, primary, error_message(&e));
+        (
+            http::StatusCode::BAD_GATEWAY,
+            format!("failed to verify header at height {}: {}", height, error_message(&e)),
+        )
+    })?;
+
+    let client = api.state.read().await.client.clone();
+    let detection = run_fork_detector(primary, witnesses, trace.clone(), &client, &api.audit).await;
+    if !detection.cross_checked(&client) {
+        if detection.divergences.is_empty() {
+            return Err((
+                http::StatusCode::BAD_GATEWAY,
+                format!("header at height {} could not be cross-checked against the witnesses", height),
+            ));
+        }
+        record_divergences(&api.state, &api.audit, detection.divergences).await;
+        return Err((
+            http::StatusCode::BAD_GATEWAY,
+            format!("fork detected while verifying header at height {}", height),
+        ));
+    }
+
+    primary.merge_verified(height, &trace, &store);
+    Ok(light_block)
+}
+
+/// Returns the verified light blocks at `heights`, in order, from the cache when possible.
+///
+/// The providers are only locked, waiting at most for `timeout`, if some heights are not cached,
+/// in which case they are verified against the primary, see [`verify_at`], and cached.
+async fn verified_blocks(
+    api: &ApiState,
+    heights: &[Height],
+    timeout: Duration,
+) -> Result<Vec<Result<LightBlock, ApiError>>, ApiError> {
+    {
+        let lock = api.state.read().await;
+        check_halted(&lock)?;
+        check_clock(&lock)?;
+    }
+
+    let mut blocks: Vec<_> = heights
+        .iter()
+        .map(|height| api.cache.get(*height).map(Ok))
+        .collect();
+
+    if blocks.iter().any(Option::is_none) {
+        let mut providers = lock_providers(&api.providers, timeout).await?;
+        for (height, block) in heights.iter().zip(&mut blocks) {
+            if block.is_none() {
+                let result = verify_at(api, &mut providers, *height).await;
+                if let Ok(light_block) = &result {
+                    api.cache.insert(light_block.clone());
+                }
+                *block = Some(result);
+            }
+        }
+    }
+
+    Ok(blocks.into_iter().flatten().collect())
+}
+
+/// Returns the verified light block at `height`, see [`verified_blocks`].
+pub async fn verified_block(
+    api: &ApiState,
+    height: Height,
+    timeout: Duration,
+) -> Result<LightBlock, ApiError> {
+    verified_blocks(api, &[height], timeout)
+        .await?
+        .pop()
+        .expect("one block per height")
+}
+
+pub fn parse_height(height: u64) -> Result<Height, ApiError> {
+    Height::try_from(height)
+        .map_err(|e| (http::StatusCode::BAD_REQUEST, format!("invalid height: {}", e)))
+}
+
+/// Fetches the block results at `height` from the primary and checks them against the
+/// `last_results_hash` of the verified header at `height + 1`, returned with its hash.
+async fn checked_block_results(
+    api: &ApiState,
+    height: Height,
+) -> Result<(Hash, block_results::Response), ApiError> {
+    let next_height = height.increment();
+
+    // The results of block H are committed to by the header of block H+1
+    let timeout = api.api_timeout().await;
+    let header = verified_block(api, next_height, timeout).await?.signed_header.header;
+
+    let providers = lock_providers(&api.providers, timeout).await?;
+    let primary = &providers.primary;
+    let block_results = primary.rpc_client().block_results(height).await.map_err(|e| {
+        warn!("failed to fetch block results {} from primary ({}): {}", height, primary, error_message(&e));
+        (
+            http::StatusCode::BAD_GATEWAY,
+            format!("failed to fetch block results at height {}: {}", height, error_message(&e)),
+        )
+    })?;
+    drop(providers);
+
+    let computed_hash = last_results_hash(block_results.txs_results.as_deref().unwrap_or_default());
+    if header.last_results_hash != Some(computed_hash) {
+        warn!(
+            "block results at height {} do not match last_results_hash of verified header {}",
+            height, next_height
+        );
+        return Err((
+            http::StatusCode::BAD_GATEWAY,
+            format!(
+                "block results at height {} do not match the last_results_hash of the verified header at height {}",
+                height, next_height
+            ),
+        ));
+    }
+    Ok((computed_hash, block_results))
+}
+
+pub async fn block_results_handler(
+    State(api): State<ApiState>,
+    Path(height): Path<u64>,
+) -> Result<Json<BlockResultsResponse>, ApiError> {
+    let height = parse_height(height)?;
+    let (last_results_hash, block_results) = checked_block_results(&api, height).await?;
+    let (txs_results, unverified) = results::split(block_results);
+
+    let lock = api.state.read().await;
+    Ok(Json(BlockResultsResponse {
+        height,
+        verified_header_height: height.increment(),
+        last_results_hash,
+        txs_results,
+        unverified,
+        warning: lock.config.warning(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlockEventsParams {
+    /// Only return events of this type, e.g. `transfer`
+    #[serde(rename = "type")]
+    kind: Option<String>,
+}
+
+/// Events of the block results at `height`, once the transaction results are checked as in
+/// [`block_results_handler`]. The events themselves are not committed to by any header.
+pub async fn block_events_handler(
+    State(api): State<ApiState>,
+    Path(height): Path<u64>,
+    Query(params): Query<BlockEventsParams>,
+) -> Result<Json<BlockEventsResponse>, ApiError> {
+    let height = parse_height(height)?;
+    let (_, block_results) = checked_block_results(&api, height).await?;
+    let (_, unverified) = results::split(block_results);
+
+    let lock = api.state.read().await;
+    Ok(Json(BlockEventsResponse {
+        height,
+        verified_header_height: height.increment(),
+        unverified_events: results::events(unverified, params.kind.as_deref()),
+        warning: lock.config.warning(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IbcHeaderParams {
+    /// Height of the consensus state the IBC client currently trusts
+    trusted_height: u64,
+    #[serde(default)]
+    encoding: IbcHeaderEncoding,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IbcHeaderEncoding {
+    #[default]
+    Base64,
+    Binary,
+}
+
+pub async fn ibc_header_handler(
+    State(api): State<ApiState>,
+    Path(height): Path<u64>,
+    Query(params): Query<IbcHeaderParams>,
+) -> Result<Response, ApiError> {
+    let height = parse_height(height)?;
+    let trusted_height = parse_height(params.trusted_height)?;
+    if trusted_height >= height {
+        return Err((
+            http::StatusCode::BAD_REQUEST,
+            format!("trusted height {} must be below height {}", trusted_height, height),
+        ));
+    }
+
+    let heights = [trusted_height, trusted_height.increment(), height];
+    let blocks = verified_blocks(&api, &heights, api.api_timeout().await).await?;
+    let [trusted, after_trusted, target]: [_; 3] = blocks.try_into().expect("one block per height");
+    let (trusted, after_trusted, target) = (trusted?, after_trusted?, target?);
+    if after_trusted.validators.hash() != trusted.signed_header.header.next_validators_hash {
+        return Err((
+            http::StatusCode::BAD_GATEWAY,
+            format!(
+                "the validators at height {} are not the next validators of the trusted block",
+                after_trusted.height()
+            ),
+        ));
+    }
+
+    let header = encode_header(&target, &trusted, &after_trusted);
+    let response = match params.encoding {
+        IbcHeaderEncoding::Binary => (
+            [(http::header::CONTENT_TYPE, "application/x-protobuf")],
+            header,
+        )
+            .into_response(),
+        IbcHeaderEncoding::Base64 => Json(IbcHeaderResponse {
+            height,
+            trusted_height,
+            type_url: HEADER_TYPE_URL,
+            header: BASE64_STANDARD.encode(header),
+            warning: api.state.read().await.config.warning(),
+        })
+        .into_response(),
+    };
+    Ok(response)
+}
+
+pub async fn app_hash_handler(
+    State(api): State<ApiState>,
+    Path(height): Path<u64>,
+) -> Result<Json<AppHashResponse>, ApiError> {
+    let height = parse_height(height)?;
+    let header = verified_block(&api, height, api.api_timeout().await).await?.signed_header.header;
+
+    let lock = api.state.read().await;
+    Ok(Json(AppHashResponse {
+        height,
+        block_hash: header.hash(),
+        app_hash: header.app_hash.to_string(),
+        warning: lock.config.warning(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ValidatorsDiffParams {
+    from: u64,
+    to: u64,
+}
+
+/// Compares the verified validator sets at two heights.
+pub async fn validators_diff_handler(
+    State(api): State<ApiState>,
+    Query(params): Query<ValidatorsDiffParams>,
+) -> Result<Json<ValidatorsDiffResponse>, ApiError> {
+    let (from, to) = (parse_height(params.from)?, parse_height(params.to)?);
+
+    let blocks = verified_blocks(&api, &[from, to], api.api_timeout().await).await?;
+    let [from_block, to_block]: [_; 2] = blocks.try_into().expect("one block per height");
+    let (from_block, to_block) = (from_block?, to_block?);
+
+    let diff = validators::diff(&from_block.validators, &to_block.validators);
+    Ok(Json(ValidatorsDiffResponse {
+        from,
+        to,
+        from_total_voting_power: from_block.validators.total_voting_power(),
+        to_total_voting_power: to_block.validators.total_voting_power(),
+        joined: diff.joined,
+        left: diff.left,
+        power_changes: diff.power_changes,
+        warning: api.state.read().await.config.warning(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProveRequest {
+    /// ABCI query path, e.g. `store/bank/key`
+    path: String,
+    /// Base64-encoded key
+    key: String,
+    /// Height at which to query the store (default: the one before the latest trusted block)
+    height: Option<u64>,
+}
+
+/// Queries a key from the primary with a proof, and verifies the proof against the app hash of
+/// the verified header committing to the queried height.
+pub async fn prove_handler(
+    State(api): State<ApiState>,
+    Json(request): Json<ProveRequest>,
+) -> Result<Json<ProveResponse>, ApiError> {
+    let key = BASE64_STANDARD
+        .decode(&request.key)
+        .map_err(|e| (http::StatusCode::BAD_REQUEST, format!("key is not valid base64: {}", e)))?;
+    let store = request
+        .path
+        .strip_prefix("store/")
+        .and_then(|path| path.strip_suffix("/key"))
+        .filter(|store| !store.is_empty() && !store.contains('/'))
+        .ok_or_else(|| {
+            (
+                http::StatusCode::BAD_REQUEST,
+                format!("path {} is not of the form store/<name>/key", request.path),
+            )
+        })?
+        .to_string();
+
+    // The state after block H is committed to by the app hash in the header of block H+1
+    let height = match request.height {
+        Some(height) => parse_height(height)?,
+        None => {
+            let latest = api.state.read().await.light_block.as_ref().map(LightBlock::height);
+            let latest = latest
+                .filter(|height| height.value() > 1)
+                .ok_or_else(|| (http::StatusCode::SERVICE_UNAVAILABLE, "no trusted block yet".to_string()))?;
+            parse_height(latest.value() - 1)?
+        }
+    };
+    let next_height = height.increment();
+    let timeout = api.api_timeout().await;
+    let header = verified_block(&api, next_height, timeout).await?.signed_header.header;
+
+    let providers = lock_providers(&api.providers, timeout).await?;
+    let primary = &providers.primary;
+    let query = primary
+        .rpc_client()
+        .abci_query(Some(request.path.clone()), key.clone(), Some(height), true)
+        .await
+        .map_err(|e| {
+            warn!("failed to query {} from primary ({}): {}", request.path, primary, error_message(&e));
+            (
+                http::StatusCode::BAD_GATEWAY,
+                format!("failed to query {} at height {}: {}", request.path, height, error_message(&e)),
+            )
+        })?;
+    drop(providers);
+
+    if query.code.is_err() {
+        return Err((
+            http::StatusCode::BAD_GATEWAY,
+            format!("query of {} failed with code {}: {}", request.path, query.code.value(), query.log),
+        ));
+    }
+    if query.height != height {
+        return Err((
+            http::StatusCode::BAD_GATEWAY,
+            format!("the primary answered at height {} instead of {}", query.height, height),
+        ));
+    }
+
+    let verification = match &query.proof {
+        Some(proof) => verify_membership(proof, &store, &key, &query.value, header.app_hash.as_bytes()),
+        None => Err(eyre!("the primary returned no proof")),
+    };
+    if let Err(e) = &verification {
+        warn!("failed to verify the proof of {} at height {}: {}", request.path, height, e);
+    }
+
+    let lock = api.state.read().await;
+    Ok(Json(ProveResponse {
+        height,
+        verified_header_height: next_height,
+        app_hash: header.app_hash.to_string(),
+        path: request.path,
+        key: request.key,
+        value: BASE64_STANDARD.encode(&query.value),
+        verified: verification.is_ok(),
+        error: verification.err().map(|e| e.to_string()),
+        warning: lock.config.warning(),
+    }))
+}
+
+/// Externally supplied header to verify, as JSON
+#[derive(Debug, Deserialize)]
+pub struct VerifyRequest {
+    signed_header: SignedHeader,
+    validators: validator::Set,
+    /// Checked against the `next_validators_hash` of the header if present
+    next_validators: Option<validator::Set>,
+}
+
+/// Verifies a signed header and its validator set against the trusted state and the trust
+/// options in effect, without adding it to the trusted state.
+///
+/// The body is a [`VerifyRequest`] as JSON, or a `tendermint.types.LightBlock` with
+/// `Content-Type: application/x-protobuf`. The header is verified from the highest trusted block
+/// below it, or compared with the trusted block at its height.
+pub async fn verify_handler(
+    State(api): State<ApiState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<VerifyResponse>, ApiError> {
+    let protobuf = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-protobuf"));
+    let request = if protobuf {
+        decode_light_block(&body)
     } else {
-        Err(http::StatusCode::SERVICE_UNAVAILABLE)
+        serde_json::from_slice(&body).map_err(|e| e.to_string())
+    }
+    .map_err(|e| (http::StatusCode::BAD_REQUEST, format!("invalid header: {}", e)))?;
+
+    let untrusted = UntrustedBlockState {
+        signed_header: &request.signed_header,
+        validators: &request.validators,
+        next_validators: request.next_validators.as_ref(),
+    };
+    let height = untrusted.height();
+    let hash = request.signed_header.header.hash();
+
+    {
+        let lock = api.state.read().await;
+        check_halted(&lock)?;
+        // Expiration and clock drift are checked against the local clock
+        check_clock(&lock)?;
+    }
+
+    let store = lock_providers(&api.providers, api.api_timeout().await).await?.primary.store.clone();
+    let trusted_blocks: Vec<_> = store
+        .entries()
+        .into_iter()
+        .filter(|(_, status)| matches!(status, Status::Trusted | Status::Verified))
+        .map(|(block, _)| block)
+        .collect();
+
+    let lock = api.state.read().await;
+    let options = lock.client.options();
+    let warning = lock.config.warning();
+    drop(lock);
+
+    let (trusted_height, rejection) = offload(|| verify_untrusted(untrusted, &trusted_blocks, &options, Time::now()));
+
+    Ok(Json(VerifyResponse {
+        accepted: rejection.is_none(),
+        height,
+        hash,
+        trusted_height,
+        reason: rejection,
+        warning,
+    }))
+}
+
+/// Verifies `untrusted` from the highest of `trusted_blocks` below it, or, if one is at its
+/// height, checks it is the same header with its validator set and a valid commit, returning the
+/// height of the trusted block used and the reason for a rejection.
+fn verify_untrusted(
+    untrusted: UntrustedBlockState<'_>,
+    trusted_blocks: &[LightBlock],
+    options: &Options,
+    now: Time,
+) -> (Option<Height>, Option<String>) {
+    let height = untrusted.height();
+    let verifier = BatchVerifier::default();
+    let rejection = |verdict, trusted: &LightBlock| match verdict {
+        Verdict::Success => None,
+        Verdict::NotEnoughTrust(tally) => Some(format!(
+            "not enough trust from the validators of trusted height {}: {}",
+            trusted.height(),
+            tally
+        )),
+        Verdict::Invalid(detail) => Some(detail.to_string()),
+    };
+
+    if let Some(trusted) = trusted_blocks.iter().find(|block| block.height() == height) {
+        // The header hash commits to the validator set hashes but not to the commit, so both the
+        // sets and the signatures are checked as they would be for a new header
+        let trusted_hash = trusted.signed_header.header.hash();
+        let rejection = if trusted_hash != untrusted.signed_header.header.hash() {
+            Some(format!("conflicts with the trusted header {}", trusted_hash))
+        } else {
+            match verifier.verify_validator_sets(&untrusted) {
+                Verdict::Success => rejection(verifier.verify_commit(&untrusted), trusted),
+                verdict => rejection(verdict, trusted),
+            }
+        };
+        return (Some(height), rejection);
+    }
+
+    match trusted_blocks.iter().rev().find(|block| block.height() < height) {
+        Some(trusted) => {
+            let verdict = verifier.verify_update_header(untrusted, trusted.as_trusted_state(), options, now);
+            (Some(trusted.height()), rejection(verdict, trusted))
+        }
+        None => (None, Some("no trusted block below this height".to_string())),
+    }
+}
+
+fn decode_light_block(body: &[u8]) -> Result<VerifyRequest, String> {
+    let raw = RawLightBlock::decode(body).map_err(|e| e.to_string())?;
+    let signed_header = raw.signed_header.ok_or("missing signed header")?;
+    let validators = raw.validator_set.ok_or("missing validator set")?;
+    Ok(VerifyRequest {
+        signed_header: signed_header.try_into().map_err(|e| error_message(&e))?,
+        validators: validators.try_into().map_err(|e| error_message(&e))?,
+        next_validators: None,
+    })
+}
+
+/// Encodes a light block as a `tendermint.types.LightBlock`, the inverse of `decode_light_block`
+fn encode_light_block(light_block: &LightBlock) -> Vec<u8> {
+    RawLightBlock {
+        signed_header: Some(light_block.signed_header.clone().into()),
+        validator_set: Some(light_block.validators.clone().into()),
+    }
+    .encode_to_vec()
+}
+
+/// Replaces a header with its base64-encoded `tendermint.types.Header`
+fn base64_header(header: HeaderResponse) -> HeaderResponse<String> {
+    HeaderResponse {
+        height: header.height,
+        hash: header.hash,
+        header: BASE64_STANDARD.encode(RawHeader::from(header.header).encode_to_vec()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeadersRequest {
+    heights: Vec<u64>,
+}
+
+/// Verifies a batch of heights, returning their headers as JSON or, with `?format=base64`, as
+/// base64-encoded `tendermint.types.Header`.
+pub async fn headers_handler(
+    State(api): State<ApiState>,
+    Query(params): Query<HashMap<String, String>>,
+    Json(request): Json<HeadersRequest>,
+) -> Result<Response, ApiError> {
+    let format = block_format(&params)?;
+    if format == BlockFormat::Proto {
+        return Err((
+            http::StatusCode::BAD_REQUEST,
+            "batches of headers are not available with format=proto, use format=base64".to_string(),
+        ));
+    }
+
+    let max_batch_size = { api.state.read().await.config.max_batch_size };
+    if request.heights.len() > max_batch_size {
+        return Err((
+            http::StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "requested {} heights, at most {} are allowed per request",
+                request.heights.len(),
+                max_batch_size
+            ),
+        ));
+    }
+
+    // Verifying in ascending order lets each height build on the previous one
+    let mut heights = request.heights;
+    heights.sort_unstable();
+    heights.dedup();
+
+    let mut errors = Vec::new();
+    let mut valid_heights = Vec::with_capacity(heights.len());
+    for height in heights {
+        match parse_height(height) {
+            Ok(height) => valid_heights.push(height),
+            Err((_, error)) => errors.push(HeaderError { height, error }),
+        }
+    }
+
+    let mut headers = Vec::with_capacity(valid_heights.len());
+    let blocks = verified_blocks(&api, &valid_heights, api.api_timeout().await).await?;
+    for (height, block) in valid_heights.into_iter().zip(blocks) {
+        match block {
+            Ok(light_block) => headers.push(HeaderResponse {
+                height: light_block.height(),
+                hash: light_block.signed_header.header.hash(),
+                header: light_block.signed_header.header,
+            }),
+            Err((_, error)) => errors.push(HeaderError {
+                height: height.value(),
+                error,
+            }),
+        }
+    }
+
+    let warning = api.state.read().await.config.warning();
+    let response = match format {
+        BlockFormat::Base64 => Json(HeadersResponse {
+            headers: headers.into_iter().map(base64_header).collect(),
+            errors,
+            warning,
+        })
+        .into_response(),
+        _ => Json(HeadersResponse { headers, errors, warning }).into_response(),
+    };
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tendermint::{block::CommitSig, Signature};
+    use tendermint_light_client::verifier::types::TrustThreshold;
+
+    use super::*;
+    use crate::testing::{light_block, signing_keys};
+
+    fn options() -> Options {
+        Options {
+            trust_threshold: TrustThreshold::ONE_THIRD,
+            trusting_period: Duration::from_secs(100 * 365 * 24 * 3600),
+            clock_drift: Duration::from_secs(10),
+        }
+    }
+
+    fn verify(light_block: &LightBlock, trusted_blocks: &[LightBlock]) -> (Option<Height>, Option<String>) {
+        let untrusted = UntrustedBlockState {
+            signed_header: &light_block.signed_header,
+            validators: &light_block.validators,
+            next_validators: Some(&light_block.next_validators),
+        };
+        verify_untrusted(untrusted, trusted_blocks, &options(), Time::now())
+    }
+
+    #[test]
+    fn verifies_from_a_lower_trusted_block() {
+        let keys = signing_keys(4);
+        let trusted = [light_block(5, &keys)];
+        assert_eq!(verify(&light_block(10, &keys), &trusted), (Some(Height::from(5u32)), None));
+        assert_eq!(verify(&light_block(3, &keys), &trusted).0, None);
+    }
+
+    #[test]
+    fn checks_the_validators_of_a_trusted_height() {
+        let keys = signing_keys(4);
+        let trusted = [light_block(10, &keys)];
+        let height = Some(Height::from(10u32));
+        assert_eq!(verify(&light_block(10, &keys), &trusted), (height, None));
+
+        let mut other_validators = light_block(10, &keys);
+        other_validators.validators = light_block(10, &signing_keys(5)).validators;
+        let (trusted_height, rejection) = verify(&other_validators, &trusted);
+        assert_eq!(trusted_height, height);
+        assert!(rejection.is_some());
+
+        let mut other_next_validators = light_block(10, &keys);
+        other_next_validators.next_validators = light_block(10, &signing_keys(5)).next_validators;
+        assert!(verify(&other_next_validators, &trusted).1.is_some());
+
+        let conflicting = light_block(10, &signing_keys(5));
+        assert!(verify(&conflicting, &trusted).1.unwrap().starts_with("conflicts with the trusted header"));
+    }
+
+    #[test]
+    fn checks_the_commit_of_a_trusted_height() {
+        let keys = signing_keys(4);
+        let trusted = [light_block(10, &keys)];
+        let mut forged = light_block(10, &keys);
+        for commit_sig in &mut forged.signed_header.commit.signatures {
+            let CommitSig::BlockIdFlagCommit { signature, .. } = commit_sig else {
+                unreachable!("all validators signed");
+            };
+            let mut bytes = signature.as_ref().unwrap().as_bytes().to_vec();
+            bytes[0] ^= 1;
+            *signature = Signature::new(bytes).unwrap();
+        }
+        // The header, and so its hash, is unchanged
+        assert_eq!(forged.signed_header.header.hash(), trusted[0].signed_header.header.hash());
+        assert!(verify(&forged, &trusted).1.is_some());
+    }
+
+    fn status(light_block: &LightBlock) -> StatusResponse {
+        let header = &light_block.signed_header.header;
+        StatusResponse {
+            block_height: light_block.height(),
+            block_hash: header.hash(),
+            block_timestamp: header.time,
+            app_hash: header.app_hash.to_string(),
+            halted: false,
+            stale: false,
+            state: SyncState::Synced,
+            last_sync_success: true,
+            consecutive_sync_failures: 0,
+            secs_since_last_success: Some(1),
+            network_height: Some(light_block.height()),
+            lag_blocks: Some(0),
+            lag_secs: Some(0),
+            witness_check: None,
+            trace: None,
+            warning: None,
+        }
+    }
+
+    #[test]
+    fn status_etag_follows_the_sync_fields() {
+        let keys = signing_keys(4);
+        let block = light_block(10, &keys);
+        let etag = status_etag(&status(&block));
+
+        // The ages change on every request without changing the tag
+        let mut later = status(&block);
+        later.secs_since_last_success = Some(30);
+        later.lag_secs = Some(30);
+        assert_eq!(status_etag(&later), etag);
+
+        let mut failing = status(&block);
+        failing.state = SyncState::Degraded;
+        failing.consecutive_sync_failures = 1;
+        assert_ne!(status_etag(&failing), etag);
+
+        let mut behind = status(&block);
+        behind.network_height = Some(Height::from(11u32));
+        assert_ne!(status_etag(&behind), etag);
+
+        assert_ne!(status_etag(&status(&light_block(11, &keys))), etag);
     }
 }
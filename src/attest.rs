@@ -0,0 +1,79 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use ed25519_consensus::{Signature, SigningKey, VerificationKey};
+
+/// Response header carrying the attestation signature over the response body
+pub const SIGNATURE_HEADER: &str = "x-helios-signature";
+
+/// Ed25519 key with which the daemon signs its responses, so that downstream services can
+/// check they come from this instance.
+pub struct Attestor {
+    signing_key: SigningKey,
+}
+
+pub type SharedAttestor = Arc<Attestor>;
+
+impl Attestor {
+    /// Loads the key from a file holding the base64-encoded private key, either the 32-byte
+    /// seed or the 64-byte seed and public key pair used by CometBFT key files.
+    pub fn load(path: &Path) -> Result<SharedAttestor> {
+        let contents = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read attestation key {}", path.display()))?;
+        let bytes = BASE64_STANDARD
+            .decode(contents.trim())
+            .wrap_err_with(|| format!("attestation key {} is not valid base64", path.display()))?;
+
+        let seed: [u8; 32] = match bytes.len() {
+            32 | 64 => bytes[..32].try_into()?,
+            len => {
+                return Err(eyre!(
+                    "attestation key {} must be 32 or 64 bytes long, got {}",
+                    path.display(),
+                    len
+                ))
+            }
+        };
+
+        Ok(Arc::new(Self {
+            signing_key: SigningKey::from(seed),
+        }))
+    }
+
+    /// Base64-encoded signature of `message`
+    pub fn sign(&self, message: &[u8]) -> String {
+        BASE64_STANDARD.encode(self.signing_key.sign(message).to_bytes())
+    }
+
+    /// Base64-encoded public key against which signatures can be verified
+    pub fn public_key(&self) -> String {
+        BASE64_STANDARD.encode(self.public_key_bytes())
+    }
+
+    pub fn public_key_bytes(&self) -> [u8; 32] {
+        self.signing_key.verification_key().to_bytes()
+    }
+}
+
+/// Verifies a base64-encoded ed25519 `signature` of `message` against a base64-encoded
+/// `public_key`, as returned by [`Attestor::sign`] and [`Attestor::public_key`].
+pub fn verify_signature(public_key: &str, message: &[u8], signature: &str) -> Result<()> {
+    let public_key: [u8; 32] = BASE64_STANDARD
+        .decode(public_key)
+        .wrap_err("public key is not valid base64")?
+        .try_into()
+        .map_err(|_| eyre!("public key must be 32 bytes long"))?;
+    let signature: [u8; 64] = BASE64_STANDARD
+        .decode(signature)
+        .wrap_err("signature is not valid base64")?
+        .try_into()
+        .map_err(|_| eyre!("signature must be 64 bytes long"))?;
+
+    VerificationKey::try_from(public_key)
+        .map_err(|e| eyre!("invalid public key: {}", e))?
+        .verify(&Signature::from(signature), message)
+        .map_err(|e| eyre!("{}", e))
+}
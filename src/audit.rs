@@ -0,0 +1,242 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use color_eyre::eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use tendermint::crypto::{default::Sha256, Sha256 as _};
+use tendermint::{Hash, Time};
+use tendermint_light_client::types::Height;
+use tracing::warn;
+
+use crate::evidence::DivergencePeer;
+
+/// Security-relevant event recorded in the audit log
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// A witness presented a header conflicting with the primary
+    ForkDetected {
+        primary: DivergencePeer,
+        witness: DivergencePeer,
+        conflicting_height: Height,
+    },
+    /// Light client attack evidence was submitted to a provider
+    EvidenceReported {
+        peer: DivergencePeer,
+        conflicting_height: Height,
+        /// Why the submission failed, if it did
+        error: Option<String>,
+    },
+    /// Light client attack evidence was broadcast to an `--evidence-sinks` endpoint
+    EvidenceBroadcast {
+        sink: String,
+        conflicting_height: Height,
+        /// Why the broadcast failed, if it did
+        error: Option<String>,
+    },
+    /// A former witness took over as the primary
+    PrimaryFailover {
+        from: DivergencePeer,
+        to: DivergencePeer,
+    },
+    /// A witness was discovered among the peers of a `--discovery-seeds` node and enrolled
+    WitnessDiscovered {
+        peer: DivergencePeer,
+        seed: String,
+    },
+    /// Trust options were changed through the admin API
+    TrustOptionsChanged {
+        trust_threshold: String,
+        trusting_period: u64,
+        max_clock_drift: u64,
+        /// Height of the trusted block the providers were rebuilt from
+        trusted_height: Height,
+    },
+    /// The trusted state was replaced by a new root of trust through the admin API
+    TrustedStateReset {
+        trusted_height: Height,
+        trusted_hash: Hash,
+        /// Height of the latest trusted block before the reset, if any
+        previous_height: Option<Height>,
+    },
+    /// Syncing was halted
+    Halted {
+        reason: &'static str,
+        /// How long the halt lasts, unless it lasts until the providers agree again
+        duration_secs: Option<u64>,
+    },
+    /// Syncing resumed after a halt
+    Resumed,
+}
+
+/// A line of the audit log.
+///
+/// Every entry carries the hash of the line before it, so that modifying or removing an entry
+/// breaks the chain of every entry after it.
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    seq: u64,
+    time: Time,
+    prev_hash: Hash,
+    #[serde(flatten)]
+    event: &'a AuditEvent,
+}
+
+/// Fields of a previous entry needed to continue the chain
+#[derive(Deserialize)]
+struct ChainHead {
+    seq: u64,
+}
+
+/// Append-only JSON lines file of [`AuditEvent`]s, rotated once it exceeds its maximum size.
+///
+/// Rotated files are renamed to `<path>.1` (the most recent) up to `<path>.<max_files>`,
+/// and the hash chain carries on across them.
+pub struct AuditLog {
+    writer: Option<Mutex<Writer>>,
+}
+
+pub type SharedAuditLog = Arc<AuditLog>;
+
+struct Writer {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+    seq: u64,
+    prev_hash: Hash,
+}
+
+impl AuditLog {
+    /// An audit log which discards all events
+    pub fn disabled() -> SharedAuditLog {
+        Arc::new(Self { writer: None })
+    }
+
+    /// Opens the audit log at `path`, continuing the hash chain of its last entry.
+    pub fn open(path: &Path, max_size: u64, max_files: usize) -> Result<SharedAuditLog> {
+        // The current file may have been rotated right before the daemon stopped
+        let last_line = match last_line(path)? {
+            Some(line) => Some(line),
+            None => last_line(&rotated_path(path, 1))?,
+        };
+
+        let (seq, prev_hash) = match last_line {
+            Some(line) => {
+                let head: ChainHead = serde_json::from_str(&line)
+                    .wrap_err_with(|| format!("failed to parse audit log {}", path.display()))?;
+                (head.seq, line_hash(&line))
+            }
+            None => (0, Hash::Sha256([0; 32])),
+        };
+
+        let file = open_append(path)?;
+        let size = file.metadata()?.len();
+        Ok(Arc::new(Self {
+            writer: Some(Mutex::new(Writer {
+                path: path.to_path_buf(),
+                max_size,
+                max_files,
+                file,
+                size,
+                seq,
+                prev_hash,
+            })),
+        }))
+    }
+
+    /// Appends `event` to the log.
+    ///
+    /// Failures are logged rather than returned, so that they never interrupt syncing.
+    pub fn record(&self, event: AuditEvent) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+        let Ok(mut writer) = writer.lock() else {
+            return;
+        };
+        if let Err(e) = writer.append(&event) {
+            warn!("failed to write to audit log {}: {:#}", writer.path.display(), e);
+        }
+    }
+}
+
+impl Writer {
+    fn append(&mut self, event: &AuditEvent) -> Result<()> {
+        if self.size >= self.max_size {
+            self.rotate()?;
+        }
+
+        let entry = AuditEntry {
+            seq: self.seq + 1,
+            time: Time::now(),
+            prev_hash: self.prev_hash,
+            event,
+        };
+        let line = serde_json::to_string(&entry)?;
+
+        self.file.write_all(format!("{}\n", line).as_bytes())?;
+        self.file.sync_data()?;
+
+        self.seq += 1;
+        self.prev_hash = line_hash(&line);
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        for i in (1..self.max_files).rev() {
+            let from = rotated_path(&self.path, i);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, i + 1))
+                    .wrap_err_with(|| format!("failed to rotate audit log {}", from.display()))?;
+            }
+        }
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)
+        } else {
+            fs::rename(&self.path, rotated_path(&self.path, 1))
+        }
+        .wrap_err_with(|| format!("failed to rotate audit log {}", self.path.display()))?;
+
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .wrap_err_with(|| format!("failed to open audit log {}", path.display()))
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+/// Last non-empty line of the file at `path`, if it exists.
+fn last_line(path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(contents
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .map(str::to_string)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).wrap_err_with(|| format!("failed to read audit log {}", path.display())),
+    }
+}
+
+fn line_hash(line: &str) -> Hash {
+    Hash::Sha256(Sha256::digest(line))
+}
@@ -0,0 +1,117 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use tendermint::{block::signed_header::SignedHeader, validator, Time};
+use tendermint_light_client::types::{Hash, Height, LightBlock};
+
+use crate::attest::{verify_signature, Attestor};
+
+/// Trusted header exported by an instance, from which other instances can start syncing rather
+/// than from a height and hash copied by hand
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustBundle {
+    pub chain_id: String,
+    pub height: Height,
+    pub hash: Hash,
+    pub signed_header: SignedHeader,
+    pub validators: validator::Set,
+    /// Signature of the exporting instance, if it has an attestation key
+    pub attestation: Option<BundleAttestation>,
+    /// Set if the exporting instance runs without fork detection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleAttestation {
+    pub algorithm: String,
+    /// Base64-encoded public key of the exporting instance
+    pub public_key: String,
+    /// Base64-encoded signature of the chain id, height and hash of the bundle, which the header
+    /// and validator set are checked against
+    pub signature: String,
+}
+
+impl TrustBundle {
+    /// Bundles a trusted light block, signed with `attestor` if set.
+    pub fn new(
+        chain_id: &str,
+        light_block: &LightBlock,
+        attestor: Option<&Attestor>,
+        warning: Option<&str>,
+    ) -> Self {
+        let height = light_block.height();
+        let hash = light_block.signed_header.header.hash();
+        Self {
+            chain_id: chain_id.to_string(),
+            height,
+            hash,
+            signed_header: light_block.signed_header.clone(),
+            validators: light_block.validators.clone(),
+            attestation: attestor.map(|attestor| BundleAttestation {
+                algorithm: "ed25519".to_string(),
+                public_key: attestor.public_key(),
+                signature: attestor.sign(&signed_message(chain_id, height, &hash)),
+            }),
+            warning: warning.map(str::to_string),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)
+            .wrap_err_with(|| format!("failed to read trust bundle {}", path.display()))?;
+        serde_json::from_slice(&bytes)
+            .wrap_err_with(|| format!("failed to parse trust bundle {}", path.display()))
+    }
+
+    /// Checks that the bundle is for `chain_id` and within the trusting period, that its header
+    /// and validator set match its height and hash and, if `public_key` is set, that it was signed
+    /// with that attestation key.
+    pub fn check(&self, chain_id: &str, trusting_period: Duration, public_key: Option<&str>) -> Result<()> {
+        if self.chain_id != chain_id {
+            return Err(eyre!("the bundle is for chain {}, not {}", self.chain_id, chain_id));
+        }
+
+        let header = &self.signed_header.header;
+        if header.chain_id.as_str() != self.chain_id || header.height != self.height {
+            return Err(eyre!("the header of the bundle is not at its chain id and height"));
+        }
+        if header.hash() != self.hash || self.signed_header.commit.block_id.hash != self.hash {
+            return Err(eyre!("the header of the bundle does not have hash {}", self.hash));
+        }
+        if self.validators.hash() != header.validators_hash {
+            return Err(eyre!("the validator set of the bundle does not match its header"));
+        }
+        if !(header.time + trusting_period).is_ok_and(|expiry| expiry > Time::now()) {
+            return Err(eyre!(
+                "the header of the bundle, from {}, is outside the trusting period",
+                header.time
+            ));
+        }
+
+        let Some(public_key) = public_key else {
+            return Ok(());
+        };
+        let attestation = self
+            .attestation
+            .as_ref()
+            .ok_or_else(|| eyre!("the bundle is not signed"))?;
+        if attestation.algorithm != "ed25519" || attestation.public_key != public_key {
+            return Err(eyre!("the bundle is signed with another key"));
+        }
+        verify_signature(
+            public_key,
+            &signed_message(&self.chain_id, self.height, &self.hash),
+            &attestation.signature,
+        )
+        .wrap_err("invalid bundle signature")
+    }
+}
+
+/// Message signed by the attestation of a bundle
+fn signed_message(chain_id: &str, height: Height, hash: &Hash) -> Vec<u8> {
+    format!("helios-light-client trust bundle\n{}\n{}\n{}", chain_id, height, hash).into_bytes()
+}
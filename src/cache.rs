@@ -0,0 +1,168 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use tendermint::validator;
+use tendermint_light_client::types::{Hash, Height, LightBlock};
+
+/// In-process cache of verified light blocks (headers along with their validator sets),
+/// evicting the least recently used height once full.
+pub struct LightBlockCache {
+    inner: Mutex<Lru<Height, LightBlock>>,
+}
+
+/// Entries evicting the least recently used one once `capacity` is reached
+struct Lru<K, V> {
+    capacity: usize,
+    /// Entries by key, along with the tick at which they were last used
+    entries: HashMap<K, (u64, V)>,
+    /// Keys by the tick at which they were last used, least recent first
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K: std::hash::Hash + Eq + Clone, V: Clone> Lru<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn touch(&mut self, key: K) -> u64 {
+        self.tick += 1;
+        self.recency.insert(self.tick, key);
+        self.tick
+    }
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        let (last_used, _) = *self.entries.get(key)?;
+        self.recency.remove(&last_used);
+
+        let tick = self.touch(key.clone());
+        let (last_used, value) = self.entries.get_mut(key)?;
+        *last_used = tick;
+        Some(value.clone())
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some((last_used, _)) = self.entries.remove(&key) {
+            self.recency.remove(&last_used);
+        }
+
+        while self.entries.len() >= self.capacity {
+            let Some((_, evicted)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&evicted);
+        }
+
+        let tick = self.touch(key.clone());
+        self.entries.insert(key, (tick, value));
+    }
+}
+
+pub type SharedCache = Arc<LightBlockCache>;
+
+impl LightBlockCache {
+    /// Creates a cache holding at most `capacity` light blocks, a capacity of 0 disables caching.
+    pub fn new(capacity: usize) -> SharedCache {
+        Arc::new(Self {
+            inner: Mutex::new(Lru::new(capacity)),
+        })
+    }
+
+    pub fn get(&self, height: Height) -> Option<LightBlock> {
+        self.inner.lock().ok()?.get(&height)
+    }
+
+    /// Removes all light blocks, e.g. once they no longer derive from the trusted state.
+    pub fn clear(&self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.clear();
+        }
+    }
+
+    /// Inserts a light block, which must have been verified by the caller.
+    pub fn insert(&self, block: LightBlock) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.insert(block.height(), block);
+        }
+    }
+}
+
+/// Validators fetched from the providers, by height and validator set hash, shared by all of them
+/// so that a set is fetched once rather than for every light block it belongs to.
+///
+/// A set is only served for the hash it was inserted under, which the header it is fetched for
+/// commits to, so sets fetched from one provider are as good as those fetched from another and
+/// need not be verified first.
+pub struct ValidatorSetCache {
+    inner: Mutex<Lru<(Height, Hash), Vec<validator::Info>>>,
+}
+
+pub type SharedValidatorSetCache = Arc<ValidatorSetCache>;
+
+impl ValidatorSetCache {
+    /// Creates a cache holding at most `capacity` validator sets, a capacity of 0 disables caching.
+    pub fn new(capacity: usize) -> SharedValidatorSetCache {
+        Arc::new(Self {
+            inner: Mutex::new(Lru::new(capacity)),
+        })
+    }
+
+    pub fn get(&self, height: Height, hash: Hash) -> Option<Vec<validator::Info>> {
+        self.inner.lock().ok()?.get(&(height, hash))
+    }
+
+    /// Inserts the validators at `height`, which the caller must have checked to hash to `hash`.
+    pub fn insert(&self, height: Height, hash: Hash, validators: Vec<validator::Info>) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.insert((height, hash), validators);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut lru = Lru::new(3);
+        for key in 1..=3 {
+            lru.insert(key, key * 10);
+        }
+
+        // 1 is used, so 2 is now the least recently used entry
+        assert_eq!(lru.get(&1), Some(10));
+        lru.insert(4, 40);
+        assert_eq!(lru.get(&2), None);
+        assert_eq!(lru.entries.len(), 3);
+
+        // Reinserting an entry counts as a use
+        lru.insert(3, 31);
+        lru.insert(5, 50);
+        assert_eq!(lru.get(&1), None);
+        assert_eq!(lru.get(&3), Some(31));
+        assert_eq!(lru.get(&4), Some(40));
+        assert_eq!(lru.get(&5), Some(50));
+        assert_eq!(lru.recency.len(), 3);
+    }
+
+    #[test]
+    fn caches_nothing_without_capacity() {
+        let mut lru = Lru::new(0);
+        lru.insert(1, 10);
+        assert_eq!(lru.get(&1), None);
+    }
+}
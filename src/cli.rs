@@ -1,12 +1,20 @@
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use color_eyre::eyre::{eyre, Result};
-use tendermint_light_client::types::{Hash, Height, TrustThreshold};
-use tendermint_rpc::HttpClientUrl;
+use tendermint_light_client::{
+    light_client::Options,
+    types::{Hash, Height, TrustThreshold},
+};
+use reqwest::header::{HeaderName, HeaderValue};
+use tendermint_rpc::{client::CompatMode, HttpClientUrl, WebSocketClientUrl};
 use tracing::metadata::LevelFilter;
 
+use crate::access::AccessLogFormat;
+
 pub fn parse_trust_threshold(s: &str) -> Result<TrustThreshold> {
     if let Some((l, r)) = s.split_once('/') {
         TrustThreshold::new(l.parse()?, r.parse()?).map_err(Into::into)
@@ -25,16 +33,123 @@ impl<E, T: FromStr<Err = E>> FromStr for List<T> {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         s.split(',')
+            .filter(|s| !s.trim().is_empty())
             .map(|s| s.parse())
             .collect::<Result<Vec<_>, _>>()
             .map(Self)
     }
 }
 
+/// RPC protocol version to speak with a provider
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompatModeSetting {
+    /// Detect the version from the node's `/status` response
+    Auto,
+    /// Always use the given version
+    Fixed(CompatMode),
+}
+
+impl FromStr for CompatModeSetting {
+    type Err = tendermint_rpc::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            Ok(Self::Auto)
+        } else {
+            s.parse().map(Self::Fixed)
+        }
+    }
+}
+
+impl std::fmt::Display for CompatModeSetting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => f.write_str("auto"),
+            Self::Fixed(mode) => mode.fmt(f),
+        }
+    }
+}
+
+/// What the API serves while syncing fails
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DegradationPolicy {
+    /// Wait for a sync whenever the latest block is older than the freshness threshold, failing with
+    /// `504 Gateway Timeout` if it does not complete within the API timeout
+    #[default]
+    Fail,
+    /// Serve the last verified block, flagged as stale, instead of waiting for syncs that keep
+    /// failing or timing out, until it exits the trusting period
+    ServeLastVerified,
+}
+
+/// A value that applies to a single RPC address, given as `<URL>=<VALUE>`.
+///
+/// Both the URL and the value may contain `=`, the last split from which both parse is used.
+#[derive(Clone, Debug)]
+pub struct UrlOverride<T> {
+    pub url: HttpClientUrl,
+    pub value: T,
+}
+
+impl<T> FromStr for UrlOverride<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut error = eyre!("invalid override: {s}, format must be <URL>=<VALUE>");
+        for (i, _) in s.rmatch_indices('=') {
+            let (url, value) = (&s[..i], &s[i + 1..]);
+            let Ok(url) = url.parse() else {
+                continue;
+            };
+            match value.parse() {
+                Ok(value) => return Ok(Self { url, value }),
+                Err(e) => error = eyre!("invalid override value for {}: {e}", &s[..i]),
+            }
+        }
+        Err(error)
+    }
+}
+
+/// Static header sent with the RPC requests to the providers, given as `<NAME>:<VALUE>`
+#[derive(Clone, Debug)]
+pub struct RpcHeader {
+    pub name: HeaderName,
+    pub value: HeaderValue,
+}
+
+impl FromStr for RpcHeader {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once(':')
+            .ok_or_else(|| eyre!("invalid header: {s}, format must be <NAME>:<VALUE>"))?;
+        let name: HeaderName = name.trim().parse()?;
+        let mut value = HeaderValue::from_str(value.trim())
+            .map_err(|e| eyre!("invalid value for header {name}: {e}"))?;
+        // Headers are typically API keys, keep them out of debug output
+        value.set_sensitive(true);
+        Ok(Self { name, value })
+    }
+}
+
+/// Returns the value overridden for the given URL, if any. Later overrides win.
+pub fn find_override<'a, T>(overrides: &'a [UrlOverride<T>], url: &HttpClientUrl) -> Option<&'a T> {
+    overrides
+        .iter()
+        .rev()
+        .find(|o| &o.url == url)
+        .map(|o| &o.value)
+}
+
 #[derive(clap::Args, Debug, Clone)]
 pub struct Verbosity {
     /// Increase verbosity, can be repeated up to 2 times
-    #[arg(long, short, action = clap::ArgAction::Count)]
+    #[arg(long, short, action = clap::ArgAction::Count, global = true)]
     verbose: u8,
 }
 
@@ -48,66 +163,472 @@ impl Verbosity {
     }
 }
 
-#[derive(Debug, Parser, Clone)]
+#[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
-pub struct Args {
-    /// The address to bind the RPC server to
-    #[arg(long, default_value = "127.0.0.1:8080")]
-    pub listen_addr: SocketAddr,
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
 
+    /// Increase verbosity
+    #[command(flatten)]
+    pub verbose: Verbosity,
+}
+
+// Parsed once at startup, so the size of the variants does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the light client daemon and serve its API
+    Run(RunArgs),
+    /// Verify a single height against the primary and witnesses, then print the verified light block
+    Verify(VerifyArgs),
+    /// Print the sync state persisted in a state file
+    Inspect(InspectArgs),
+    /// Query a running daemon and print a summary of its state
+    Status(StatusArgs),
+}
+
+/// Providers and verification parameters, shared by all commands talking to the chain
+#[derive(clap::Args, Debug, Clone)]
+pub struct ClientArgs {
     /// Identifier of the chain
-    #[arg(long)]
+    #[arg(long, env = "HELIOS_CHAIN_ID")]
     pub chain_id: String,
 
     /// Primary RPC address
-    #[arg(long)]
+    #[arg(long, env = "HELIOS_PRIMARY")]
     pub primary: HttpClientUrl,
 
+    /// Comma-separated list of further RPC addresses serving the primary, e.g. the backends behind
+    /// its load balancer. Requests move on to the next address when one fails.
+    #[arg(long, env = "HELIOS_PRIMARY_ENDPOINTS", default_value = "")]
+    pub primary_endpoints: List<HttpClientUrl>,
+
+    /// Resolve the host of each primary address to all of its IP addresses at startup, and rotate
+    /// between them when requests fail
+    #[arg(long, env = "HELIOS_PRIMARY_RESOLVE_ALL")]
+    pub primary_resolve_all: bool,
+
     /// Comma-separated list of witnesses RPC addresses
-    #[arg(long)]
+    #[cfg_attr(not(feature = "no-detector"), arg(long, env = "HELIOS_WITNESSES"))]
+    #[cfg_attr(
+        feature = "no-detector",
+        arg(long, env = "HELIOS_WITNESSES", default_value = "", required_unless_present = "no_detector")
+    )]
     pub witnesses: List<HttpClientUrl>,
 
-    /// Height of trusted header
-    #[arg(long)]
-    pub trusted_height: Height,
+    /// UNSAFE: run against the primary only, without witnesses or fork detection
+    #[cfg(feature = "no-detector")]
+    #[arg(long, env = "HELIOS_NO_DETECTOR", conflicts_with = "witnesses")]
+    pub no_detector: bool,
+
+    /// Comma-separated list of further RPC addresses to which all evidence generated by the fork
+    /// detector is broadcast, besides the providers involved
+    #[arg(long, env = "HELIOS_EVIDENCE_SINKS", default_value = "")]
+    pub evidence_sinks: List<HttpClientUrl>,
+
+    /// Height of trusted header, required unless `run` resumes from the trusted block of its
+    /// `--state-file`
+    #[arg(long, env = "HELIOS_TRUSTED_HEIGHT", requires = "trusted_hash")]
+    pub trusted_height: Option<Height>,
 
     /// Hash of trusted header
-    #[arg(long)]
-    pub trusted_hash: Hash,
+    #[arg(long, env = "HELIOS_TRUSTED_HASH", requires = "trusted_height")]
+    pub trusted_hash: Option<Hash>,
 
     /// Trust threshold
-    #[arg(long, value_parser = parse_trust_threshold, default_value_t = TrustThreshold::TWO_THIRDS)]
+    #[arg(long, env = "HELIOS_TRUST_THRESHOLD", value_parser = parse_trust_threshold, default_value_t = TrustThreshold::TWO_THIRDS)]
     pub trust_threshold: TrustThreshold,
 
     /// Trusting period, in seconds (default: two weeks)
-    #[arg(long, default_value = "1209600")]
+    #[arg(long, env = "HELIOS_TRUSTING_PERIOD", default_value = "1209600")]
     pub trusting_period: u64,
 
     /// Maximum clock drift, in seconds
-    #[arg(long, default_value = "5")]
+    #[arg(long, env = "HELIOS_MAX_CLOCK_DRIFT", default_value = "5")]
     pub max_clock_drift: u64,
 
     /// Maximum block lag, in seconds
-    #[arg(long, default_value = "5")]
+    #[arg(long, env = "HELIOS_MAX_BLOCK_LAG", default_value = "5")]
     pub max_block_lag: u64,
 
+    /// Per-witness maximum clock drift during fork detection, as `<URL>=<SECONDS>`, can be repeated
+    #[arg(long, env = "HELIOS_MAX_CLOCK_DRIFT_OVERRIDE", value_delimiter = ',')]
+    pub max_clock_drift_override: Vec<UrlOverride<u64>>,
+
+    /// Per-witness maximum block lag during fork detection, as `<URL>=<SECONDS>`, can be repeated
+    #[arg(long, env = "HELIOS_MAX_BLOCK_LAG_OVERRIDE", value_delimiter = ',')]
+    pub max_block_lag_override: Vec<UrlOverride<u64>>,
+
+    /// Number of witnesses against which fork detection must complete, rather than fail, for a
+    /// block to be trusted
+    #[cfg_attr(
+        not(feature = "no-detector"),
+        arg(long, env = "HELIOS_MIN_WITNESSES", default_value = "0")
+    )]
+    #[cfg_attr(
+        feature = "no-detector",
+        arg(long, env = "HELIOS_MIN_WITNESSES", default_value = "0", conflicts_with = "no_detector")
+    )]
+    pub min_witnesses: usize,
+
+    /// Timeout for each RPC request sent to the primary and witnesses (in seconds)
+    #[arg(long, env = "HELIOS_RPC_TIMEOUT", default_value = "10")]
+    pub rpc_timeout: u64,
+
+    /// Per-provider RPC timeout, as `<URL>=<SECONDS>`, can be repeated
+    #[arg(long, env = "HELIOS_RPC_TIMEOUT_OVERRIDE", value_delimiter = ',')]
+    pub rpc_timeout_override: Vec<UrlOverride<u64>>,
+
+    /// Number of times a light block fetch failing with an RPC error or timeout is retried within a
+    /// sync, before the whole sync fails
+    #[arg(long, env = "HELIOS_RPC_RETRIES", default_value = "0")]
+    pub rpc_retries: u32,
+
+    /// Per-provider number of retries, as `<URL>=<RETRIES>`, can be repeated
+    #[arg(long, env = "HELIOS_RPC_RETRIES_OVERRIDE", value_delimiter = ',')]
+    pub rpc_retries_override: Vec<UrlOverride<u32>>,
+
+    /// Delay before the first retry of a light block fetch, doubled for each further retry (in milliseconds)
+    #[arg(long, env = "HELIOS_RPC_RETRY_BACKOFF", default_value = "500")]
+    pub rpc_retry_backoff: u64,
+
+    /// Maximum number of idle connections kept open to each provider endpoint for reuse by later
+    /// RPC requests, `0` to open a new connection for every request
+    #[arg(long, env = "HELIOS_RPC_POOL_MAX_IDLE", default_value = "0")]
+    pub rpc_pool_max_idle: usize,
+
+    /// Time after which idle pooled connections are closed (in seconds)
+    #[arg(long, env = "HELIOS_RPC_POOL_IDLE_TIMEOUT", default_value = "90")]
+    pub rpc_pool_idle_timeout: u64,
+
+    /// Interval of the TCP keep-alive probes sent on open connections to the providers (in
+    /// seconds), disabled if unset
+    #[arg(long, env = "HELIOS_RPC_TCP_KEEPALIVE")]
+    pub rpc_tcp_keepalive: Option<u64>,
+
+    /// Number of validator sets fetched from the providers kept for later light blocks, which
+    /// all providers share, 0 disables it
+    #[arg(long, env = "HELIOS_VALIDATOR_SET_CACHE_CAPACITY", default_value = "100")]
+    pub validator_set_cache_capacity: usize,
+
+    /// User-Agent of the RPC requests sent to the providers
+    #[arg(long, env = "HELIOS_USER_AGENT", default_value = concat!("helios-light-client/", env!("CARGO_PKG_VERSION")))]
+    pub user_agent: String,
+
+    /// Static header sent with every RPC request to the providers, as `<NAME>:<VALUE>` (e.g.
+    /// `x-api-key:<KEY>`), can be repeated. Since header values may contain commas, the
+    /// environment variable takes one header per line
+    #[arg(long, env = "HELIOS_RPC_HEADER", value_delimiter = '\n')]
+    pub rpc_header: Vec<RpcHeader>,
+
+    /// Per-provider header, as `<URL>=<NAME>:<VALUE>`, can be repeated, one per line in the
+    /// environment variable. Replaces the `--rpc-header` of the same name
+    #[arg(long, env = "HELIOS_RPC_HEADER_OVERRIDE", value_delimiter = '\n')]
+    pub rpc_header_override: Vec<UrlOverride<RpcHeader>>,
+
+    /// RPC compatibility mode: `auto`, `0.34`, `0.37` or `0.38`
+    #[arg(long, env = "HELIOS_COMPAT_MODE", default_value_t = CompatModeSetting::Auto)]
+    pub compat_mode: CompatModeSetting,
+
+    /// Per-provider RPC compatibility mode, as `<URL>=<MODE>`, can be repeated
+    #[arg(long, env = "HELIOS_COMPAT_MODE_OVERRIDE", value_delimiter = ',')]
+    pub compat_mode_override: Vec<UrlOverride<CompatModeSetting>>,
+
+    /// Fixture of light blocks, e.g. a `/v1/admin/snapshot` dump, served by every provider without
+    /// a `--mock-fixture-override` instead of fetching them over RPC
+    #[cfg(feature = "mock-provider")]
+    #[arg(long, env = "HELIOS_MOCK_FIXTURE")]
+    pub mock_fixture: Option<PathBuf>,
+
+    /// Per-provider fixture, as `<URL>=<PATH>`, can be repeated
+    #[cfg(feature = "mock-provider")]
+    #[arg(long, env = "HELIOS_MOCK_FIXTURE_OVERRIDE", value_delimiter = ',')]
+    pub mock_fixture_override: Vec<UrlOverride<PathBuf>>,
+
+    /// Proxy for outbound RPC requests, e.g. `http://proxy:3128` or `socks5://proxy:1080`
+    #[arg(long, env = "HELIOS_PROXY")]
+    pub proxy: Option<reqwest::Url>,
+
+    /// Comma-separated list of hosts, domains or IP ranges that bypass `--proxy`
+    #[arg(long, env = "HELIOS_NO_PROXY", requires = "proxy")]
+    pub no_proxy: Option<String>,
+}
+
+impl ClientArgs {
+    /// Height and hash of the trusted header the providers are initialized from.
+    pub fn trust_root(&self) -> Result<(Height, Hash)> {
+        match (self.trusted_height, self.trusted_hash) {
+            (Some(height), Some(hash)) => Ok((height, hash)),
+            _ => Err(eyre!("--trusted-height and --trusted-hash are required")),
+        }
+    }
+
+    /// Whether the client runs in the unsafe single-provider mode, without fork detection.
+    pub fn unsafe_no_detector(&self) -> bool {
+        #[cfg(feature = "no-detector")]
+        return self.no_detector;
+
+        #[cfg(not(feature = "no-detector"))]
+        return false;
+    }
+
+    /// Fails if no witnesses are configured, e.g. with `--witnesses ""`, unless fork detection is
+    /// disabled or `discovery` will find some.
+    pub fn check_witnesses(&self, discovery: bool) -> Result<()> {
+        if self.witnesses.0.is_empty() && !self.unsafe_no_detector() && !discovery {
+            return Err(eyre!("no witnesses provided, at least one is required for fork detection"));
+        }
+        Ok(())
+    }
+
+    pub fn options(&self) -> Options {
+        Options {
+            trust_threshold: self.trust_threshold,
+            trusting_period: Duration::from_secs(self.trusting_period),
+            clock_drift: Duration::from_secs(self.max_clock_drift),
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(group(clap::ArgGroup::new("trust_root").args(["trusted_height", "bootstrap_bundle"]).multiple(true)))]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub client: ClientArgs,
+
+    /// The address to bind the RPC server to, can be repeated
+    #[arg(long, env = "HELIOS_LISTEN_ADDR", value_delimiter = ',', default_value = "127.0.0.1:8080")]
+    pub listen_addr: Vec<SocketAddr>,
+
+    /// Address on which admin routes are served, can be repeated.
+    /// When set, admin routes are no longer served on `--listen-addr`.
+    #[arg(long, env = "HELIOS_ADMIN_LISTEN_ADDR", value_delimiter = ',')]
+    pub admin_listen_addr: Vec<SocketAddr>,
+
+    /// Log every API request (client IP, method, path, status, size and latency) in this format
+    #[arg(long, env = "HELIOS_ACCESS_LOG", value_enum)]
+    pub access_log: Option<AccessLogFormat>,
+
+    /// Serve the `status`, `commit` and `validators` CometBFT RPC endpoints under `/rpc`, from
+    /// verified blocks only, so that other light clients can use the daemon as a provider
+    #[arg(long, env = "HELIOS_SERVE_RPC")]
+    pub serve_rpc: bool,
+
     /// The maximum age of the trusted state before a new sync is triggered by an API request (in seconds)
-    #[arg(long, default_value = "10")]
+    #[arg(long, env = "HELIOS_FRESHNESS_THRESHOLD", default_value = "10")]
     pub freshness_threshold: u64,
 
-    /// The interval for the periodic 'keep-warm' syncs when the server is idle (in seconds) (default: 5 minutes)
-    #[arg(long, default_value = "300")]
+    /// Serve a trusted state older than the freshness threshold right away, flagged as stale, while
+    /// it is refreshed in the background, instead of waiting for the refresh
+    #[arg(long, env = "HELIOS_STALE_WHILE_REVALIDATE")]
+    pub stale_while_revalidate: bool,
+
+    /// What `/v1/status` and `/v1/light_block` serve while syncing fails
+    #[arg(long, env = "HELIOS_DEGRADATION_POLICY", value_enum, default_value = "fail")]
+    pub degradation_policy: DegradationPolicy,
+
+    /// The interval for the periodic 'keep-warm' syncs when the server is idle (in seconds) (default: 5 minutes),
+    /// 0 to disable them
+    #[arg(long, env = "HELIOS_KEEP_WARM_INTERVAL", default_value = "300")]
     pub keep_warm_interval: u64,
 
+    /// Adapt the keep-warm interval to the API load: back it off up to this interval (in seconds)
+    /// while no API request arrives, and tighten it down to the freshness threshold under load
+    #[arg(long, env = "HELIOS_KEEP_WARM_MAX_INTERVAL")]
+    pub keep_warm_max_interval: Option<u64>,
+
     /// The duration to halt syncing for after a fork is detected (in seconds) (default: 60 minutes)
-    #[arg(long, default_value = "3600")]
+    #[arg(long, env = "HELIOS_HALT_DURATION_ON_FORK", default_value = "3600")]
     pub halt_duration_on_fork: u64,
 
+    /// Instead of halting for a fixed duration after a fork, re-run fork detection at this interval
+    /// (in seconds) and resume syncing once the primary and witnesses agree again
+    #[arg(long, env = "HELIOS_FORK_RECOVERY_INTERVAL", conflicts_with = "halt_duration_on_fork")]
+    pub fork_recovery_interval: Option<u64>,
+
+    /// Treat the primary and witnesses alike: verify the tip on each and only publish a block once
+    /// this many of them agree on its hash, instead of cross-checking the primary with fork detection
+    #[arg(long, env = "HELIOS_QUORUM")]
+    pub quorum: Option<usize>,
+
+    /// Comma-separated list of RPC addresses of seed nodes, whose peers are enrolled as further
+    /// witnesses at startup if they serve the trusted header
+    #[cfg_attr(
+        not(feature = "no-detector"),
+        arg(long, env = "HELIOS_DISCOVERY_SEEDS", default_value = "")
+    )]
+    #[cfg_attr(
+        feature = "no-detector",
+        arg(long, env = "HELIOS_DISCOVERY_SEEDS", default_value = "", conflicts_with = "no_detector")
+    )]
+    pub discovery_seeds: List<HttpClientUrl>,
+
+    /// Maximum number of witnesses, configured and discovered ones together
+    #[arg(long, env = "HELIOS_DISCOVERY_MAX_WITNESSES", default_value = "5")]
+    pub discovery_max_witnesses: usize,
+
     /// Timeout for API-triggered waits (in seconds)
-    #[arg(long, default_value = "5")]
+    #[arg(long, env = "HELIOS_API_TIMEOUT", default_value = "5")]
     pub api_timeout: u64,
 
-    /// Increase verbosity
+    /// Maximum timeout API callers can request with `?timeout=<seconds>` (in seconds)
+    #[arg(long, env = "HELIOS_MAX_API_TIMEOUT", default_value = "60")]
+    pub max_api_timeout: u64,
+
+    /// Maximum number of heights accepted by a single `POST /v1/headers` request
+    #[arg(long, env = "HELIOS_MAX_BATCH_SIZE", default_value = "100")]
+    pub max_batch_size: usize,
+
+    /// Number of verified light blocks kept in the in-process cache, 0 disables it
+    #[arg(long, env = "HELIOS_CACHE_CAPACITY", default_value = "1000")]
+    pub cache_capacity: usize,
+
+    /// Number of highest blocks kept in the light stores, older ones are pruned after every sync
+    /// (the latest trusted block is always kept)
+    #[arg(long, env = "HELIOS_STORE_KEEP_BLOCKS")]
+    pub store_keep_blocks: Option<usize>,
+
+    /// Prune blocks older than the trusting period from the light stores after every sync
+    #[arg(long, env = "HELIOS_STORE_KEEP_WITHIN_TRUSTING_PERIOD")]
+    pub store_keep_within_trusting_period: bool,
+
+    /// File in which sync bookkeeping (last sync time, result, failure count and backoff) and the
+    /// latest trusted block are persisted across restarts. Syncing resumes from that block if it is
+    /// still within the trusting period, in which case `--trusted-height` and `--trusted-hash` can
+    /// be omitted
+    #[arg(long, env = "HELIOS_STATE_FILE")]
+    pub state_file: Option<PathBuf>,
+
+    /// Start from `--trusted-height` and `--trusted-hash`, or the `--bootstrap-bundle`, even if
+    /// the `--state-file` holds a trusted block to resume from
+    #[arg(long, env = "HELIOS_REBOOTSTRAP", requires = "trust_root")]
+    pub rebootstrap: bool,
+
+    /// Trust bundle exported by another instance at `/v1/trust_bundle`, whose header is trusted
+    /// instead of `--trusted-height` and `--trusted-hash`
+    #[arg(long, env = "HELIOS_BOOTSTRAP_BUNDLE", conflicts_with_all = ["trusted_height", "trusted_hash"])]
+    pub bootstrap_bundle: Option<PathBuf>,
+
+    /// Base64-encoded attestation public key of the instance that exported the
+    /// `--bootstrap-bundle`, which must then be signed with it
+    #[arg(long, env = "HELIOS_BOOTSTRAP_BUNDLE_KEY", requires = "bootstrap_bundle")]
+    pub bootstrap_bundle_key: Option<String>,
+
+    /// File holding a base64-encoded ed25519 private key with which status and light block responses,
+    /// and trust bundles, are signed
+    #[arg(long, env = "HELIOS_ATTESTATION_KEY")]
+    pub attestation_key: Option<PathBuf>,
+
+    /// Directory in which divergences found by the fork detector are stored, one JSON file each
+    #[arg(long, env = "HELIOS_EVIDENCE_DIR")]
+    pub evidence_dir: Option<PathBuf>,
+
+    /// File holding the bearer token required by the admin API, which is disabled without it
+    #[arg(long, env = "HELIOS_ADMIN_TOKEN_FILE")]
+    pub admin_token_file: Option<PathBuf>,
+
+    /// File to which security-relevant events (forks, evidence reports, failovers, halts) are appended as JSON lines
+    #[arg(long, env = "HELIOS_AUDIT_LOG")]
+    pub audit_log: Option<PathBuf>,
+
+    /// Size (in bytes) above which the audit log is rotated (default: 10 MiB)
+    #[arg(long, env = "HELIOS_AUDIT_LOG_MAX_SIZE", default_value = "10485760", requires = "audit_log")]
+    pub audit_log_max_size: u64,
+
+    /// Number of rotated audit log files kept
+    #[arg(long, env = "HELIOS_AUDIT_LOG_MAX_FILES", default_value = "10", requires = "audit_log")]
+    pub audit_log_max_files: usize,
+
+    /// Sync whenever the primary announces a new block, through a `NewBlock` WebSocket subscription
+    #[arg(long, env = "HELIOS_SUBSCRIBE")]
+    pub subscribe: bool,
+
+    /// WebSocket endpoint used by `--subscribe` (default: the `/websocket` path of the primary)
+    #[arg(long, env = "HELIOS_WEBSOCKET_URL", requires = "subscribe")]
+    pub websocket_url: Option<WebSocketClientUrl>,
+
+    /// NTP servers (`<HOST>[:<PORT>]`, port 123 by default) against which the local clock is checked
+    /// at startup and every `--clock-check-interval`, can be repeated (default: check it against the
+    /// latest block time of the providers, at every sync)
+    #[arg(long, env = "HELIOS_NTP_SERVER", value_delimiter = ',')]
+    pub ntp_server: Vec<String>,
+
+    /// Interval between two checks of the local clock against the NTP servers (in seconds)
+    #[arg(
+        long,
+        env = "HELIOS_CLOCK_CHECK_INTERVAL",
+        default_value = "600",
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "ntp_server"
+    )]
+    pub clock_check_interval: u64,
+
+    /// Offset of the local clock from the reference time source beyond which `/v1/health` reports
+    /// it as wrong (in seconds)
+    #[arg(long, env = "HELIOS_MAX_CLOCK_OFFSET")]
+    pub max_clock_offset: Option<u64>,
+
+    /// Refuse to serve verified data with `503 Service Unavailable` while the local clock is off by
+    /// more than `--max-clock-offset`, instead of only reporting it
+    #[arg(long, env = "HELIOS_REFUSE_ON_CLOCK_OFFSET", requires = "max_clock_offset")]
+    pub refuse_on_clock_offset: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct VerifyArgs {
     #[command(flatten)]
-    pub verbose: Verbosity,
+    pub client: ClientArgs,
+
+    /// Height to verify (default: the latest height of the primary)
+    #[arg(long, env = "HELIOS_HEIGHT")]
+    pub height: Option<Height>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct InspectArgs {
+    /// State file written by `run --state-file`
+    #[arg(long, env = "HELIOS_STATE_FILE")]
+    pub state_file: PathBuf,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct StatusArgs {
+    /// Base URL of the daemon's API
+    #[arg(long, env = "HELIOS_URL", default_value = "http://127.0.0.1:8080")]
+    pub url: reqwest::Url,
+
+    /// Timeout for each API request (in seconds)
+    #[arg(long, env = "HELIOS_TIMEOUT", default_value = "10")]
+    pub timeout: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_commas_in_rpc_headers() {
+        let cli = Cli::try_parse_from([
+            "helios-light-client",
+            "verify",
+            "--chain-id=test-1",
+            "--primary=http://127.0.0.1:26657",
+            "--witnesses=http://127.0.0.1:26658",
+            "--rpc-header=accept:application/json, text/plain",
+            "--rpc-header=x-api-key:key",
+            "--rpc-header-override=http://127.0.0.1:26658=x-api-key:a,b",
+        ])
+        .unwrap();
+        let Command::Verify(args) = cli.command else {
+            unreachable!("verify command");
+        };
+
+        let headers: Vec<_> = args.client.rpc_header.iter().map(|header| header.value.to_str().unwrap()).collect();
+        assert_eq!(headers, ["application/json, text/plain", "key"]);
+        let [header_override] = &args.client.rpc_header_override[..] else {
+            panic!("expected a single override");
+        };
+        assert_eq!(header_override.value.value, "a,b");
+    }
 }
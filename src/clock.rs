@@ -0,0 +1,311 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use color_eyre::eyre::{eyre, Result};
+use futures::future::join_all;
+use tendermint::Time;
+use tendermint_rpc::endpoint::status;
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+use crate::{
+    metrics::{Metrics, SharedMetrics},
+    provider::{error_message, Providers},
+    state::{ClockOffset, ClockSource, NetworkTip, SharedState},
+};
+
+/// Seconds from the NTP era (1900) to the Unix epoch
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Port of NTP servers given without one
+const NTP_PORT: u16 = 123;
+
+/// How long an NTP server is waited for
+const NTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Offset of `time` from the local clock in seconds, positive if it lies in the future
+pub fn offset_secs(time: Time) -> f64 {
+    let now = Time::now();
+    match time.duration_since(now) {
+        Ok(ahead) => ahead.as_secs_f64(),
+        Err(_) => -now.duration_since(time).unwrap_or_default().as_secs_f64(),
+    }
+}
+
+/// Compares the local clock against the latest block time reported in the `/status` of each
+/// provider, fetched by the caller,
+/// recording the largest offset in the state and metrics and warning if it exceeds
+/// `max_clock_drift`.
+///
+/// The latest block reported by the primary is also recorded as the network tip, from which the
+/// sync lag is derived.
+///
+/// A block can never be newer than the present, so a block time in the future means the local
+/// clock is behind. A clock running ahead makes blocks look old, which cannot be told apart from
+/// a stalled chain and is not flagged.
+///
+/// Without NTP servers, the median offset is also recorded as the offset of the local clock, see
+/// [`record_clock_offset`].
+pub async fn check_clock_skew(
+    providers: &Providers,
+    statuses: &[Result<status::Response, tendermint_rpc::Error>],
+    max_clock_drift: Duration,
+    state: &SharedState,
+    metrics: &Metrics,
+) {
+    let peers = std::iter::once(&providers.primary).chain(&providers.witnesses);
+
+    let mut offsets = Vec::new();
+    for (i, (peer, status)) in peers.zip(statuses).enumerate() {
+        match status {
+            Ok(status) => {
+                if i == 0 {
+                    state.write().await.network_tip = Some(NetworkTip {
+                        height: status.sync_info.latest_block_height,
+                        time: status.sync_info.latest_block_time,
+                    });
+                }
+                offsets.push(offset_secs(status.sync_info.latest_block_time));
+            }
+            Err(e) => debug!(
+                "failed to fetch the status of provider ({}) to check the clock skew: {}",
+                peer,
+                error_message(e)
+            ),
+        }
+    }
+
+    let Some(skew) = offsets.iter().copied().reduce(f64::max) else {
+        return;
+    };
+    if skew > max_clock_drift.as_secs_f64() {
+        warn!(
+            "Local clock is {:.1} seconds behind the latest block time, more than the maximum clock drift of {} seconds. Check the NTP synchronization of this host.",
+            skew,
+            max_clock_drift.as_secs()
+        );
+    }
+
+    metrics.clock_skew.set(skew);
+    state.write().await.clock_skew = Some(skew);
+
+    if state.read().await.config.clock_source == ClockSource::Providers {
+        if let Some(offset) = median(&mut offsets) {
+            record_clock_offset(offset, ClockSource::Providers, state, metrics).await;
+        }
+    }
+}
+
+/// Checks the local clock against the NTP servers right away and then at every `interval`,
+/// recording the median of their offsets, see [`record_clock_offset`].
+///
+/// The previous offset is kept if no server answers.
+pub async fn run_ntp_check(
+    servers: Vec<String>,
+    interval: Duration,
+    state: SharedState,
+    metrics: SharedMetrics,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+
+        let results = join_all(servers.iter().map(|server| ntp_offset(server))).await;
+        let mut offsets = Vec::new();
+        for (server, result) in servers.iter().zip(results) {
+            match result {
+                Ok(offset) => {
+                    debug!("NTP server {} is offset by {:+.3} seconds from the local clock", server, offset);
+                    offsets.push(offset);
+                }
+                Err(e) => warn!("failed to query NTP server {}: {}", server, e),
+            }
+        }
+
+        match median(&mut offsets) {
+            Some(offset) => record_clock_offset(offset, ClockSource::Ntp, &state, &metrics).await,
+            None => warn!("No NTP server answered, the local clock could not be checked"),
+        }
+    }
+}
+
+/// Records the offset of the local clock from `source` in the state and metrics, logging when
+/// it starts or stops exceeding the maximum clock offset.
+async fn record_clock_offset(
+    offset_secs: f64,
+    source: ClockSource,
+    state: &SharedState,
+    metrics: &Metrics,
+) {
+    metrics.clock_offset.set(offset_secs);
+
+    let mut lock = state.write().await;
+    let previous_issue = lock.clock_offset_issue();
+    lock.clock_offset = Some(ClockOffset {
+        offset_secs,
+        source,
+        checked_at: Time::now(),
+    });
+    match (previous_issue, lock.clock_offset_issue()) {
+        (None, Some(issue)) if lock.config.refuse_on_clock_offset => {
+            warn!("The {}, refusing to serve verified data until it is fixed", issue)
+        }
+        (None, Some(issue)) => warn!("The {}. Check the NTP synchronization of this host.", issue),
+        (Some(_), None) => info!("Local clock is back within the maximum clock offset"),
+        _ => {}
+    }
+}
+
+/// Median of `values`, or `None` if there are none
+fn median(values: &mut [f64]) -> Option<f64> {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    match values.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((values[mid - 1] + values[mid]) / 2.0),
+        _ => Some(values[mid]),
+    }
+}
+
+/// Queries `server` (`<HOST>[:<PORT>]`) with SNTP (RFC 4330), returning the offset of its clock
+/// from the local clock in seconds, positive if the local clock is behind.
+///
+/// The round trip is assumed symmetric, as in NTP, so the offset is off by at most half of it.
+async fn ntp_offset(server: &str) -> Result<f64> {
+    let host = with_default_port(server)?;
+    let addr = tokio::net::lookup_host(&host)
+        .await?
+        .next()
+        .ok_or_else(|| eyre!("{} does not resolve to any address", server))?;
+    let local_addr: SocketAddr = if addr.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(local_addr).await?;
+    socket.connect(addr).await?;
+
+    // Version 4, client mode, with the transmit time echoed back as the originate time
+    let mut request = [0u8; 48];
+    request[0] = 0x23;
+    let sent_at = SystemTime::now();
+    let transmit = to_ntp_timestamp(sent_at);
+    request[40..48].copy_from_slice(&transmit.to_be_bytes());
+    socket.send(&request).await?;
+
+    let mut response = [0u8; 48];
+    let received = tokio::time::timeout(NTP_TIMEOUT, socket.recv(&mut response))
+        .await
+        .map_err(|_| eyre!("timed out after {} seconds", NTP_TIMEOUT.as_secs()))??;
+    let received_at = SystemTime::now();
+
+    if received < response.len() {
+        return Err(eyre!("truncated response of {} bytes", received));
+    }
+    if response[0] & 0x07 != 4 {
+        return Err(eyre!("response is not in server mode"));
+    }
+    if response[1] == 0 {
+        let code = String::from_utf8_lossy(&response[12..16]);
+        return Err(eyre!("server sent kiss-o'-death {}", code.trim_end_matches('\0')));
+    }
+    if response[24..32] != transmit.to_be_bytes() {
+        return Err(eyre!("response does not answer the request"));
+    }
+
+    let timestamp = |offset: usize| {
+        let bytes = response[offset..offset + 8].try_into().expect("8 bytes");
+        from_ntp_timestamp(u64::from_be_bytes(bytes))
+    };
+    Ok(offset(unix_secs(sent_at), timestamp(32), timestamp(40), unix_secs(received_at)))
+}
+
+/// `server` (`<HOST>[:<PORT>]`) as `host:port`, with the NTP port if none is given.
+///
+/// IPv6 addresses are taken with or without brackets, and with a port only in brackets, since
+/// the last group of a bare address cannot be told apart from a port.
+fn with_default_port(server: &str) -> Result<String> {
+    if let Ok(addr) = server.parse::<SocketAddr>() {
+        return Ok(addr.to_string());
+    }
+    let bare = server.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(server);
+    if let Ok(ip) = bare.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, NTP_PORT).to_string());
+    }
+    match server.split_once(':') {
+        None => Ok(format!("{}:{}", server, NTP_PORT)),
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(server.to_string()),
+        Some(_) => Err(eyre!("{} is not of the form <HOST>[:<PORT>]", server)),
+    }
+}
+
+/// Offset of the server clock from the local clock, from the local send time `t1`, the server
+/// receive and transmit times `t2` and `t3`, and the local receive time `t4`
+fn offset(t1: f64, t2: f64, t3: f64, t4: f64) -> f64 {
+    ((t2 - t1) + (t3 - t4)) / 2.0
+}
+
+/// 64-bit NTP timestamp of `time`: seconds since 1900 and the fraction of a second, as 32.32 fixed point
+fn to_ntp_timestamp(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() + NTP_UNIX_OFFSET;
+    let fraction = (u64::from(since_epoch.subsec_nanos()) << 32) / 1_000_000_000;
+    (secs << 32) | fraction
+}
+
+/// Seconds since the Unix epoch of a 64-bit NTP timestamp
+fn from_ntp_timestamp(timestamp: u64) -> f64 {
+    let secs = (timestamp >> 32) as f64 - NTP_UNIX_OFFSET as f64;
+    let fraction = (timestamp & 0xffff_ffff) as f64 / (1u64 << 32) as f64;
+    secs + fraction
+}
+
+fn unix_secs(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_ntp_timestamps() {
+        assert_eq!(to_ntp_timestamp(UNIX_EPOCH), NTP_UNIX_OFFSET << 32);
+        assert_eq!(from_ntp_timestamp(NTP_UNIX_OFFSET << 32), 0.0);
+
+        // Half a second is half of the 32-bit fraction
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        let timestamp = to_ntp_timestamp(time);
+        assert_eq!(timestamp >> 32, 1_700_000_000 + NTP_UNIX_OFFSET);
+        assert_eq!(timestamp & 0xffff_ffff, 1 << 31);
+        assert_eq!(from_ntp_timestamp(timestamp), 1_700_000_000.5);
+    }
+
+    #[test]
+    fn computes_offset() {
+        // The server is 10 seconds ahead, with 1 second each way and 0.5 seconds to answer
+        assert_eq!(offset(100.0, 111.0, 111.5, 102.5), 10.0);
+        // The local clock is 3 seconds ahead, with 1 second each way and an immediate answer
+        assert_eq!(offset(100.0, 98.0, 98.0, 102.0), -3.0);
+    }
+
+    #[test]
+    fn takes_median() {
+        assert_eq!(median(&mut []), None);
+        assert_eq!(median(&mut [3.0, -1.0, 2.0]), Some(2.0));
+        assert_eq!(median(&mut [4.0, -1.0, 1.0, 10.0]), Some(2.5));
+    }
+
+    #[test]
+    fn adds_default_port() {
+        assert_eq!(with_default_port("pool.ntp.org").unwrap(), "pool.ntp.org:123");
+        assert_eq!(with_default_port("pool.ntp.org:1123").unwrap(), "pool.ntp.org:1123");
+        assert_eq!(with_default_port("192.0.2.1").unwrap(), "192.0.2.1:123");
+        assert_eq!(with_default_port("192.0.2.1:1123").unwrap(), "192.0.2.1:1123");
+        assert_eq!(with_default_port("2001:db8::1").unwrap(), "[2001:db8::1]:123");
+        assert_eq!(with_default_port("[2001:db8::1]").unwrap(), "[2001:db8::1]:123");
+        assert_eq!(with_default_port("[2001:db8::1]:1123").unwrap(), "[2001:db8::1]:1123");
+        assert!(with_default_port("pool.ntp.org:ntp").is_err());
+    }
+}
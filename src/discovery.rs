@@ -0,0 +1,174 @@
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+use color_eyre::eyre::{eyre, Result};
+use futures::future::join_all;
+use tendermint::node;
+use tendermint_rpc::{endpoint::net_info::PeerInfo, Client, HttpClientUrl};
+use tracing::{debug, warn};
+
+use crate::{
+    cli::{ClientArgs, CompatModeSetting},
+    provider::{build_rpc_client, detect_compat_mode, error_message, RpcConfig},
+};
+
+/// Witness found among the peers of a seed node
+pub struct DiscoveredWitness {
+    pub url: HttpClientUrl,
+    pub peer_id: node::Id,
+    /// Seed node which listed it as a peer
+    pub seed: String,
+}
+
+/// Finds further witnesses among the `net_info` peers of the `seeds`, as many as needed for
+/// `max_witnesses` witnesses overall.
+///
+/// Peers on the chain which expose their RPC are candidates, and are only kept if their header
+/// at the trusted height has the trusted hash. Providers that are already configured are skipped.
+pub async fn discover_witnesses(
+    args: &ClientArgs,
+    seeds: &[HttpClientUrl],
+    max_witnesses: usize,
+) -> Vec<DiscoveredWitness> {
+    let wanted = max_witnesses.saturating_sub(args.witnesses.0.len());
+    if seeds.is_empty() || wanted == 0 {
+        return Vec::new();
+    }
+
+    let known: HashSet<_> = std::iter::once(&args.primary)
+        .chain(&args.primary_endpoints.0)
+        .chain(&args.witnesses.0)
+        .map(url_string)
+        .collect();
+
+    let peer_lists = join_all(seeds.iter().map(|seed| peers_of(args, seed))).await;
+    let mut peer_ids = HashSet::new();
+    let mut candidates = Vec::new();
+    for (seed, peers) in seeds.iter().zip(peer_lists) {
+        let seed = url_string(seed);
+        let peers = match peers {
+            Ok(peers) => peers,
+            Err(e) => {
+                warn!("failed to fetch the peers of seed node {}: {:#}", seed, e);
+                continue;
+            }
+        };
+
+        for peer in peers {
+            let node_info = peer.node_info;
+            if node_info.network.as_str() != args.chain_id {
+                continue;
+            }
+            let Some(url) = rpc_url(&node_info.other.rpc_address, peer.remote_ip) else {
+                debug!(
+                    "peer {} of seed node {} does not expose its RPC ({})",
+                    node_info.id, seed, node_info.other.rpc_address
+                );
+                continue;
+            };
+            if known.contains(&url_string(&url)) || !peer_ids.insert(node_info.id) {
+                continue;
+            }
+            candidates.push(DiscoveredWitness {
+                url,
+                peer_id: node_info.id,
+                seed: seed.clone(),
+            });
+        }
+    }
+    debug!(
+        "Found {} witness candidates among the peers of {} seed nodes",
+        candidates.len(),
+        seeds.len()
+    );
+
+    let validations =
+        join_all(candidates.iter().map(|candidate| validate(args, &candidate.url))).await;
+    let discovered: Vec<_> = candidates
+        .into_iter()
+        .zip(validations)
+        .filter_map(|(candidate, validation)| match validation {
+            Ok(()) => Some(candidate),
+            Err(e) => {
+                debug!(
+                    "rejected witness candidate {} from seed node {}: {:#}",
+                    url_string(&candidate.url),
+                    candidate.seed,
+                    e
+                );
+                None
+            }
+        })
+        .take(wanted)
+        .collect();
+
+    if discovered.is_empty() {
+        warn!("No witness could be discovered among the peers of the seed nodes");
+    }
+    discovered
+}
+
+async fn peers_of(args: &ClientArgs, seed: &HttpClientUrl) -> Result<Vec<PeerInfo>> {
+    let client = build_rpc_client(seed, None, &RpcConfig::for_url(args, seed))?;
+    let net_info = client.net_info().await.map_err(|e| eyre!(error_message(&e)))?;
+    Ok(net_info.peers)
+}
+
+/// RPC address of a peer, from the `rpc_address` it advertises (e.g. `tcp://0.0.0.0:26657`) and
+/// the IP address it connected from.
+///
+/// Peers whose RPC only listens on the loopback interface or on a UNIX socket are not reachable.
+fn rpc_url(rpc_address: &str, remote_ip: IpAddr) -> Option<HttpClientUrl> {
+    let address = rpc_address
+        .strip_prefix("tcp://")
+        .or_else(|| rpc_address.strip_prefix("http://"))?;
+    let (host, port) = address.rsplit_once(':')?;
+    let port: u16 = port.parse().ok()?;
+
+    let host = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) if ip.is_loopback() => return None,
+        Ok(ip) if ip.is_unspecified() => remote_ip,
+        Ok(ip) => ip,
+        Err(_) => return format!("http://{}:{}", host, port).parse().ok(),
+    };
+    let host = match host {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("[{}]", ip),
+    };
+    format!("http://{}:{}", host, port).parse().ok()
+}
+
+/// Checks that `url` serves the chain, with the trusted header at the trusted height.
+async fn validate(args: &ClientArgs, url: &HttpClientUrl) -> Result<()> {
+    let rpc_config = RpcConfig::for_url(args, url);
+    let mut client = build_rpc_client(url, None, &rpc_config)?;
+
+    let node_info = client.status().await.map_err(|e| eyre!(error_message(&e)))?.node_info;
+    if node_info.network.as_str() != args.chain_id {
+        return Err(eyre!("it serves chain {}", node_info.network));
+    }
+    client.set_compat_mode(match rpc_config.compat_mode {
+        CompatModeSetting::Fixed(mode) => mode,
+        CompatModeSetting::Auto => detect_compat_mode(&node_info.version, url),
+    });
+
+    let (trusted_height, trusted_hash) = args.trust_root()?;
+    let commit = client
+        .commit(trusted_height)
+        .await
+        .map_err(|e| eyre!(error_message(&e)))?;
+    let hash = commit.signed_header.header.hash();
+    if hash != trusted_hash {
+        return Err(eyre!(
+            "its header at the trusted height {} has hash {}, not the trusted hash {}",
+            trusted_height,
+            hash,
+            trusted_hash
+        ));
+    }
+    Ok(())
+}
+
+fn url_string(url: &HttpClientUrl) -> String {
+    tendermint_rpc::Url::from(url.clone()).to_string()
+}
@@ -0,0 +1,141 @@
+use std::fs;
+use std::path::Path;
+
+use color_eyre::eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use tendermint::{evidence::LightClientAttackEvidence, node, Time};
+use tendermint_light_client::types::{Hash, Height, LightBlock};
+use tendermint_light_client_detector::Divergence;
+
+use crate::provider::Peer;
+
+/// Identity of a provider involved in a divergence
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DivergencePeer {
+    pub peer_id: node::Id,
+    pub url: String,
+}
+
+impl From<&Peer> for DivergencePeer {
+    fn from(peer: &Peer) -> Self {
+        Self {
+            peer_id: *peer.provider.peer_id(),
+            url: tendermint_rpc::Url::from(peer.url.clone()).to_string(),
+        }
+    }
+}
+
+/// Everything the detector gathered about a divergence between the primary and a witness,
+/// kept for post-incident analysis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DivergenceRecord {
+    pub detected_at: Time,
+    pub primary: DivergencePeer,
+    pub witness: DivergencePeer,
+    /// Height of the first header on which the primary and the witness disagree
+    pub conflicting_height: Height,
+    /// Light blocks verified on the primary, from the trusted block to the target
+    pub primary_trace: Vec<LightBlock>,
+    /// Light blocks verified on the witness, from the common block to the conflicting one
+    pub witness_trace: Vec<LightBlock>,
+    /// Witness block which conflicts with the primary trace
+    pub challenging_block: LightBlock,
+    /// Evidence reported to the witness
+    pub against_primary: LightClientAttackEvidence,
+    /// Evidence reported to the primary, if the witness trace could be verified against it
+    pub against_witness: Option<LightClientAttackEvidence>,
+}
+
+impl DivergenceRecord {
+    pub fn new(
+        primary: &Peer,
+        witness: &Peer,
+        primary_trace: Vec<LightBlock>,
+        divergence: Divergence,
+    ) -> Self {
+        let evidence = divergence.evidence;
+        Self {
+            detected_at: Time::now(),
+            primary: primary.into(),
+            witness: witness.into(),
+            conflicting_height: evidence.against_primary.conflicting_block.signed_header.header.height,
+            primary_trace,
+            witness_trace: evidence.witness_trace.into_vec(),
+            challenging_block: divergence.challenging_block,
+            against_primary: evidence.against_primary,
+            against_witness: evidence.against_witness,
+        }
+    }
+
+    /// Hash of the primary block on which the witness disagrees.
+    pub fn conflicting_hash(&self) -> Hash {
+        self.against_primary.conflicting_block.signed_header.header.hash()
+    }
+
+    /// Whether both records are about the same conflicting block of the primary, seen by the same
+    /// witness.
+    pub fn same_divergence(&self, other: &Self) -> bool {
+        self.witness.peer_id == other.witness.peer_id
+            && self.conflicting_height == other.conflicting_height
+            && self.conflicting_hash() == other.conflicting_hash()
+    }
+
+    /// Name of the file this record is stored in within the `--evidence-dir`.
+    ///
+    /// Names sort in detection order.
+    fn file_name(&self) -> String {
+        format!(
+            "divergence-{}-{}.json",
+            self.detected_at.unix_timestamp_nanos(),
+            self.conflicting_height
+        )
+    }
+
+    /// Saves the record as a new JSON file in `dir`.
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)
+            .wrap_err_with(|| format!("failed to create evidence directory {}", dir.display()))?;
+        let path = dir.join(self.file_name());
+        let tmp_path = path.with_extension("tmp");
+        let bytes = serde_json::to_vec_pretty(self)?;
+        fs::write(&tmp_path, bytes)
+            .wrap_err_with(|| format!("failed to write evidence file {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path)
+            .wrap_err_with(|| format!("failed to write evidence file {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Loads all records stored in `dir`, in detection order.
+    pub fn load_all(dir: &Path) -> Result<Vec<Self>> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).wrap_err_with(|| {
+                    format!("failed to read evidence directory {}", dir.display())
+                })
+            }
+        };
+
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        paths
+            .iter()
+            .map(|path| {
+                let bytes = fs::read(path).wrap_err_with(|| {
+                    format!("failed to read evidence file {}", path.display())
+                })?;
+                serde_json::from_slice(&bytes).wrap_err_with(|| {
+                    format!("failed to parse evidence file {}", path.display())
+                })
+            })
+            .collect()
+    }
+}
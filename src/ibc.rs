@@ -0,0 +1,94 @@
+use prost::Message;
+use tendermint_light_client::types::LightBlock;
+use tendermint_proto::v0_38::types::{SignedHeader as RawSignedHeader, ValidatorSet as RawValidatorSet};
+
+/// Type URL of the IBC Tendermint client header, for wrapping it in a protobuf `Any`
+pub const HEADER_TYPE_URL: &str = "/ibc.lightclients.tendermint.v1.Header";
+
+/// `ibc.core.client.v1.Height`
+#[derive(Clone, PartialEq, Message)]
+pub struct RawHeight {
+    #[prost(uint64, tag = "1")]
+    pub revision_number: u64,
+    #[prost(uint64, tag = "2")]
+    pub revision_height: u64,
+}
+
+/// `ibc.lightclients.tendermint.v1.Header`
+#[derive(Clone, PartialEq, Message)]
+pub struct RawHeader {
+    #[prost(message, optional, tag = "1")]
+    pub signed_header: Option<RawSignedHeader>,
+    #[prost(message, optional, tag = "2")]
+    pub validator_set: Option<RawValidatorSet>,
+    #[prost(message, optional, tag = "3")]
+    pub trusted_height: Option<RawHeight>,
+    #[prost(message, optional, tag = "4")]
+    pub trusted_validators: Option<RawValidatorSet>,
+}
+
+/// Parses the IBC revision number out of a chain ID of the form `{name}-{revision}`,
+/// defaulting to 0 for chain IDs that don't follow this format.
+pub fn revision_number(chain_id: &str) -> u64 {
+    match chain_id.rsplit_once('-') {
+        Some((name, revision))
+            if !name.is_empty() && !name.ends_with('-') && !revision.starts_with('0') =>
+        {
+            revision.parse().unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+/// Builds the IBC client update header for `target`, trusting the consensus state at `trusted`.
+///
+/// The trusted validators are the next validators of the trusted block, as expected by the IBC
+/// Tendermint client when verifying the update. They are taken from `after_trusted`, the block
+/// following `trusted`, whose validator set carries its proposer: the client rejects sets without
+/// one, and the next validators fetched with the trusted block have none.
+pub fn encode_header(target: &LightBlock, trusted: &LightBlock, after_trusted: &LightBlock) -> Vec<u8> {
+    let revision_number = revision_number(target.signed_header.header.chain_id.as_str());
+
+    RawHeader {
+        signed_header: Some(target.signed_header.clone().into()),
+        validator_set: Some(target.validators.clone().into()),
+        trusted_height: Some(RawHeight {
+            revision_number,
+            revision_height: trusted.height().value(),
+        }),
+        trusted_validators: Some(after_trusted.validators.clone().into()),
+    }
+    .encode_to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{light_block, signing_keys};
+
+    #[test]
+    fn trusted_validators_have_a_proposer() {
+        let keys = signing_keys(4);
+        let trusted = light_block(10, &keys);
+        let after_trusted = light_block(11, &keys);
+        let target = light_block(20, &keys);
+        assert!(trusted.next_validators.proposer().is_none());
+
+        let header = RawHeader::decode(&encode_header(&target, &trusted, &after_trusted)[..]).unwrap();
+        let trusted_validators = header.trusted_validators.unwrap();
+        assert!(trusted_validators.proposer.is_some());
+        assert_eq!(
+            tendermint::validator::Set::try_from(trusted_validators).unwrap().hash(),
+            trusted.signed_header.header.next_validators_hash
+        );
+        assert_eq!(header.trusted_height, Some(RawHeight { revision_number: 1, revision_height: 10 }));
+    }
+
+    #[test]
+    fn parses_revision_number() {
+        assert_eq!(revision_number("cosmoshub-4"), 4);
+        assert_eq!(revision_number("test-1"), 1);
+        assert_eq!(revision_number("osmosis"), 0);
+        assert_eq!(revision_number("chain-01"), 0);
+    }
+}
@@ -0,0 +1,192 @@
+use ::ics23::{
+    calculate_existence_root, commitment_proof::Proof, iavl_spec, tendermint_spec, CommitmentProof,
+    HostFunctionsManager, ProofSpec,
+};
+use color_eyre::eyre::{eyre, Result};
+use prost::Message;
+use tendermint::merkle::proof::{ProofOp, ProofOps};
+
+/// Verifies that `proof` proves `key` holds `value` in the store named `store` under `root`.
+///
+/// Cosmos SDK chains answer a query of `store/<name>/key` with two proof operations: an
+/// `ics23:iavl` proof of the key in the IAVL tree of the store, then an `ics23:simple` proof of
+/// the store root in the multistore, whose root is the app hash. Absence proofs are not supported.
+pub fn verify_membership(proof: &ProofOps, store: &str, key: &[u8], value: &[u8], root: &[u8]) -> Result<()> {
+    let [first, second] = &proof.ops[..] else {
+        return Err(eyre!(
+            "the proof has {} operations instead of a store proof and a multistore proof",
+            proof.ops.len()
+        ));
+    };
+
+    // Both keys are bound to the request, otherwise a proof of the key in another store would pass
+    if first.key != key {
+        return Err(eyre!("the proof is for another key"));
+    }
+    if second.key != store.as_bytes() {
+        return Err(eyre!("the proof is for another store than {}", store));
+    }
+    let store_root = existence_root(first, "ics23:iavl", &iavl_spec(), key, value)?;
+    let computed = existence_root(second, "ics23:simple", &tendermint_spec(), &second.key, &store_root)?;
+
+    if computed != root {
+        return Err(eyre!(
+            "the proof leads to root {}, not to the app hash {}",
+            hex(&computed),
+            hex(root)
+        ));
+    }
+    Ok(())
+}
+
+/// Root of the tree in which the proof operation `op`, of type `field_type`, proves `key` holds
+/// `value`, checking the proof against `spec`.
+fn existence_root(op: &ProofOp, field_type: &str, spec: &ProofSpec, key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+    if op.field_type != field_type {
+        return Err(eyre!("unexpected proof operation {} instead of {}", op.field_type, field_type));
+    }
+
+    let proof = CommitmentProof::decode(&op.data[..])
+        .map_err(|e| eyre!("failed to decode {} proof: {}", field_type, e))?;
+    let exist = match &proof.proof {
+        Some(Proof::Exist(exist)) => exist,
+        Some(Proof::Nonexist(_)) => return Err(eyre!("the key is absent, absence proofs are not supported")),
+        _ => return Err(eyre!("unsupported {} proof type", field_type)),
+    };
+
+    // The proof is checked against the root it leads to, which is then compared with the expected
+    // one, so that a mismatch can be told apart from a malformed proof
+    let root = calculate_existence_root::<HostFunctionsManager>(exist)
+        .map_err(|e| eyre!("invalid {} proof: {}", field_type, e))?;
+    if !::ics23::verify_membership::<HostFunctionsManager>(&proof, spec, &root, key, value) {
+        return Err(eyre!(
+            "the {} proof is for another key or value, or does not match the tree",
+            field_type
+        ));
+    }
+    Ok(root)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ::ics23::{ExistenceProof, HashOp, InnerOp, LeafOp, LengthOp};
+
+    use super::*;
+
+    const KEY: &[u8] = b"balances/alice";
+    const VALUE: &[u8] = b"100uatom";
+
+    fn leaf(prefix: Vec<u8>) -> LeafOp {
+        LeafOp {
+            hash: HashOp::Sha256.into(),
+            prehash_key: HashOp::NoHash.into(),
+            prehash_value: HashOp::Sha256.into(),
+            length: LengthOp::VarProto.into(),
+            prefix,
+        }
+    }
+
+    fn inner(prefix: Vec<u8>, suffix: Vec<u8>) -> InnerOp {
+        InnerOp {
+            hash: HashOp::Sha256.into(),
+            prefix,
+            suffix,
+        }
+    }
+
+    /// Proof operation of `key` holding `value`, along with the root it leads to
+    fn op(field_type: &str, key: &[u8], value: &[u8], leaf: LeafOp, path: Vec<InnerOp>) -> (ProofOp, Vec<u8>) {
+        let exist = ExistenceProof {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            leaf: Some(leaf),
+            path,
+        };
+        let root = calculate_existence_root::<HostFunctionsManager>(&exist).unwrap();
+        let proof = CommitmentProof {
+            proof: Some(Proof::Exist(exist)),
+        };
+        let op = ProofOp {
+            field_type: field_type.to_string(),
+            key: key.to_vec(),
+            data: proof.encode_to_vec(),
+        };
+        (op, root)
+    }
+
+    /// Proof of `KEY` in a two-leaf IAVL `bank` store with the given leaf and inner node prefixes,
+    /// itself next to another store in the multistore, along with the resulting app hash.
+    fn bank_proof_with(leaf_prefix: Vec<u8>, inner_prefix: Vec<u8>) -> (ProofOps, Vec<u8>) {
+        // The proven leaf is the left child, its sibling hash is length-prefixed in the suffix
+        let sibling = [&[32][..], &[0xAB; 32]].concat();
+        let (iavl, store_root) = op("ics23:iavl", KEY, VALUE, leaf(leaf_prefix), vec![inner(inner_prefix, sibling)]);
+        let simple_inner = inner(vec![1], vec![0xCD; 32]);
+        let (simple, app_hash) = op("ics23:simple", b"bank", &store_root, leaf(vec![0]), vec![simple_inner]);
+        (ProofOps { ops: vec![iavl, simple] }, app_hash)
+    }
+
+    fn bank_proof() -> (ProofOps, Vec<u8>) {
+        // Zigzag-encoded height, size and version: 0, 1 and 1 for the leaf, 1, 2 and 1 for the inner
+        // node, whose prefix ends with the length of the proven child hash
+        bank_proof_with(vec![0, 2, 2], vec![2, 4, 2, 32])
+    }
+
+    #[test]
+    fn verifies_store_proof() {
+        let (proof, app_hash) = bank_proof();
+        verify_membership(&proof, "bank", KEY, VALUE, &app_hash).unwrap();
+    }
+
+    #[test]
+    fn rejects_wrong_store() {
+        let (proof, app_hash) = bank_proof();
+        assert!(verify_membership(&proof, "staking", KEY, VALUE, &app_hash).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_value() {
+        let (proof, app_hash) = bank_proof();
+        assert!(verify_membership(&proof, "bank", KEY, b"1000000uatom", &app_hash).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_inner_node() {
+        let (mut proof, app_hash) = bank_proof();
+        let op = &mut proof.ops[0];
+        let mut commitment = CommitmentProof::decode(&op.data[..]).unwrap();
+        let Some(Proof::Exist(exist)) = &mut commitment.proof else {
+            unreachable!("existence proof");
+        };
+        *exist.path[0].suffix.last_mut().unwrap() ^= 1;
+        op.data = commitment.encode_to_vec();
+        assert!(verify_membership(&proof, "bank", KEY, VALUE, &app_hash).is_err());
+    }
+
+    #[test]
+    fn rejects_extra_operations() {
+        let (mut proof, app_hash) = bank_proof();
+        proof.ops.push(proof.ops[1].clone());
+        assert!(verify_membership(&proof, "bank", KEY, VALUE, &app_hash).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_leaf_prefix() {
+        // A fourth byte after the height, size and version
+        let (proof, app_hash) = bank_proof_with(vec![0, 2, 2, 7], vec![2, 4, 2, 32]);
+        assert!(verify_membership(&proof, "bank", KEY, VALUE, &app_hash).is_err());
+        // A leaf at height 1
+        let (proof, app_hash) = bank_proof_with(vec![2, 2, 2], vec![2, 4, 2, 32]);
+        assert!(verify_membership(&proof, "bank", KEY, VALUE, &app_hash).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_inner_remainder() {
+        // Two bytes rather than one or 34 after the height, size and version
+        let (proof, app_hash) = bank_proof_with(vec![0, 2, 2], vec![2, 4, 2, 32, 7]);
+        assert!(verify_membership(&proof, "bank", KEY, VALUE, &app_hash).is_err());
+    }
+}
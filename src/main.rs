@@ -1,96 +1,502 @@
 #![allow(unused)]
 
+use std::future::IntoFuture;
+use std::process::ExitCode;
 use std::sync::Arc;
 use std::time::Instant;
 
-use axum::{routing::get, Router};
+use axum::{
+    middleware,
+    routing::{get, post},
+    Router,
+};
 use clap::Parser;
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use futures::future::try_join_all;
 use tokio::sync::{broadcast, watch};
-use tower_http::cors::{Any, CorsLayer};
-use tracing::info;
-use tracing_subscriber::{util::SubscriberInitExt, EnvFilter};
+use tower_http::{
+    compression::CompressionLayer,
+    cors::{Any, CorsLayer},
+};
+use tracing::{info, warn};
+use tracing_subscriber::{fmt::writer::BoxMakeWriter, util::SubscriberInitExt, EnvFilter};
 
+mod access;
+mod admin;
 mod api;
+mod attest;
+mod audit;
+mod bundle;
+mod cache;
 mod cli;
+mod clock;
+mod discovery;
+mod evidence;
+mod ibc;
+mod ics23;
+mod metrics;
+#[cfg(feature = "mock-provider")]
+mod mock;
+mod openapi;
+mod persist;
+mod provider;
+mod results;
+mod rpc_server;
+mod signatures;
 mod state;
+mod stats;
+mod status;
+mod store;
+mod subscribe;
 mod sync;
+mod systemd;
+#[cfg(test)]
+mod testing;
+mod validators;
+mod verify;
 
 use crate::{
-    api::{root_handler, status_handler},
-    cli::Args,
-    state::{AppState, Config, SharedState},
+    access::access_log,
+    admin::{
+        detect_handler, require_admin_token, reset_trust_handler, snapshot_handler,
+        trust_options_handler, update_trust_options_handler, AdminToken,
+    },
+    api::{
+        app_hash_handler, attestation_key_handler, block_events_handler, block_results_handler,
+        divergence_handler,
+        evidence_handler, headers_handler, health_handler, ibc_header_handler,
+        latest_light_block_handler, light_block_handler, peers_handler, prove_handler,
+        root_handler, status_handler, trust_bundle_handler, validators_diff_handler,
+        verify_handler, ApiState,
+    },
+    attest::Attestor,
+    audit::AuditLog,
+    bundle::TrustBundle,
+    cache::{LightBlockCache, ValidatorSetCache},
+    cli::{Cli, Command, InspectArgs, RunArgs},
+    clock::run_ntp_check,
+    evidence::DivergenceRecord,
+    metrics::{metrics_handler, track_http, Metrics},
+    openapi::openapi_handler,
+    persist::{SyncBookkeeping, TrustedCheckpoint},
+    provider::SharedProviders,
+    rpc_server::{rpc_routes, RpcServerState},
+    state::{AppState, ClockSource, Config, SharedState, UNSAFE_NO_DETECTOR_BANNER},
+    status::run_status,
+    subscribe::run_subscription,
     sync::run_sync,
+    systemd::Notifier,
+    verify::run_verify,
 };
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     color_eyre::install()?;
 
-    let args = Args::parse();
+    let cli = Cli::parse();
 
     let env_filter = EnvFilter::builder()
-        .with_default_directive(args.verbose.to_level_filter().into())
+        .with_default_directive(cli.verbose.to_level_filter().into())
         .from_env_lossy();
 
+    // One-shot commands print their result on stdout, so their logs go to stderr
+    let writer = match cli.command {
+        Command::Run(_) => BoxMakeWriter::new(std::io::stdout),
+        _ => BoxMakeWriter::new(std::io::stderr),
+    };
+
     tracing_subscriber::fmt()
         .with_target(false)
         .with_env_filter(env_filter)
+        .with_writer(writer)
         .finish()
         .init();
 
-    info!("Starting daemon...");
-    run_server(args).await
+    match cli.command {
+        Command::Run(args) => {
+            info!("Starting daemon...");
+            if args.client.unsafe_no_detector() {
+                warn!(
+                    "{}: running against the primary only, a malicious primary can make this daemon serve forged headers",
+                    UNSAFE_NO_DETECTOR_BANNER
+                );
+            }
+            run_server(args).await.map(|_| ExitCode::SUCCESS)
+        }
+        Command::Verify(args) => run_verify(args).await,
+        Command::Inspect(args) => run_inspect(args).map(|_| ExitCode::SUCCESS),
+        Command::Status(args) => run_status(args).await.map(|_| ExitCode::SUCCESS),
+    }
+}
+
+fn run_inspect(args: InspectArgs) -> Result<()> {
+    let bookkeeping = SyncBookkeeping::load(&args.state_file)?
+        .ok_or_else(|| eyre!("state file {} does not exist", args.state_file.display()))?;
+    println!("{}", serde_json::to_string_pretty(&bookkeeping)?);
+    Ok(())
 }
 
-async fn run_server(args: Args) -> Result<()> {
-    let state = Arc::new(tokio::sync::RwLock::new(AppState {
+async fn run_server(mut args: RunArgs) -> Result<()> {
+    let bookkeeping = args.state_file.as_deref().map(SyncBookkeeping::load).transpose()?.flatten();
+    apply_bootstrap_bundle(&mut args)?;
+    let resumed_from = resolve_trust_root(&mut args, bookkeeping.as_ref())?;
+
+    args.client.check_witnesses(!args.discovery_seeds.0.is_empty())?;
+    if args.discovery_seeds.0.is_empty() && args.client.min_witnesses > args.client.witnesses.0.len() {
+        return Err(eyre!(
+            "invalid minimum number of witnesses: {}, there are only {} witnesses",
+            args.client.min_witnesses,
+            args.client.witnesses.0.len()
+        ));
+    }
+
+    if let Some(max_interval) = args.keep_warm_max_interval {
+        if args.keep_warm_interval == 0 || max_interval < args.keep_warm_interval {
+            return Err(eyre!(
+                "invalid maximum keep-warm interval: {}, must be at least the keep-warm interval of {}",
+                max_interval,
+                args.keep_warm_interval
+            ));
+        }
+    }
+
+    if let Some(quorum) = args.quorum {
+        let providers = args.client.witnesses.0.len() + 1;
+        if quorum == 0 || quorum > providers {
+            return Err(eyre!(
+                "invalid quorum: {quorum}, must be between 1 and the number of providers ({providers})"
+            ));
+        }
+    }
+
+    let mut app_state = AppState {
         config: Config {
             freshness_threshold: std::time::Duration::from_secs(args.freshness_threshold),
+            stale_while_revalidate: args.stale_while_revalidate,
+            degradation_policy: args.degradation_policy,
             keep_warm_interval: std::time::Duration::from_secs(args.keep_warm_interval),
             halt_duration_on_fork: std::time::Duration::from_secs(args.halt_duration_on_fork),
             api_timeout: std::time::Duration::from_secs(args.api_timeout),
+            max_api_timeout: std::time::Duration::from_secs(args.max_api_timeout),
+            max_batch_size: args.max_batch_size,
+            unsafe_no_detector: args.client.unsafe_no_detector(),
+            evidence_dir: args.evidence_dir.clone(),
+            clock_source: if args.ntp_server.is_empty() {
+                ClockSource::Providers
+            } else {
+                ClockSource::Ntp
+            },
+            max_clock_offset: args.max_clock_offset.map(|secs| secs as f64),
+            refuse_on_clock_offset: args.refuse_on_clock_offset,
         },
+        client: args.client.clone(),
         light_block: None,
-        last_sync: Instant::now(),
+        trace: Vec::new(),
+        last_sync: None,
+        last_sync_attempt: None,
         syncing: true,
+        api_requests: 0,
+        halted: false,
         last_sync_success: false,
-    }));
+        initialized: false,
+        init_error: None,
+        backoff_secs: 1,
+        sync_failures: 0,
+        providers: Vec::new(),
+        divergences: Vec::new(),
+        clock_skew: None,
+        clock_offset: None,
+        network_tip: None,
+        witness_check: None,
+        resumed_from,
+    };
+
+    if let (Some(path), Some(bookkeeping)) = (&args.state_file, &bookkeeping) {
+        info!("Restoring sync bookkeeping from {}", path.display());
+        bookkeeping.apply(&mut app_state);
+    }
+
+    if let Some(dir) = &args.evidence_dir {
+        app_state.divergences = DivergenceRecord::load_all(dir)?;
+        if !app_state.divergences.is_empty() {
+            warn!(
+                "Loaded {} previously detected divergences from {}",
+                app_state.divergences.len(),
+                dir.display()
+            );
+        }
+    }
+
+    let state = Arc::new(tokio::sync::RwLock::new(app_state));
+    let metrics = Arc::new(Metrics::new()?);
 
     let (sync_trigger_tx, sync_trigger_rx) = broadcast::channel(1);
     let (sync_done_tx, sync_done_rx) = watch::channel(());
+    let (trust_reset_tx, trust_reset_rx) = watch::channel(());
+    let providers: SharedProviders = Arc::new(tokio::sync::Mutex::new(None));
+    let cache = LightBlockCache::new(args.cache_capacity);
+    let validator_sets = ValidatorSetCache::new(args.client.validator_set_cache_capacity);
+    let attestor = args.attestation_key.as_deref().map(Attestor::load).transpose()?;
+    if let Some(attestor) = &attestor {
+        info!("Signing responses with attestation key {}", attestor.public_key());
+    }
+
+    let audit = match &args.audit_log {
+        Some(path) => AuditLog::open(path, args.audit_log_max_size, args.audit_log_max_files)?,
+        None => AuditLog::disabled(),
+    };
+    let admin_token = args.admin_token_file.as_deref().map(AdminToken::load).transpose()?;
+    let notifier = Notifier::from_env();
 
     // Spawn the background syncing task
     let sync_task_state = state.clone();
     let sync_task_args = args.clone();
+    let sync_task_metrics = metrics.clone();
+    let sync_task_providers = providers.clone();
+    let sync_task_cache = cache.clone();
+    let sync_task_validator_sets = validator_sets.clone();
+    let sync_task_audit = audit.clone();
+    let sync_task_notifier = notifier.clone();
     tokio::spawn(async move {
         run_sync(
             sync_task_args,
             sync_task_state,
+            sync_task_providers,
+            sync_task_cache,
+            sync_task_validator_sets,
             sync_trigger_rx,
             sync_done_tx,
+            trust_reset_rx,
+            sync_task_metrics,
+            sync_task_audit,
+            sync_task_notifier,
         )
         .await;
     });
 
+    if !args.ntp_server.is_empty() {
+        tokio::spawn(run_ntp_check(
+            args.ntp_server.clone(),
+            std::time::Duration::from_secs(args.clock_check_interval),
+            state.clone(),
+            metrics.clone(),
+        ));
+    }
+
+    if args.subscribe {
+        tokio::spawn(run_subscription(args.clone(), state.clone(), sync_trigger_tx.clone()));
+    }
+
     // Create the Axum app
-    let sync_trigger_tx_for_state = sync_trigger_tx.clone();
-    let app = Router::new()
+    let api_state = ApiState {
+        state: state.clone(),
+        sync_trigger: sync_trigger_tx.clone(),
+        sync_done: sync_done_rx,
+        trust_reset: Arc::new(trust_reset_tx),
+        providers,
+        cache,
+        validator_sets,
+        attestor,
+        audit,
+    };
+    let mut public_routes = Router::new()
         .route("/", get(root_handler))
         .route("/v1/status", get(status_handler))
-        .with_state((state, sync_trigger_tx_for_state, sync_done_rx))
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods(Any)
-                .allow_headers(Any),
+        .route("/v1/health", get(health_handler))
+        .route("/v1/light_block", get(latest_light_block_handler))
+        .route("/v1/light_block/:height", get(light_block_handler))
+        .route("/v1/peers", get(peers_handler))
+        .route("/v1/attestation_key", get(attestation_key_handler))
+        .route("/v1/trust_bundle", get(trust_bundle_handler))
+        .route("/v1/block_results/:height", get(block_results_handler))
+        .route("/v1/block_results/:height/events", get(block_events_handler))
+        .route("/v1/app_hash/:height", get(app_hash_handler))
+        .route("/v1/ibc_header/:height", get(ibc_header_handler))
+        .route("/v1/headers", post(headers_handler))
+        .route("/v1/prove", post(prove_handler))
+        .route("/v1/verify", post(verify_handler))
+        .route("/v1/validators/diff", get(validators_diff_handler))
+        .route("/v1/evidence", get(evidence_handler))
+        .route("/v1/evidence/:id", get(divergence_handler))
+        .route("/openapi.json", get(openapi_handler))
+        .with_state(api_state.clone());
+    if args.serve_rpc {
+        let rpc_state = RpcServerState::new(
+            api_state.clone(),
+            api_state.attestor.as_deref(),
+            &args.client.chain_id,
+            args.listen_addr[0],
         );
+        public_routes = public_routes.merge(rpc_routes(rpc_state));
+    }
+    let public_routes =
+        public_routes.layer(middleware::from_fn_with_state(metrics.clone(), track_http));
+    let mut admin_routes = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .with_state(metrics);
+    if let Some(admin_token) = admin_token {
+        let admin_api_routes = Router::new()
+            .route(
+                "/v1/admin/trust_options",
+                get(trust_options_handler).patch(update_trust_options_handler),
+            )
+            .route("/v1/admin/reset_trust", post(reset_trust_handler))
+            .route("/v1/admin/snapshot", get(snapshot_handler))
+            .route("/v1/admin/detect", post(detect_handler))
+            .with_state(api_state)
+            .layer(middleware::from_fn_with_state(admin_token, require_admin_token));
+        admin_routes = admin_routes.merge(admin_api_routes);
+    }
+    let cors = CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods(Any)
+        .allow_headers(Any);
+
+    // Responses are compressed (gzip or brotli) when the client accepts it
+    let mut full_app = public_routes
+        .clone()
+        .merge(admin_routes)
+        .layer(cors.clone())
+        .layer(CompressionLayer::new());
+    let mut public_app = public_routes.layer(cors).layer(CompressionLayer::new());
+    if let Some(format) = args.access_log {
+        full_app = full_app.layer(middleware::from_fn_with_state(format, access_log));
+        public_app = public_app.layer(middleware::from_fn_with_state(format, access_log));
+    }
+
+    // Admin routes are served on every listener, unless dedicated admin listeners are set
+    let mut bindings = Vec::new();
+    if args.admin_listen_addr.is_empty() {
+        bindings.extend(args.listen_addr.iter().map(|addr| (*addr, full_app.clone(), "")));
+    } else {
+        bindings.extend(args.listen_addr.iter().map(|addr| (*addr, public_app.clone(), "")));
+        bindings.extend(args.admin_listen_addr.iter().map(|addr| (*addr, full_app.clone(), " (admin)")));
+    }
+
+    let mut servers = Vec::with_capacity(bindings.len());
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    for (addr, app, kind) in bindings {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        info!("Listening on http://{}{}", addr, kind);
+
+        let mut shutdown_rx = shutdown_rx.clone();
+        let app = app.into_make_service_with_connect_info::<std::net::SocketAddr>();
+        let server = axum::serve(listener, app).with_graceful_shutdown(async move {
+            let _ = shutdown_rx.changed().await;
+        });
+        servers.push(server.into_future());
+    }
+
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = shutdown_tx.send(());
+    });
 
-    info!("Listening on http://{}", args.listen_addr);
-    let listener = tokio::net::TcpListener::bind(args.listen_addr).await?;
     // Trigger initial sync immediately on startup
     let _ = sync_trigger_tx.send(());
-    axum::serve(listener, app).await?;
+    try_join_all(servers).await?;
+
+    info!("Shutting down...");
+    notifier.stopping();
+    if let Some(path) = &args.state_file {
+        let bookkeeping = SyncBookkeeping::from_state(&*state.read().await);
+        bookkeeping.save(path)?;
+    }
+
+    Ok(())
+}
+
+/// Takes the trusted header from the `--bootstrap-bundle`, as if it was given with
+/// `--trusted-height` and `--trusted-hash`.
+fn apply_bootstrap_bundle(args: &mut RunArgs) -> Result<()> {
+    let Some(path) = &args.bootstrap_bundle else {
+        return Ok(());
+    };
+    let bundle = TrustBundle::load(path)?;
+    let trusting_period = std::time::Duration::from_secs(args.client.trusting_period);
+    bundle
+        .check(&args.client.chain_id, trusting_period, args.bootstrap_bundle_key.as_deref())
+        .wrap_err_with(|| format!("invalid trust bundle {}", path.display()))?;
 
+    if args.bootstrap_bundle_key.is_none() {
+        warn!(
+            "The signature of trust bundle {} is not checked without --bootstrap-bundle-key",
+            path.display()
+        );
+    }
+    if let Some(warning) = &bundle.warning {
+        warn!("Trust bundle {} was exported with the warning: {}", path.display(), warning);
+    }
+    info!(
+        "Trusting block {} ({}) of trust bundle {}",
+        bundle.height,
+        bundle.hash,
+        path.display()
+    );
+    args.client.trusted_height = Some(bundle.height);
+    args.client.trusted_hash = Some(bundle.hash);
     Ok(())
 }
+
+/// Starts from the trusted block of the state file if it is on the chain and within the trusting
+/// period, unless `--rebootstrap` is set, and from the trusted header of the command line
+/// otherwise. Returns the trusted block of the state file if it is the one used.
+fn resolve_trust_root(
+    args: &mut RunArgs,
+    bookkeeping: Option<&SyncBookkeeping>,
+) -> Result<Option<TrustedCheckpoint>> {
+    let checkpoint = bookkeeping
+        .and_then(|bookkeeping| bookkeeping.trusted_block.as_ref())
+        .filter(|_| !args.rebootstrap);
+    if let Some(checkpoint) = checkpoint {
+        let trusting_period = std::time::Duration::from_secs(args.client.trusting_period);
+        if checkpoint.chain_id != args.client.chain_id {
+            warn!(
+                "Not resuming from the trusted block of the state file, which belongs to chain {}",
+                checkpoint.chain_id
+            );
+        } else if !checkpoint.within_trusting_period(trusting_period) {
+            warn!(
+                "Not resuming from the trusted block {} of the state file, whose trusting period expired",
+                checkpoint.height
+            );
+        } else {
+            info!(
+                "Resuming from trusted block {} ({}) of the state file",
+                checkpoint.height, checkpoint.hash
+            );
+            args.client.trusted_height = Some(checkpoint.height);
+            args.client.trusted_hash = Some(checkpoint.hash);
+            return Ok(Some(checkpoint.clone()));
+        }
+    }
+
+    args.client.trust_root().map_err(|_| {
+        eyre!("--trusted-height and --trusted-hash are required without a trusted block within the trusting period to resume from in the --state-file")
+    })?;
+    Ok(None)
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
@@ -0,0 +1,183 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    body::HttpBody,
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use color_eyre::eyre::Result;
+use prometheus::{
+    exponential_buckets, Encoder, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec,
+    IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+use crate::stats::ProviderRole;
+
+/// Prometheus metrics exposed on `/metrics`
+pub struct Metrics {
+    registry: Registry,
+    http_request_duration: HistogramVec,
+    http_response_size: HistogramVec,
+    provider_rpc_duration: HistogramVec,
+    provider_rpc_errors: IntCounterVec,
+    provider_success_rate: GaugeVec,
+    /// Number of times syncing resumed after the primary and witnesses agreed again following a fork
+    pub fork_recoveries: IntCounter,
+    /// Latest block time reported by the providers minus the local time, see [`crate::clock`]
+    pub clock_skew: Gauge,
+    /// Reference time minus the local time, see [`crate::clock`]
+    pub clock_offset: Gauge,
+}
+
+/// Metrics of a single provider, see [`Metrics::provider`]
+pub struct ProviderMetrics {
+    pub latency: Histogram,
+    pub errors: IntCounter,
+    pub success_rate: Gauge,
+}
+
+pub type SharedMetrics = Arc<Metrics>;
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new_custom(Some("helios".to_string()), None)?;
+
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Latency of API requests, by route and status code",
+            ),
+            &["route", "status"],
+        )?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+
+        let http_response_size = HistogramVec::new(
+            HistogramOpts::new(
+                "http_response_size_bytes",
+                "Size of API response bodies, by route and status code",
+            )
+            .buckets(exponential_buckets(128.0, 4.0, 8)?),
+            &["route", "status"],
+        )?;
+        registry.register(Box::new(http_response_size.clone()))?;
+
+        let provider_rpc_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "provider_rpc_duration_seconds",
+                "Latency of RPC calls to the providers, by role and URL",
+            ),
+            &["role", "url"],
+        )?;
+        registry.register(Box::new(provider_rpc_duration.clone()))?;
+
+        let provider_rpc_errors = IntCounterVec::new(
+            Opts::new(
+                "provider_rpc_errors_total",
+                "Number of failed RPC calls to the providers, by role and URL",
+            ),
+            &["role", "url"],
+        )?;
+        registry.register(Box::new(provider_rpc_errors.clone()))?;
+
+        let provider_success_rate = GaugeVec::new(
+            Opts::new(
+                "provider_rpc_success_rate",
+                "Rolling success rate of RPC calls to the providers, by role and URL",
+            ),
+            &["role", "url"],
+        )?;
+        registry.register(Box::new(provider_success_rate.clone()))?;
+
+        let fork_recoveries = IntCounter::new(
+            "fork_recoveries_total",
+            "Number of times syncing resumed after the primary and witnesses agreed again following a fork",
+        )?;
+        registry.register(Box::new(fork_recoveries.clone()))?;
+
+        let clock_skew = Gauge::new(
+            "clock_skew_seconds",
+            "Latest block time reported by the providers minus the local time, positive when the local clock is behind",
+        )?;
+        registry.register(Box::new(clock_skew.clone()))?;
+
+        let clock_offset = Gauge::new(
+            "clock_offset_seconds",
+            "Time of the NTP servers, or median latest block time of the providers, minus the local time",
+        )?;
+        registry.register(Box::new(clock_offset.clone()))?;
+
+        Ok(Self {
+            registry,
+            http_request_duration,
+            http_response_size,
+            provider_rpc_duration,
+            provider_rpc_errors,
+            provider_success_rate,
+            fork_recoveries,
+            clock_skew,
+            clock_offset,
+        })
+    }
+
+    /// Returns the metrics of the provider with the given role and URL.
+    pub fn provider(&self, role: ProviderRole, url: &str) -> ProviderMetrics {
+        let role = role.to_string();
+        let labels = [role.as_str(), url];
+        ProviderMetrics {
+            latency: self.provider_rpc_duration.with_label_values(&labels),
+            errors: self.provider_rpc_errors.with_label_values(&labels),
+            success_rate: self.provider_success_rate.with_label_values(&labels),
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+/// Middleware recording the latency and response size of every API request.
+pub async fn track_http(
+    State(metrics): State<SharedMetrics>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = start.elapsed();
+
+    let status = response.status().as_u16().to_string();
+    let labels = [route.as_str(), status.as_str()];
+    metrics
+        .http_request_duration
+        .with_label_values(&labels)
+        .observe(elapsed.as_secs_f64());
+
+    if let Some(size) = response.body().size_hint().exact() {
+        metrics
+            .http_response_size
+            .with_label_values(&labels)
+            .observe(size as f64);
+    }
+
+    response
+}
+
+pub async fn metrics_handler(State(metrics): State<SharedMetrics>) -> Response {
+    match metrics.render() {
+        Ok(body) => ([(header::CONTENT_TYPE, TextEncoder::new().format_type().to_string())], body)
+            .into_response(),
+        Err(_) => http::StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
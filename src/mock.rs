@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use tendermint::crypto::{default::Sha256, Sha256 as _};
+use tendermint::node;
+use tendermint_light_client::{
+    components::io::{AtHeight, Io, IoError},
+    types::{Height, LightBlock, Status},
+};
+use tendermint_rpc::HttpClientUrl;
+
+use crate::{provider::error_message, stats::SharedProviderStats};
+
+/// Light blocks of a provider, in the format of the `/v1/admin/snapshot` dump
+#[derive(Debug, Deserialize)]
+struct Fixture {
+    chain_id: String,
+    blocks: Vec<FixtureBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FixtureBlock {
+    /// Status in the light store the block was dumped from, if any
+    status: Option<Status>,
+    light_block: LightBlock,
+}
+
+/// [`Io`] component serving the light blocks of a fixture file instead of fetching them over RPC,
+/// for integration tests and local development without a running chain.
+///
+/// The highest block of the fixture is the latest block of the provider.
+pub struct FixtureIo {
+    path: PathBuf,
+    peer_id: node::Id,
+    blocks: BTreeMap<Height, LightBlock>,
+    stats: SharedProviderStats,
+}
+
+impl FixtureIo {
+    /// Loads the fixture at `path`, gzip-compressed if its name ends with `.gz`.
+    ///
+    /// Blocks that failed verification in the light store they were dumped from are skipped.
+    pub fn load(
+        path: &Path,
+        chain_id: &str,
+        url: &HttpClientUrl,
+        stats: SharedProviderStats,
+    ) -> Result<Self> {
+        let file = File::open(path)
+            .wrap_err_with(|| format!("failed to open fixture {}", path.display()))?;
+        let reader: Box<dyn Read> = if path.extension().is_some_and(|ext| ext == "gz") {
+            Box::new(GzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+        let fixture: Fixture = serde_json::from_reader(BufReader::new(reader))
+            .wrap_err_with(|| format!("failed to parse fixture {}", path.display()))?;
+        if fixture.chain_id != chain_id {
+            return Err(eyre!(
+                "fixture {} is for chain {}, not {}",
+                path.display(),
+                fixture.chain_id,
+                chain_id
+            ));
+        }
+
+        let peer_id = peer_id(url);
+        let blocks: BTreeMap<_, _> = fixture
+            .blocks
+            .into_iter()
+            .filter(|block| block.status != Some(Status::Failed))
+            .map(|block| {
+                let mut light_block = block.light_block;
+                light_block.provider = peer_id;
+                (light_block.height(), light_block)
+            })
+            .collect();
+        if blocks.is_empty() {
+            return Err(eyre!("fixture {} has no light blocks", path.display()));
+        }
+
+        if let Ok(mut stats) = stats.lock() {
+            stats.peer_id = Some(peer_id);
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            peer_id,
+            blocks,
+            stats,
+        })
+    }
+
+    pub fn peer_id(&self) -> node::Id {
+        self.peer_id
+    }
+
+    fn lookup(&self, height: AtHeight) -> Result<LightBlock, IoError> {
+        let (&latest_height, latest) = self.blocks.last_key_value().expect("fixtures are not empty");
+        match height {
+            AtHeight::Highest => Ok(latest.clone()),
+            AtHeight::At(height) if height > latest_height => {
+                Err(IoError::height_too_high(height, latest_height))
+            }
+            AtHeight::At(height) => self.blocks.get(&height).cloned().ok_or_else(|| {
+                IoError::rpc(tendermint_rpc::Error::server(format!(
+                    "height {} is not in fixture {}",
+                    height,
+                    self.path.display()
+                )))
+            }),
+        }
+    }
+}
+
+impl Io for FixtureIo {
+    fn fetch_light_block(&self, height: AtHeight) -> Result<LightBlock, IoError> {
+        let result = self.lookup(height);
+        if let Ok(mut stats) = self.stats.lock() {
+            let outcome = result.as_ref().map(|_| ()).map_err(error_message);
+            stats.record(Duration::ZERO, outcome);
+        }
+        result
+    }
+}
+
+/// Peer id of a mock provider, derived from its URL so that providers sharing a fixture are still
+/// told apart
+fn peer_id(url: &HttpClientUrl) -> node::Id {
+    let url = tendermint_rpc::Url::from(url.clone()).to_string();
+    let digest = Sha256::digest(url.as_bytes());
+    let mut bytes = [0; 20];
+    bytes.copy_from_slice(&digest[..20]);
+    node::Id::new(bytes)
+}
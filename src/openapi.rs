@@ -0,0 +1,744 @@
+use axum::Json;
+use serde_json::{json, Map, Value};
+
+/// Serves the OpenAPI description of the public API.
+pub async fn openapi_handler() -> Json<Value> {
+    Json(document())
+}
+
+/// OpenAPI 3.0 description of the public API, along with the `--serve-rpc` routes and `/metrics`.
+///
+/// Kept next to the route table in `main.rs`: a route added there must be described here too,
+/// which the tests check. The admin API is left out.
+pub fn document() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "helios-light-client",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Verified CometBFT light client state. Errors are returned as plain text.",
+        },
+        "paths": {
+            "/": {
+                "get": operation("Daemon name and version", &[], &[("200", ok("RootResponse"))]),
+            },
+            "/v1/status": {
+                "get": operation(
+                    "Latest trusted block, synced first if older than the freshness threshold",
+                    &[freshness_param(), timeout_param(), trace_param()],
+                    &[
+                        ("200", ok("StatusResponse")),
+                        ("304", text("The block matches `If-None-Match`")),
+                        ("503", ok("StatusUnavailableResponse")),
+                        ("504", text("The sync did not complete within the API timeout")),
+                    ],
+                ),
+            },
+            "/v1/health": {
+                "get": operation(
+                    "Whether the daemon is healthy",
+                    &[],
+                    &[("200", ok("HealthResponse")), ("503", ok("HealthResponse"))],
+                ),
+            },
+            "/v1/light_block": {
+                "get": operation(
+                    "Latest trusted light block, synced first if older than the freshness threshold",
+                    &[freshness_param(), timeout_param(), trace_param(), format_param(&["json", "proto", "base64"])],
+                    &[
+                        ("200", light_block_ok()),
+                        ("400", text("Unknown format, or a trace requested with `format=proto`")),
+                        ("304", text("The block matches `If-None-Match`")),
+                        ("503", text("No block has been verified yet")),
+                        ("504", text("The sync did not complete within the API timeout")),
+                    ],
+                ),
+            },
+            "/v1/light_block/{height}": {
+                "get": operation(
+                    "Light block verified at the given height",
+                    &[
+                        height_param(),
+                        query_param(
+                            "timeout",
+                            "How long to wait for a sync in progress before verifying the height (in seconds), overriding `--api-timeout` up to `--max-api-timeout`",
+                            false,
+                            json!({ "type": "integer", "minimum": 0 }),
+                        ),
+                        trace_param(),
+                        format_param(&["json", "proto", "base64"]),
+                    ],
+                    &verified(("200", light_block_ok())),
+                ),
+            },
+            "/v1/peers": {
+                "get": operation("Primary and witnesses with their RPC statistics", &[], &[("200", ok("PeersResponse"))]),
+            },
+            "/v1/attestation_key": {
+                "get": operation(
+                    "Public key with which responses are signed",
+                    &[],
+                    &[("200", ok("AttestationKeyResponse")), ("404", text("No attestation key is configured"))],
+                ),
+            },
+            "/v1/trust_bundle": {
+                "get": operation(
+                    "Latest trusted block as a bundle from which other instances can start syncing with `--bootstrap-bundle`",
+                    &[],
+                    &[("200", ok("TrustBundle")), ("503", text("No block has been verified yet, or syncing is halted"))],
+                ),
+            },
+            "/v1/block_results/{height}": {
+                "get": operation(
+                    "Block results checked against the `last_results_hash` of the verified next header",
+                    &[height_param()],
+                    &verified(("200", ok("BlockResultsResponse"))),
+                ),
+            },
+            "/v1/block_results/{height}/events": {
+                "get": operation(
+                    "Unverified events of the block results whose transaction results are checked against the verified next header",
+                    &[
+                        height_param(),
+                        query_param("type", "Only return events of this type", false, json!({ "type": "string" })),
+                    ],
+                    &verified(("200", ok("BlockEventsResponse"))),
+                ),
+            },
+            "/v1/app_hash/{height}": {
+                "get": operation(
+                    "App hash of the verified header at the given height",
+                    &[height_param()],
+                    &verified(("200", ok("AppHashResponse"))),
+                ),
+            },
+            "/v1/ibc_header/{height}": {
+                "get": operation(
+                    "IBC `MsgUpdateClient` header from a trusted height to the given height",
+                    &[
+                        height_param(),
+                        query_param("trusted_height", "Height of the consensus state the IBC client trusts", true, json!({ "type": "integer", "minimum": 1 })),
+                        query_param("encoding", "`base64` (JSON) or `binary` (protobuf)", false, json!({ "type": "string", "enum": ["base64", "binary"], "default": "base64" })),
+                    ],
+                    &verified(("200", ok("IbcHeaderResponse"))),
+                ),
+            },
+            "/v1/headers": {
+                "post": {
+                    "summary": "Verifies a batch of heights",
+                    "parameters": [format_param(&["json", "base64"])],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": schema_ref("HeadersRequest") } },
+                    },
+                    "responses": responses(&[
+                        ("200", ok("HeadersResponse")),
+                        ("400", text("Unknown or unsupported format")),
+                        ("413", text("More heights than `--max-batch-size`")),
+                        ("503", text("The providers are not initialized yet")),
+                    ]),
+                },
+            },
+            "/v1/prove": {
+                "post": {
+                    "summary": "Queries a key with an ICS-23 proof and verifies it against the app hash of a verified header",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": schema_ref("ProveRequest") } },
+                    },
+                    "responses": responses(&verified(("200", ok("ProveResponse")))),
+                },
+            },
+            "/v1/verify": {
+                "post": {
+                    "summary": "Verifies an externally supplied header against the trusted state and trust options",
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/json": { "schema": schema_ref("VerifyRequest") },
+                            "application/x-protobuf": {
+                                "schema": { "type": "string", "format": "binary", "description": "tendermint.types.LightBlock" },
+                            },
+                        },
+                    },
+                    "responses": responses(&[
+                        ("200", ok("VerifyResponse")),
+                        ("400", text("The body is not a valid header and validator set")),
+                        ("503", text("The providers are not initialized yet")),
+                        ("504", text("The providers are busy syncing")),
+                    ]),
+                },
+            },
+            "/v1/validators/diff": {
+                "get": operation(
+                    "Validator set changes between two verified heights",
+                    &[
+                        query_param("from", "First height", true, json!({ "type": "integer", "minimum": 1 })),
+                        query_param("to", "Second height", true, json!({ "type": "integer", "minimum": 1 })),
+                    ],
+                    &verified(("200", ok("ValidatorsDiffResponse"))),
+                ),
+            },
+            "/v1/evidence": {
+                "get": operation("Divergences found by the fork detector", &[], &[("200", ok("EvidenceResponse"))]),
+            },
+            "/v1/evidence/{id}": {
+                "get": operation(
+                    "Full record of a divergence, with the evidence submitted to the providers",
+                    &[path_param("id", "Identifier listed by `/v1/evidence`")],
+                    &[("200", ok("DivergenceRecord")), ("404", text("No divergence with this id"))],
+                ),
+            },
+            "/openapi.json": {
+                "get": operation("This document", &[], &[("200", json!({ "description": "OpenAPI document" }))]),
+            },
+            "/rpc": {
+                "post": {
+                    "summary": "CometBFT JSON-RPC 2.0 call to `status`, `commit` or `validators`, answered from verified blocks only, with `--serve-rpc`",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": { "type": "object", "description": "JSON-RPC 2.0 request" } } },
+                    },
+                    "responses": responses(&[("200", rpc_ok())]),
+                },
+            },
+            "/rpc/{method}": {
+                "get": operation(
+                    "CometBFT URI request, e.g. `/rpc/commit?height=5`, with the parameters of the method as query parameters, with `--serve-rpc`",
+                    &[json!({
+                        "name": "method",
+                        "in": "path",
+                        "required": true,
+                        "description": "`status`, `commit` or `validators`",
+                        "schema": { "type": "string" },
+                    })],
+                    &[("200", rpc_ok())],
+                ),
+            },
+            "/metrics": {
+                "get": operation(
+                    "Prometheus metrics, served on `--admin-listen-addr` if set",
+                    &[],
+                    &[(
+                        "200",
+                        json!({
+                            "description": "Metrics in the Prometheus text format",
+                            "content": { "text/plain": { "schema": { "type": "string" } } },
+                        }),
+                    )],
+                ),
+            },
+        },
+        "components": { "schemas": schemas() },
+    })
+}
+
+fn schemas() -> Value {
+    let string = || json!({ "type": "string" });
+    let height = || json!({ "type": "string", "description": "Block height, as a decimal string" });
+    let hash = || json!({ "type": "string", "description": "Upper-case hex-encoded hash" });
+    let time = || json!({ "type": "string", "format": "date-time" });
+    let power = || json!({ "type": "string", "description": "Voting power, as a decimal string" });
+    let warning = || {
+        json!({ "type": "string", "description": "Set when the daemon runs without fork detection" })
+    };
+    let light_block = || {
+        json!({ "type": "object", "description": "CometBFT light block: `signed_header`, `validators`, `next_validators` and `provider`" })
+    };
+    let array = |items: Value| json!({ "type": "array", "items": items });
+
+    json!({
+        "RootResponse": object(&[("name", string()), ("version", string()), ("warning", warning())], &["name", "version"]),
+        "TraceStep": object(&[("height", height()), ("hash", hash()), ("time", time())], &["height", "hash", "time"]),
+        "StatusResponse": object(
+            &[
+                ("block_height", height()),
+                ("block_hash", hash()),
+                ("block_timestamp", time()),
+                ("app_hash", hash()),
+                ("halted", json!({ "type": "boolean", "description": "Whether syncing is halted after a detected fork" })),
+                ("stale", json!({ "type": "boolean", "description": "Whether the block is older than the freshness threshold, e.g. while it is refreshed in the background" })),
+                ("state", schema_ref("SyncState")),
+                ("last_sync_success", json!({ "type": "boolean", "description": "Whether the last sync attempt succeeded" })),
+                ("consecutive_sync_failures", json!({ "type": "integer", "description": "Syncs that failed since the last successful one" })),
+                ("secs_since_last_success", json!({ "type": "integer", "nullable": true, "description": "Seconds since the last successful sync" })),
+                ("network_height", json!({ "type": "string", "nullable": true, "description": "Latest block height reported by the primary, as a decimal string" })),
+                ("lag_blocks", json!({ "type": "integer", "nullable": true, "description": "Blocks between the verified block and the network height" })),
+                ("lag_secs", json!({ "type": "integer", "nullable": true, "description": "Seconds between the verified block and the latest block of the primary" })),
+                ("witness_check", json!({ "allOf": [schema_ref("WitnessSummary")], "nullable": true })),
+                ("trace", array(schema_ref("TraceStep"))),
+                ("warning", warning()),
+            ],
+            &["block_height", "block_hash", "block_timestamp", "app_hash", "halted", "stale", "state", "last_sync_success", "consecutive_sync_failures", "secs_since_last_success", "network_height", "lag_blocks", "lag_secs", "witness_check"],
+        ),
+        "WitnessSummary": object(
+            &[
+                ("checked", json!({ "type": "integer", "description": "Witnesses against which fork detection ran" })),
+                ("agreed", json!({ "type": "integer" })),
+                ("diverged", json!({ "type": "integer" })),
+                ("errored", json!({ "type": "integer", "description": "Witnesses against which fork detection failed, e.g. unreachable ones" })),
+                ("checked_at", time()),
+            ],
+            &["checked", "agreed", "diverged", "errored", "checked_at"],
+        ),
+        "SyncState": json!({
+            "type": "string",
+            "enum": ["initializing", "synced", "degraded", "halted"],
+            "description": "`initializing` until the providers are initialized, `degraded` after a failed sync, `halted` after a detected fork",
+        }),
+        "StatusUnavailableResponse": object(
+            &[
+                ("state", schema_ref("SyncState")),
+                (
+                    "error",
+                    json!({
+                        "type": "string",
+                        "description": "Why the providers could not be initialized yet, or why the block is refused while the local clock is off",
+                    }),
+                ),
+            ],
+            &["state"],
+        ),
+        "HealthResponse": object(
+            &[
+                ("healthy", json!({ "type": "boolean" })),
+                ("clock_skew_secs", json!({ "type": "number", "nullable": true })),
+                ("clock_offset", json!({ "allOf": [schema_ref("ClockOffset")], "nullable": true })),
+                ("issues", array(string())),
+            ],
+            &["healthy", "clock_skew_secs", "clock_offset", "issues"],
+        ),
+        "ClockOffset": object(
+            &[
+                ("offset_secs", json!({ "type": "number", "description": "Reference time minus the local time" })),
+                ("source", json!({ "type": "string", "enum": ["ntp", "providers"] })),
+                ("checked_at", json!({ "type": "string", "format": "date-time" })),
+            ],
+            &["offset_secs", "source", "checked_at"],
+        ),
+        "LightBlockResponse": object(
+            &[
+                (
+                    "light_block",
+                    json!({
+                        "oneOf": [
+                            light_block(),
+                            { "type": "string", "format": "byte", "description": "tendermint.types.LightBlock, with `format=base64`" },
+                        ],
+                    }),
+                ),
+                ("trace", array(schema_ref("TraceStep"))),
+                ("warning", warning()),
+            ],
+            &["light_block"],
+        ),
+        "PeerResponse": object(
+            &[
+                ("role", json!({ "type": "string", "enum": ["primary", "witness"] })),
+                ("url", string()),
+                ("peer_id", json!({ "type": "string", "nullable": true })),
+                ("health", json!({ "type": "string", "enum": ["unknown", "healthy", "unhealthy", "wrong_chain"] })),
+                ("wrong_chain", string()),
+                ("last_contact", json!({ "type": "string", "format": "date-time", "nullable": true })),
+                ("success_rate", json!({ "type": "number", "nullable": true })),
+                ("mean_latency_ms", json!({ "type": "integer", "nullable": true })),
+                ("last_error", json!({ "type": "string", "nullable": true })),
+            ],
+            &["role", "url", "peer_id", "health", "last_contact", "success_rate", "mean_latency_ms", "last_error"],
+        ),
+        "PeersResponse": object(&[("peers", array(schema_ref("PeerResponse")))], &["peers"]),
+        "AttestationKeyResponse": object(
+            &[("algorithm", json!({ "type": "string", "enum": ["ed25519"] })), ("public_key", json!({ "type": "string", "format": "byte" }))],
+            &["algorithm", "public_key"],
+        ),
+        "TrustBundle": object(
+            &[
+                ("chain_id", string()),
+                ("height", height()),
+                ("hash", hash()),
+                ("signed_header", json!({ "type": "object", "description": "CometBFT signed header" })),
+                ("validators", json!({ "type": "object", "description": "CometBFT validator set" })),
+                (
+                    "attestation",
+                    json!({ "allOf": [schema_ref("BundleAttestation")], "nullable": true, "description": "Set if an attestation key is configured" }),
+                ),
+                ("warning", warning()),
+            ],
+            &["chain_id", "height", "hash", "signed_header", "validators", "attestation"],
+        ),
+        "BundleAttestation": object(
+            &[
+                ("algorithm", json!({ "type": "string", "enum": ["ed25519"] })),
+                ("public_key", json!({ "type": "string", "format": "byte" })),
+                ("signature", json!({ "type": "string", "format": "byte", "description": "Signature of `helios-light-client trust bundle\\n<chain_id>\\n<height>\\n<hash>`" })),
+            ],
+            &["algorithm", "public_key", "signature"],
+        ),
+        "BlockResultsResponse": object(
+            &[
+                ("height", height()),
+                ("verified_header_height", height()),
+                ("last_results_hash", hash()),
+                ("txs_results", array(schema_ref("TxResult"))),
+                ("unverified", json!({
+                    "type": "object",
+                    "description": "Not committed to by any header, as returned by the primary: `txs_results` (`log`, `info`, `codespace` and `events` of each transaction), `finalize_block_events`, `begin_block_events`, `end_block_events`, `validator_updates` and `consensus_param_updates`",
+                })),
+                ("warning", warning()),
+            ],
+            &["height", "verified_header_height", "last_results_hash", "txs_results", "unverified"],
+        ),
+        "TxResult": object(
+            &[
+                ("code", json!({ "type": "integer" })),
+                ("data", json!({ "type": "string", "format": "byte" })),
+                ("gas_wanted", string()),
+                ("gas_used", string()),
+            ],
+            &["code", "data", "gas_wanted", "gas_used"],
+        ),
+        "BlockEvent": object(
+            &[
+                ("source", json!({ "type": "string", "enum": ["tx", "finalize_block", "begin_block", "end_block"] })),
+                ("tx_index", json!({ "type": "integer", "description": "Index of the transaction for `tx` events" })),
+                ("type", string()),
+                ("attributes", array(json!({ "type": "object" }))),
+            ],
+            &["source", "type", "attributes"],
+        ),
+        "BlockEventsResponse": object(
+            &[
+                ("height", height()),
+                ("verified_header_height", height()),
+                ("unverified_events", array(schema_ref("BlockEvent"))),
+                ("warning", warning()),
+            ],
+            &["height", "verified_header_height", "unverified_events"],
+        ),
+        "AppHashResponse": object(
+            &[("height", height()), ("block_hash", hash()), ("app_hash", hash()), ("warning", warning())],
+            &["height", "block_hash", "app_hash"],
+        ),
+        "IbcHeaderResponse": object(
+            &[
+                ("height", height()),
+                ("trusted_height", height()),
+                ("type_url", string()),
+                ("header", json!({ "type": "string", "format": "byte", "description": "Protobuf-encoded `ibc.lightclients.tendermint.v1.Header`" })),
+                ("warning", warning()),
+            ],
+            &["height", "trusted_height", "type_url", "header"],
+        ),
+        "HeadersRequest": object(&[("heights", array(json!({ "type": "integer", "minimum": 1 })))], &["heights"]),
+        "HeaderResponse": object(
+            &[
+                ("height", height()),
+                ("hash", hash()),
+                (
+                    "header",
+                    json!({
+                        "oneOf": [
+                            { "type": "object", "description": "CometBFT block header" },
+                            { "type": "string", "format": "byte", "description": "tendermint.types.Header, with `format=base64`" },
+                        ],
+                    }),
+                ),
+            ],
+            &["height", "hash", "header"],
+        ),
+        "HeaderError": object(&[("height", json!({ "type": "integer" })), ("error", string())], &["height", "error"]),
+        "HeadersResponse": object(
+            &[
+                ("headers", array(schema_ref("HeaderResponse"))),
+                ("errors", array(schema_ref("HeaderError"))),
+                ("warning", warning()),
+            ],
+            &["headers", "errors"],
+        ),
+        "ProveRequest": object(
+            &[
+                ("path", json!({ "type": "string", "example": "store/bank/key" })),
+                ("key", json!({ "type": "string", "format": "byte" })),
+                ("height", json!({ "type": "integer", "minimum": 1, "description": "Default: the height before the latest trusted block" })),
+            ],
+            &["path", "key"],
+        ),
+        "ProveResponse": object(
+            &[
+                ("height", height()),
+                ("verified_header_height", height()),
+                ("app_hash", hash()),
+                ("path", string()),
+                ("key", json!({ "type": "string", "format": "byte" })),
+                ("value", json!({ "type": "string", "format": "byte" })),
+                ("verified", json!({ "type": "boolean" })),
+                ("error", string()),
+                ("warning", warning()),
+            ],
+            &["height", "verified_header_height", "app_hash", "path", "key", "value", "verified"],
+        ),
+        "VerifyRequest": object(
+            &[
+                ("signed_header", json!({ "type": "object", "description": "CometBFT signed header" })),
+                ("validators", json!({ "type": "object", "description": "CometBFT validator set" })),
+                ("next_validators", json!({ "type": "object", "nullable": true, "description": "CometBFT validator set" })),
+            ],
+            &["signed_header", "validators"],
+        ),
+        "VerifyResponse": object(
+            &[
+                ("accepted", json!({ "type": "boolean" })),
+                ("height", height()),
+                ("hash", hash()),
+                ("trusted_height", json!({ "type": "string", "nullable": true })),
+                ("reason", string()),
+                ("warning", warning()),
+            ],
+            &["accepted", "height", "hash", "trusted_height"],
+        ),
+        "ValidatorEntry": object(
+            &[("address", hash()), ("pub_key", json!({ "type": "object" })), ("voting_power", power())],
+            &["address", "pub_key", "voting_power"],
+        ),
+        "ValidatorPowerChange": object(
+            &[
+                ("address", hash()),
+                ("from_voting_power", power()),
+                ("to_voting_power", power()),
+                ("delta", json!({ "type": "integer" })),
+            ],
+            &["address", "from_voting_power", "to_voting_power", "delta"],
+        ),
+        "ValidatorsDiffResponse": object(
+            &[
+                ("from", height()),
+                ("to", height()),
+                ("from_total_voting_power", power()),
+                ("to_total_voting_power", power()),
+                ("joined", array(schema_ref("ValidatorEntry"))),
+                ("left", array(schema_ref("ValidatorEntry"))),
+                ("power_changes", array(schema_ref("ValidatorPowerChange"))),
+                ("warning", warning()),
+            ],
+            &["from", "to", "from_total_voting_power", "to_total_voting_power", "joined", "left", "power_changes"],
+        ),
+        "DivergencePeer": object(&[("peer_id", string()), ("url", string())], &["peer_id", "url"]),
+        "DivergenceSummary": object(
+            &[
+                ("id", json!({ "type": "integer" })),
+                ("detected_at", time()),
+                ("primary", schema_ref("DivergencePeer")),
+                ("witness", schema_ref("DivergencePeer")),
+                ("conflicting_height", height()),
+            ],
+            &["id", "detected_at", "primary", "witness", "conflicting_height"],
+        ),
+        "EvidenceResponse": object(&[("divergences", array(schema_ref("DivergenceSummary")))], &["divergences"]),
+        "DivergenceRecord": object(
+            &[
+                ("detected_at", time()),
+                ("primary", schema_ref("DivergencePeer")),
+                ("witness", schema_ref("DivergencePeer")),
+                ("conflicting_height", height()),
+                ("primary_trace", array(light_block())),
+                ("witness_trace", array(light_block())),
+                ("challenging_block", light_block()),
+                ("against_primary", json!({ "type": "object", "description": "Light client attack evidence" })),
+                ("against_witness", json!({ "type": "object", "nullable": true, "description": "Light client attack evidence" })),
+            ],
+            &["detected_at", "primary", "witness", "conflicting_height", "primary_trace", "witness_trace", "challenging_block", "against_primary"],
+        ),
+    })
+}
+
+fn operation(summary: &str, parameters: &[Value], responses_list: &[(&str, Value)]) -> Value {
+    let mut operation = json!({ "summary": summary, "responses": responses(responses_list) });
+    if !parameters.is_empty() {
+        operation["parameters"] = Value::Array(parameters.to_vec());
+    }
+    operation
+}
+
+/// Responses of endpoints verifying a height on demand, besides the given success
+fn verified(success: (&'static str, Value)) -> Vec<(&'static str, Value)> {
+    vec![
+        success,
+        ("400", text("Invalid height or parameters")),
+        ("502", text("The height could not be verified against the primary")),
+        ("503", text("The providers are not initialized yet, or syncing is halted")),
+        ("504", text("The providers are busy syncing")),
+    ]
+}
+
+fn responses(responses: &[(&str, Value)]) -> Value {
+    let map: Map<String, Value> = responses
+        .iter()
+        .map(|(status, response)| (status.to_string(), response.clone()))
+        .collect();
+    Value::Object(map)
+}
+
+fn ok(schema: &str) -> Value {
+    json!({
+        "description": schema,
+        "content": { "application/json": { "schema": schema_ref(schema) } },
+    })
+}
+
+/// Light block as JSON or, with `format=proto`, as protobuf
+fn light_block_ok() -> Value {
+    json!({
+        "description": "LightBlockResponse",
+        "content": {
+            "application/json": { "schema": schema_ref("LightBlockResponse") },
+            "application/x-protobuf": {
+                "schema": { "type": "string", "format": "binary", "description": "tendermint.types.LightBlock" },
+            },
+        },
+    })
+}
+
+/// JSON-RPC 2.0 response, with errors in `error` rather than in the HTTP status
+fn rpc_ok() -> Value {
+    json!({
+        "description": "JSON-RPC 2.0 response with the `result` of the CometBFT RPC, or an `error`",
+        "content": { "application/json": { "schema": { "type": "object" } } },
+    })
+}
+
+fn text(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { "text/plain": { "schema": { "type": "string" } } },
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn object(properties: &[(&str, Value)], required: &[&str]) -> Value {
+    let properties: Map<String, Value> = properties
+        .iter()
+        .map(|(name, schema)| (name.to_string(), schema.clone()))
+        .collect();
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+fn path_param(name: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "path",
+        "required": true,
+        "description": description,
+        "schema": { "type": "integer", "minimum": 0 },
+    })
+}
+
+fn height_param() -> Value {
+    path_param("height", "Block height")
+}
+
+fn query_param(name: &str, description: &str, required: bool, schema: Value) -> Value {
+    json!({ "name": name, "in": "query", "required": required, "description": description, "schema": schema })
+}
+
+fn freshness_param() -> Value {
+    query_param(
+        "freshness",
+        "Maximum age of the trusted block before a sync is triggered (in seconds), overriding `--freshness-threshold`",
+        false,
+        json!({ "type": "integer", "minimum": 0 }),
+    )
+}
+
+fn timeout_param() -> Value {
+    query_param(
+        "timeout",
+        "How long to wait for the sync triggered by the request (in seconds), overriding `--api-timeout` up to `--max-api-timeout`",
+        false,
+        json!({ "type": "integer", "minimum": 0 }),
+    )
+}
+
+fn format_param(formats: &[&str]) -> Value {
+    query_param(
+        "format",
+        "`json`, canonical protobuf bytes (`proto`) or protobuf bytes base64-encoded in the JSON response (`base64`)",
+        false,
+        json!({ "type": "string", "enum": formats, "default": "json" }),
+    )
+}
+
+fn trace_param() -> Value {
+    query_param(
+        "trace",
+        "Include the light blocks traversed to verify the block",
+        false,
+        json!({ "type": "boolean" }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    /// Method and path of the routes registered with `.route(...)` in `source`, with the path
+    /// parameters written as in OpenAPI
+    fn routes(source: &str) -> BTreeSet<(String, String)> {
+        let mut routes = BTreeSet::new();
+        for route in source.split(".route(").skip(1) {
+            let route = route.trim_start();
+            let (path, rest) = route[1..].split_once('"').unwrap();
+            let path = path
+                .split('/')
+                .map(|segment| match segment.strip_prefix(':') {
+                    Some(name) => format!("{{{}}}", name),
+                    None => segment.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+
+            // The method routers of the route, e.g. `get(handler).patch(other_handler)`, up to
+            // the parenthesis closing `.route(`
+            let mut depth = 0;
+            let end = rest
+                .char_indices()
+                .find(|(_, c)| {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    depth < 0
+                })
+                .unwrap()
+                .0;
+            let methods = rest[..end].trim_start_matches([',', ' ', '\n']);
+            for method in methods.split(").").map(|call| call.split('(').next().unwrap().trim()) {
+                routes.insert((method.to_string(), path.clone()));
+            }
+        }
+        routes
+    }
+
+    #[test]
+    fn documents_every_route() {
+        let registered: BTreeSet<_> = routes(include_str!("main.rs"))
+            .into_iter()
+            .chain(routes(include_str!("rpc_server.rs")))
+            .filter(|(_, path)| !path.starts_with("/v1/admin/"))
+            .collect();
+        let documented: BTreeSet<_> = document()["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, operations)| {
+                operations.as_object().unwrap().keys().map(|method| (method.clone(), path.clone()))
+            })
+            .collect();
+        assert_eq!(registered, documented);
+    }
+}
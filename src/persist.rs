@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use tendermint::Time;
+use tendermint_light_client::types::{Hash, Height};
+
+use crate::state::AppState;
+
+/// Sync loop bookkeeping and trusted state that survive restarts, stored as JSON in the
+/// `--state-file`.
+///
+/// Times are stored as wall-clock timestamps since `Instant`s are meaningless
+/// across process boundaries.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SyncBookkeeping {
+    /// Time of the last successful sync
+    pub last_sync: Option<Time>,
+    /// Time at which the last sync attempt completed, successful or not
+    pub last_sync_attempt: Option<Time>,
+    /// Whether the last sync attempt succeeded
+    pub last_sync_success: bool,
+    /// Current backoff applied after failed syncs (in seconds)
+    pub backoff_secs: u64,
+    /// Number of syncs that failed since the last successful one
+    #[serde(default)]
+    pub sync_failures: u64,
+    /// Latest trusted block served by the API, from which syncing can resume
+    #[serde(default)]
+    pub trusted_block: Option<TrustedCheckpoint>,
+}
+
+/// Trusted block, enough to initialize the light client from it again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedCheckpoint {
+    pub chain_id: String,
+    pub height: Height,
+    pub hash: Hash,
+    /// Time of the header, from which the end of its trusting period follows
+    pub time: Time,
+}
+
+impl TrustedCheckpoint {
+    /// Whether the block can still be trusted, i.e. its header is less than `trusting_period`
+    /// old.
+    pub fn within_trusting_period(&self, trusting_period: Duration) -> bool {
+        (self.time + trusting_period).is_ok_and(|expiry| expiry > Time::now())
+    }
+}
+
+impl SyncBookkeeping {
+    pub fn from_state(state: &AppState) -> Self {
+        Self {
+            last_sync: state.last_sync.and_then(time_from_instant),
+            last_sync_attempt: state.last_sync_attempt.and_then(time_from_instant),
+            last_sync_success: state.last_sync_success,
+            backoff_secs: state.backoff_secs,
+            sync_failures: state.sync_failures,
+            // Until a block is verified, keep the one syncing resumed from
+            trusted_block: state
+                .light_block
+                .as_ref()
+                .map(|block| TrustedCheckpoint {
+                    chain_id: state.client.chain_id.clone(),
+                    height: block.height(),
+                    hash: block.signed_header.header.hash(),
+                    time: block.signed_header.header.time,
+                })
+                .or_else(|| state.resumed_from.clone()),
+        }
+    }
+
+    /// Restores the bookkeeping into the given state.
+    pub fn apply(&self, state: &mut AppState) {
+        state.last_sync = self.last_sync.and_then(instant_from_time);
+        state.last_sync_attempt = self.last_sync_attempt.and_then(instant_from_time);
+        state.last_sync_success = self.last_sync_success;
+        state.backoff_secs = self.backoff_secs;
+        state.sync_failures = self.sync_failures;
+    }
+
+    /// Loads the bookkeeping from `path`, returning `None` if the file does not exist yet.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .wrap_err_with(|| format!("failed to parse state file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).wrap_err_with(|| format!("failed to read state file {}", path.display())),
+        }
+    }
+
+    /// Saves the bookkeeping to `path`.
+    ///
+    /// The file is written next to its destination and then renamed over it,
+    /// so an interrupted write never leaves a truncated state file behind.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        let bytes = serde_json::to_vec_pretty(self)?;
+        fs::write(&tmp_path, bytes)
+            .wrap_err_with(|| format!("failed to write state file {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, path)
+            .wrap_err_with(|| format!("failed to replace state file {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Converts an `Instant` into the corresponding wall-clock time.
+pub fn time_from_instant(instant: Instant) -> Option<Time> {
+    Time::now().checked_sub(instant.elapsed())
+}
+
+/// Converts a wall-clock time into an `Instant`, or `None` if it predates the monotonic clock origin.
+fn instant_from_time(time: Time) -> Option<Instant> {
+    let elapsed = Time::now().duration_since(time).unwrap_or(Duration::ZERO);
+    Instant::now().checked_sub(elapsed)
+}
@@ -0,0 +1,714 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use futures::future::join_all;
+use reqwest::{header::HeaderMap, Client as ReqwestClient};
+use tendermint::{account, evidence::Evidence, validator};
+use tendermint_light_client::{
+    builder::LightClientBuilder,
+    components::{
+        clock::SystemClock,
+        io::{AtHeight, Io, IoError, IoErrorDetail, ProdIo},
+        scheduler,
+    },
+    errors::Error as LightClientError,
+    light_client::Options,
+    predicates::ProdPredicates,
+    state::State,
+    store::LightStore,
+    types::{Hash, Height, LightBlock},
+};
+use tendermint_light_client_detector::Provider;
+use tendermint_rpc::{client::CompatMode, Client, HttpClient, HttpClientUrl};
+use tracing::{debug, warn};
+
+use crate::{
+    cache::SharedValidatorSetCache,
+    cli::{find_override, ClientArgs, CompatModeSetting},
+    signatures::BatchVerifier,
+    stats::SharedProviderStats,
+    store::SharedLightStore,
+};
+
+pub fn fmt_peer_url<T: std::fmt::Display>(peer_id: T, url: &HttpClientUrl) -> String {
+    format!(
+        "peer: {}, url: {}",
+        peer_id,
+        tendermint_rpc::Url::from(url.clone())
+    )
+}
+
+/// A light client provider along with the RPC endpoint it talks to
+pub struct Peer {
+    pub provider: Provider,
+    pub endpoints: Endpoints,
+    pub url: HttpClientUrl,
+    /// Handle on the light store of the provider
+    pub store: SharedLightStore,
+    /// Chain the provider reported serving at its last status check, if not `--chain-id`, in
+    /// which case it is left out of the sync rounds
+    pub wrong_chain: Option<String>,
+    /// Heights of the verification traces of the blocks added with [`Peer::merge_verified`],
+    /// which the light client does not record
+    detached_traces: HashMap<Height, Vec<Height>>,
+    /// Height and hash of the conflicting primary blocks for which evidence was reported with the
+    /// provider as the witness, so that a divergence persisting over several rounds is only
+    /// reported once
+    pub reported_divergences: HashSet<(Height, Hash)>,
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&fmt_peer_url(self.provider.peer_id(), &self.url))
+    }
+}
+
+impl Peer {
+    /// RPC client of the endpoint the provider currently talks to
+    pub fn rpc_client(&self) -> &HttpClient {
+        self.endpoints.current()
+    }
+
+    /// Submits evidence to the provider, starting with the endpoint in use and moving on to the
+    /// next one when the submission fails, as light block fetches do.
+    pub async fn report_evidence(&self, evidence: Evidence) -> Result<Hash, tendermint_rpc::Error> {
+        let first = self.endpoints.index();
+        let count = self.endpoints.clients.len();
+        let mut attempt = 0;
+        loop {
+            let index = (first + attempt) % count;
+            let (url, client) = &self.endpoints.clients[index];
+            let result = client.broadcast_evidence(evidence.clone()).await;
+
+            attempt += 1;
+            match result {
+                Ok(response) => {
+                    self.endpoints.current.store(index, Ordering::Relaxed);
+                    return Ok(response.hash);
+                }
+                Err(e) if attempt < count => warn!(
+                    "failed to report evidence to endpoint {}, trying the next one: {}",
+                    url,
+                    error_message(&e)
+                ),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Verifies the highest block of the provider, off the async runtime
+    #[allow(clippy::result_large_err)]
+    pub fn verify_to_highest(&mut self) -> Result<LightBlock, LightClientError> {
+        offload(|| self.provider.verify_to_highest())
+    }
+
+    /// Verifies the block of the provider at `height`, off the async runtime
+    #[allow(clippy::result_large_err)]
+    pub fn verify_to_height(&mut self, height: Height) -> Result<LightBlock, LightClientError> {
+        offload(|| self.provider.verify_to_height(height))
+    }
+
+    /// Verifies the block of the provider at `height` like [`Self::verify_to_height`], but in a
+    /// copy of its light store, returned along with the block and its verification trace, so that
+    /// the blocks it verified can be cross-checked before they are added to the store.
+    #[allow(clippy::result_large_err)]
+    pub fn verify_to_height_detached(
+        &self,
+        height: Height,
+    ) -> Result<(LightBlock, Vec<LightBlock>, SharedLightStore), LightClientError> {
+        let store = self.store.detached_copy();
+        let mut state = State::new(store.clone());
+        let light_block = offload(|| self.provider.verify_to_height_with_state(height, &mut state))?;
+        Ok((light_block, state.get_trace(height), store))
+    }
+
+    /// Adds the blocks verified with [`Self::verify_to_height_detached`] to the light store, along
+    /// with the verification trace of the block at `height`.
+    pub fn merge_verified(&mut self, height: Height, trace: &[LightBlock], store: &SharedLightStore) {
+        self.store.merge_verified(store);
+        self.detached_traces
+            .insert(height, trace.iter().map(LightBlock::height).collect());
+    }
+
+    /// Light blocks traversed to verify the block at `height`, including those verified with
+    /// [`Self::verify_to_height_detached`], as long as they are in the light store.
+    pub fn get_trace(&self, height: Height) -> Vec<LightBlock> {
+        let trace = self.provider.get_trace(height);
+        if !trace.is_empty() {
+            return trace;
+        }
+        let heights = self.detached_traces.get(&height).map(Vec::as_slice).unwrap_or_default();
+        heights
+            .iter()
+            .filter_map(|height| self.store.get_trusted_or_verified(*height))
+            .collect()
+    }
+}
+
+/// Runs blocking light client work, i.e. fetching light blocks and verifying their signatures,
+/// without stalling the other tasks of the runtime worker thread, such as API handlers.
+///
+/// Requires the multi-threaded runtime.
+pub fn offload<T>(f: impl FnOnce() -> T) -> T {
+    tokio::task::block_in_place(f)
+}
+
+/// RPC clients of the endpoints behind a single logical provider, of which one is in use at a time.
+///
+/// Light block fetches move on to the next endpoint when the current one fails.
+#[derive(Clone)]
+pub struct Endpoints {
+    clients: Arc<[(String, HttpClient)]>,
+    current: Arc<AtomicUsize>,
+}
+
+impl Endpoints {
+    fn index(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    pub fn current(&self) -> &HttpClient {
+        &self.clients[self.index()].1
+    }
+}
+
+/// The primary and witnesses, once initialized by the sync task
+pub struct Providers {
+    pub primary: Peer,
+    pub witnesses: Vec<Peer>,
+}
+
+/// Providers shared between the sync task and the API, `None` until initialized.
+///
+/// The sync task holds the lock for a whole sync round, so the API never
+/// observes a light store in the middle of an update.
+pub type SharedProviders = Arc<tokio::sync::Mutex<Option<Providers>>>;
+
+/// Flattens a tendermint-rs error into a single line, e.g. `HTTP error: error sending request ...`
+///
+/// Their `Display` implementation renders the full error report, backtrace included,
+/// while the messages themselves are available through the source chain.
+pub fn error_message(e: &(impl std::error::Error + 'static)) -> String {
+    let mut messages = Vec::new();
+    let mut source = e.source();
+    while let Some(err) = source {
+        messages.push(err.to_string());
+        source = err.source();
+    }
+
+    if messages.is_empty() {
+        e.to_string()
+    } else {
+        messages.join(": ")
+    }
+}
+
+/// Settings for the RPC client of a single provider
+#[derive(Clone, Debug)]
+pub struct RpcConfig {
+    pub timeout: Duration,
+    pub compat_mode: CompatModeSetting,
+    pub proxy: Option<reqwest::Url>,
+    pub no_proxy: Option<String>,
+    /// Number of retries of a failed light block fetch
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each further retry
+    pub retry_backoff: Duration,
+    /// Maximum number of idle connections pooled per endpoint, none if `0`
+    pub pool_max_idle: usize,
+    pub pool_idle_timeout: Duration,
+    pub tcp_keepalive: Option<Duration>,
+    /// Further addresses serving the same provider
+    pub endpoints: Vec<HttpClientUrl>,
+    /// Whether to use every IP address the host of each address resolves to as an endpoint
+    pub resolve_all: bool,
+    pub user_agent: String,
+    /// Static headers sent with every request
+    pub headers: HeaderMap,
+    /// Fixture serving the light blocks instead of the RPC
+    #[cfg(feature = "mock-provider")]
+    pub mock_fixture: Option<std::path::PathBuf>,
+}
+
+impl RpcConfig {
+    pub fn for_url(args: &ClientArgs, url: &HttpClientUrl) -> Self {
+        let timeout = find_override(&args.rpc_timeout_override, url)
+            .copied()
+            .unwrap_or(args.rpc_timeout);
+
+        // Overrides replace the headers of the same name, hence `insert` rather than `collect`
+        let mut headers = HeaderMap::new();
+        let overrides = args.rpc_header_override.iter().filter(|o| &o.url == url);
+        for header in args.rpc_header.iter().chain(overrides.map(|o| &o.value)) {
+            headers.insert(header.name.clone(), header.value.clone());
+        }
+
+        Self {
+            timeout: Duration::from_secs(timeout),
+            compat_mode: find_override(&args.compat_mode_override, url)
+                .copied()
+                .unwrap_or(args.compat_mode),
+            proxy: args.proxy.clone(),
+            no_proxy: args.no_proxy.clone(),
+            retries: find_override(&args.rpc_retries_override, url)
+                .copied()
+                .unwrap_or(args.rpc_retries),
+            retry_backoff: Duration::from_millis(args.rpc_retry_backoff),
+            pool_max_idle: args.rpc_pool_max_idle,
+            pool_idle_timeout: Duration::from_secs(args.rpc_pool_idle_timeout),
+            tcp_keepalive: args.rpc_tcp_keepalive.map(Duration::from_secs),
+            endpoints: if url == &args.primary {
+                args.primary_endpoints.0.clone()
+            } else {
+                Vec::new()
+            },
+            resolve_all: url == &args.primary && args.primary_resolve_all,
+            user_agent: args.user_agent.clone(),
+            headers,
+            #[cfg(feature = "mock-provider")]
+            mock_fixture: find_override(&args.mock_fixture_override, url)
+                .or(args.mock_fixture.as_ref())
+                .cloned(),
+        }
+    }
+}
+
+/// [`Io`] component that records the latency and outcome of each light block fetch.
+///
+/// A fetch failing on the current endpoint is retried on the other endpoints, in turn, and the
+/// first one to succeed becomes the current endpoint. If all of them fail with a transient error,
+/// the fetch is retried up to `retries` times with exponential backoff.
+struct MeteredIo {
+    inner: Vec<ProdIo>,
+    endpoints: Endpoints,
+    validator_sets: SharedValidatorSetCache,
+    stats: SharedProviderStats,
+    retries: u32,
+    retry_backoff: Duration,
+}
+
+impl Io for MeteredIo {
+    fn fetch_light_block(&self, height: AtHeight) -> Result<LightBlock, IoError> {
+        // `AtHeight` is neither `Clone` nor `Copy`, so it is rebuilt for every attempt
+        let height = match height {
+            AtHeight::At(height) => Some(height),
+            AtHeight::Highest => None,
+        };
+
+        let mut backoff = self.retry_backoff;
+        let mut retry = 0;
+        loop {
+            match self.fetch_from_endpoints(height) {
+                Err(e) if retry < self.retries && is_transient(&e) => {
+                    retry += 1;
+                    warn!(
+                        "failed to fetch light block, retrying in {} ms ({}/{}): {}",
+                        backoff.as_millis(),
+                        retry,
+                        self.retries,
+                        error_message(&e)
+                    );
+                    // Light block fetches block the calling thread anyway
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl MeteredIo {
+    fn fetch_from_endpoints(&self, height: Option<Height>) -> Result<LightBlock, IoError> {
+        let first = self.endpoints.index();
+        let mut attempt = 0;
+        loop {
+            let index = (first + attempt) % self.inner.len();
+            let start = Instant::now();
+            let at = height.map_or(AtHeight::Highest, AtHeight::At);
+            let result = self.fetch_light_block_from(&self.inner[index], at);
+            if let Ok(mut stats) = self.stats.lock() {
+                let outcome = result.as_ref().map(|_| ()).map_err(error_message);
+                stats.record(start.elapsed(), outcome);
+            }
+
+            attempt += 1;
+            match result {
+                Ok(block) => {
+                    self.endpoints.current.store(index, Ordering::Relaxed);
+                    return Ok(block);
+                }
+                Err(e) if attempt < self.inner.len() => warn!(
+                    "failed to fetch light block from endpoint {}, trying the next one: {}",
+                    self.endpoints.clients[index].0,
+                    error_message(&e)
+                ),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Fetches a light block from `io` as [`ProdIo`] does, taking its validator sets from the
+    /// cache if they were fetched before.
+    fn fetch_light_block_from(&self, io: &ProdIo, height: AtHeight) -> Result<LightBlock, IoError> {
+        let signed_header = io.fetch_signed_header(height)?;
+        let header = &signed_header.header;
+        let validators = self.fetch_validator_set(
+            io,
+            header.height,
+            header.validators_hash,
+            Some(header.proposer_address),
+        )?;
+        let next_validators = self.fetch_validator_set(
+            io,
+            header.height.increment(),
+            header.next_validators_hash,
+            None,
+        )?;
+        Ok(LightBlock::new(signed_header, validators, next_validators, io.peer_id()))
+    }
+
+    /// Validator set at `height`, which the header being fetched says has `hash`.
+    ///
+    /// Sets whose hash differs are not cached, and are left to fail verification.
+    fn fetch_validator_set(
+        &self,
+        io: &ProdIo,
+        height: Height,
+        hash: Hash,
+        proposer: Option<account::Id>,
+    ) -> Result<validator::Set, IoError> {
+        if let Some(validators) = self.validator_sets.get(height, hash) {
+            return match proposer {
+                Some(proposer) => validator::Set::with_proposer(validators, proposer)
+                    .map_err(IoError::invalid_validator_set),
+                None => Ok(validator::Set::without_proposer(validators)),
+            };
+        }
+
+        let validator_set = io.fetch_validator_set(AtHeight::At(height), proposer)?;
+        if validator_set.hash() == hash {
+            self.validator_sets
+                .insert(height, hash, validator_set.validators().clone());
+        }
+        Ok(validator_set)
+    }
+}
+
+/// Whether a fetch failed in a way that may not happen again, as opposed to e.g. a height that is
+/// not there yet
+fn is_transient(e: &IoError) -> bool {
+    matches!(e.detail(), IoErrorDetail::Rpc(_) | IoErrorDetail::Timeout(_))
+}
+
+/// Builds the RPC client of a single endpoint, connecting to `addr` instead of resolving the host
+/// of `url` if set.
+pub fn build_rpc_client(
+    url: &HttpClientUrl,
+    addr: Option<SocketAddr>,
+    rpc_config: &RpcConfig,
+) -> Result<HttpClient> {
+    // Build a custom reqwest client, with connection pooling disabled unless configured.
+    let mut reqwest_builder = ReqwestClient::builder()
+        .pool_max_idle_per_host(rpc_config.pool_max_idle)
+        .pool_idle_timeout(rpc_config.pool_idle_timeout)
+        .tcp_keepalive(rpc_config.tcp_keepalive)
+        .timeout(rpc_config.timeout)
+        .user_agent(&rpc_config.user_agent)
+        // After the User-Agent, so that a `User-Agent` header replaces it
+        .default_headers(rpc_config.headers.clone());
+
+    if let Some(proxy_url) = &rpc_config.proxy {
+        let proxy = reqwest::Proxy::all(proxy_url.clone())?
+            .no_proxy(rpc_config.no_proxy.as_deref().and_then(reqwest::NoProxy::from_string));
+        reqwest_builder = reqwest_builder.proxy(proxy);
+    }
+
+    if let Some(addr) = addr {
+        let url = reqwest::Url::parse(&tendermint_rpc::Url::from(url.clone()).to_string())?;
+        if let Some(host) = url.host_str() {
+            reqwest_builder = reqwest_builder.resolve(host, addr);
+        }
+    }
+
+    let custom_reqwest_client = reqwest_builder.build()?;
+
+    // Build the tendermint HttpClient, passing in our custom reqwest client.
+    // The `/status` format is the same for all dialects, so any mode works until detection.
+    HttpClient::builder(url.clone())
+        .compat_mode(CompatMode::V0_37)
+        .client(custom_reqwest_client)
+        .build()
+        .map_err(|e| eyre!(error_message(&e)))
+}
+
+/// Endpoints of a provider: its address and the further `rpc_config.endpoints`, each expanded to
+/// all the IP addresses its host resolves to with `rpc_config.resolve_all`.
+async fn resolve_endpoints(
+    rpc_addr: &HttpClientUrl,
+    rpc_config: &RpcConfig,
+) -> Result<Vec<(String, HttpClient)>> {
+    let mut clients = Vec::new();
+    for url in std::iter::once(rpc_addr).chain(&rpc_config.endpoints) {
+        let display = tendermint_rpc::Url::from(url.clone()).to_string();
+        if !rpc_config.resolve_all {
+            clients.push((display, build_rpc_client(url, None, rpc_config)?));
+            continue;
+        }
+
+        let parsed = reqwest::Url::parse(&display)?;
+        let host = parsed.host_str().unwrap_or_default();
+        let port = parsed.port_or_known_default().unwrap_or(80);
+        let addrs = tokio::net::lookup_host((host, port))
+            .await
+            .wrap_err_with(|| format!("failed to resolve {}", display))?;
+        for addr in addrs {
+            let client = build_rpc_client(url, Some(addr), rpc_config)?;
+            clients.push((format!("{} ({})", display, addr), client));
+        }
+    }
+    Ok(clients)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn make_provider(
+    chain_id: &str,
+    rpc_addr: HttpClientUrl,
+    trusted_height: Height,
+    trusted_hash: Hash,
+    options: Options,
+    rpc_config: RpcConfig,
+    validator_sets: SharedValidatorSetCache,
+    stats: SharedProviderStats,
+) -> Result<Peer> {
+    #[cfg(feature = "mock-provider")]
+    if let Some(fixture) = &rpc_config.mock_fixture {
+        let io = crate::mock::FixtureIo::load(fixture, chain_id, &rpc_addr, stats)?;
+        warn!(
+            "Serving the light blocks of {} from fixture {}, not from the chain",
+            fmt_peer_url(io.peer_id(), &rpc_addr),
+            fixture.display()
+        );
+
+        // Still needed to report evidence, which fails unless something listens there
+        let name = tendermint_rpc::Url::from(rpc_addr.clone()).to_string();
+        let endpoints = Endpoints {
+            current: Arc::new(AtomicUsize::new(0)),
+            clients: vec![(name, build_rpc_client(&rpc_addr, None, &rpc_config)?)].into(),
+        };
+        let node_id = io.peer_id();
+        return build_peer(
+            chain_id,
+            rpc_addr,
+            node_id,
+            Box::new(io),
+            trusted_height,
+            trusted_hash,
+            options,
+            endpoints,
+        );
+    }
+
+    let mut clients = resolve_endpoints(&rpc_addr, &rpc_config).await?;
+    if clients.len() > 1 {
+        debug!(
+            "Using {} endpoints for {}: {}",
+            clients.len(),
+            tendermint_rpc::Url::from(rpc_addr.clone()),
+            clients.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", ")
+        );
+    }
+
+    // Start with the first endpoint that answers
+    let mut status = Err(eyre!("no endpoint for {}", tendermint_rpc::Url::from(rpc_addr.clone())));
+    let mut current = 0;
+    for (index, (name, rpc_client)) in clients.iter().enumerate() {
+        let start = Instant::now();
+        let result = rpc_client.status().await;
+        if let Ok(mut stats) = stats.lock() {
+            let outcome = result.as_ref().map(|_| ()).map_err(error_message);
+            stats.record(start.elapsed(), outcome);
+        }
+
+        match result {
+            Ok(result) => {
+                status = Ok(result);
+                current = index;
+                break;
+            }
+            Err(e) => {
+                if clients.len() > 1 {
+                    warn!("failed to fetch the status of endpoint {}: {}", name, error_message(&e));
+                }
+                status = Err(eyre!(error_message(&e)));
+            }
+        }
+    }
+
+    let node_info = status?.node_info;
+    if node_info.network.as_str() != chain_id {
+        if let Ok(mut stats) = stats.lock() {
+            stats.wrong_chain = Some(node_info.network.to_string());
+        }
+        return Err(eyre!(
+            "{} serves chain {}, not {}",
+            fmt_peer_url(node_info.id, &rpc_addr),
+            node_info.network,
+            chain_id
+        ));
+    }
+    let compat_mode = match rpc_config.compat_mode {
+        CompatModeSetting::Fixed(mode) => mode,
+        CompatModeSetting::Auto => detect_compat_mode(&node_info.version, &rpc_addr),
+    };
+    debug!(
+        "Using RPC compat mode {} ({})",
+        compat_mode,
+        fmt_peer_url(node_info.id, &rpc_addr)
+    );
+    for (_, rpc_client) in &mut clients {
+        rpc_client.set_compat_mode(compat_mode);
+    }
+
+    let node_id = node_info.id;
+    if let Ok(mut stats) = stats.lock() {
+        stats.peer_id = Some(node_id);
+        stats.wrong_chain = None;
+    }
+
+    // All endpoints are treated as the same provider, identified by the node that answered first
+    let endpoints = Endpoints {
+        current: Arc::new(AtomicUsize::new(current)),
+        clients: clients.into(),
+    };
+    let io = MeteredIo {
+        inner: endpoints
+            .clients
+            .iter()
+            .map(|(_, rpc_client)| ProdIo::new(node_id, rpc_client.clone(), Some(rpc_config.timeout)))
+            .collect(),
+        endpoints: endpoints.clone(),
+        validator_sets,
+        stats,
+        retries: rpc_config.retries,
+        retry_backoff: rpc_config.retry_backoff,
+    };
+
+    build_peer(
+        chain_id,
+        rpc_addr,
+        node_id,
+        Box::new(io),
+        trusted_height,
+        trusted_hash,
+        options,
+        endpoints,
+    )
+}
+
+/// Builds the light client of a provider fetching its light blocks through `io`, trusting the
+/// block at `trusted_height` if it has the `trusted_hash`.
+#[allow(clippy::too_many_arguments)]
+fn build_peer(
+    chain_id: &str,
+    rpc_addr: HttpClientUrl,
+    node_id: tendermint::node::Id,
+    io: Box<dyn Io>,
+    trusted_height: Height,
+    trusted_hash: Hash,
+    options: Options,
+    endpoints: Endpoints,
+) -> Result<Peer> {
+    let store = SharedLightStore::new();
+    let instance = LightClientBuilder::custom(
+        node_id,
+        options,
+        Box::new(store.clone()),
+        io,
+        Box::new(SystemClock),
+        Box::<BatchVerifier>::default(),
+        Box::new(scheduler::basic_bisecting_schedule),
+        Box::new(ProdPredicates),
+    )
+    .trust_primary_at(trusted_height, trusted_hash)
+    .map_err(|e| eyre!(error_message(&e)))?
+    .build();
+
+    Ok(Peer {
+        // The client of the provider is only used to report evidence, see `Peer::report_evidence`
+        provider: Provider::new(chain_id.to_string(), instance, endpoints.current().clone()),
+        endpoints,
+        url: rpc_addr,
+        store,
+        wrong_chain: None,
+        detached_traces: HashMap::new(),
+        reported_divergences: HashSet::new(),
+    })
+}
+
+/// Initializes the primary from the trusted checkpoint, then the witnesses from the
+/// primary's initial trusted block.
+pub async fn make_providers(
+    args: &ClientArgs,
+    validator_sets: &SharedValidatorSetCache,
+    primary_stats: SharedProviderStats,
+    witness_stats: &[SharedProviderStats],
+) -> Result<Providers> {
+    let options = args.options();
+    let (trusted_height, trusted_hash) = args.trust_root()?;
+
+    let primary = make_provider(
+        &args.chain_id,
+        args.primary.clone(),
+        trusted_height,
+        trusted_hash,
+        options,
+        RpcConfig::for_url(args, &args.primary),
+        validator_sets.clone(),
+        primary_stats,
+    )
+    .await
+    .wrap_err("failed to initialize primary provider")?;
+
+    let trusted_block = primary.provider.latest_trusted().ok_or_else(|| {
+        eyre!("failed to get initial trusted block from primary ({})", primary)
+    })?;
+
+    let witnesses = join_all(args.witnesses.0.iter().zip(witness_stats).map(|(addr, stats)| {
+        make_provider(
+            &args.chain_id,
+            addr.clone(),
+            trusted_block.height(),
+            trusted_block.signed_header.header.hash(),
+            options,
+            RpcConfig::for_url(args, addr),
+            validator_sets.clone(),
+            stats.clone(),
+        )
+    }))
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>>>()
+    .wrap_err("failed to initialize one or more witnesses")?;
+
+    Ok(Providers { primary, witnesses })
+}
+
+pub fn detect_compat_mode(version: &tendermint::Version, rpc_addr: &HttpClientUrl) -> CompatMode {
+    CompatMode::from_version(version.clone()).unwrap_or_else(|e| {
+        let fallback = CompatMode::latest();
+        warn!(
+            "failed to detect RPC compat mode from node version {} (url: {}), falling back to {}: {}",
+            version,
+            tendermint_rpc::Url::from(rpc_addr.clone()),
+            fallback,
+            e
+        );
+        fallback
+    })
+}
@@ -0,0 +1,199 @@
+use prost::Message;
+use serde::Serialize;
+use tendermint::{
+    abci::{types::ExecTxResult, Event},
+    consensus,
+    crypto::default::Sha256,
+    merkle, serializers, validator, Hash,
+};
+use tendermint_proto::v0_38::abci::ExecTxResult as RawExecTxResult;
+use tendermint_rpc::endpoint::block_results;
+
+/// Computes the `last_results_hash` committed by the header following the given results.
+///
+/// Only the deterministic fields of each transaction result (code, data, gas wanted
+/// and gas used) are covered by the hash, logs and events are not.
+pub fn last_results_hash(txs_results: &[ExecTxResult]) -> Hash {
+    let leaves: Vec<Vec<u8>> = txs_results
+        .iter()
+        .map(|result| {
+            RawExecTxResult {
+                code: result.code.value(),
+                data: result.data.clone(),
+                gas_wanted: result.gas_wanted,
+                gas_used: result.gas_used,
+                ..Default::default()
+            }
+            .encode_to_vec()
+        })
+        .collect();
+
+    Hash::Sha256(merkle::simple_hash_from_byte_vectors::<Sha256>(&leaves))
+}
+
+/// Fields of a transaction result covered by `last_results_hash`
+#[derive(Debug, Serialize, Clone)]
+pub struct TxResult {
+    pub code: u32,
+    #[serde(with = "serializers::bytes::base64string")]
+    pub data: Vec<u8>,
+    #[serde(with = "serializers::from_str")]
+    pub gas_wanted: i64,
+    #[serde(with = "serializers::from_str")]
+    pub gas_used: i64,
+}
+
+/// Fields of a transaction result not covered by `last_results_hash`
+#[derive(Debug, Serialize, Clone)]
+pub struct UnverifiedTxResult {
+    pub log: String,
+    pub info: String,
+    pub codespace: String,
+    pub events: Vec<Event>,
+}
+
+/// Parts of the block results that no header commits to, as returned by the primary
+#[derive(Debug, Serialize, Clone)]
+pub struct UnverifiedBlockResults {
+    /// In the order of the verified `txs_results`
+    pub txs_results: Vec<UnverifiedTxResult>,
+    pub finalize_block_events: Vec<Event>,
+    pub begin_block_events: Vec<Event>,
+    pub end_block_events: Vec<Event>,
+    pub validator_updates: Vec<validator::Update>,
+    pub consensus_param_updates: Option<consensus::Params>,
+}
+
+/// Where an event of the block results was emitted
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSource {
+    Tx,
+    FinalizeBlock,
+    BeginBlock,
+    EndBlock,
+}
+
+/// Event of the block results, with the transaction that emitted it
+#[derive(Debug, Serialize, Clone)]
+pub struct BlockEvent {
+    pub source: EventSource,
+    /// Index of the transaction in the block, for events emitted by transactions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_index: Option<usize>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Splits block results between the fields covered by `last_results_hash` and the others.
+pub fn split(block_results: block_results::Response) -> (Vec<TxResult>, UnverifiedBlockResults) {
+    let (verified, unverified) = block_results
+        .txs_results
+        .unwrap_or_default()
+        .into_iter()
+        .map(|result| {
+            (
+                TxResult {
+                    code: result.code.value(),
+                    data: result.data.to_vec(),
+                    gas_wanted: result.gas_wanted,
+                    gas_used: result.gas_used,
+                },
+                UnverifiedTxResult {
+                    log: result.log,
+                    info: result.info,
+                    codespace: result.codespace,
+                    events: result.events,
+                },
+            )
+        })
+        .unzip();
+
+    let unverified = UnverifiedBlockResults {
+        txs_results: unverified,
+        finalize_block_events: block_results.finalize_block_events,
+        begin_block_events: block_results.begin_block_events.unwrap_or_default(),
+        end_block_events: block_results.end_block_events.unwrap_or_default(),
+        validator_updates: block_results.validator_updates,
+        consensus_param_updates: block_results.consensus_param_updates,
+    };
+    (verified, unverified)
+}
+
+/// Events of the block results of type `kind`, or all of them, in the order they were emitted.
+pub fn events(results: UnverifiedBlockResults, kind: Option<&str>) -> Vec<BlockEvent> {
+    let block_events = |source, events: Vec<Event>| {
+        events.into_iter().map(move |event| BlockEvent {
+            source,
+            tx_index: None,
+            event,
+        })
+    };
+    let tx_events = results.txs_results.into_iter().enumerate().flat_map(|(index, result)| {
+        result.events.into_iter().map(move |event| BlockEvent {
+            source: EventSource::Tx,
+            tx_index: Some(index),
+            event,
+        })
+    });
+
+    block_events(EventSource::BeginBlock, results.begin_block_events)
+        .chain(tx_events)
+        .chain(block_events(EventSource::EndBlock, results.end_block_events))
+        .chain(block_events(EventSource::FinalizeBlock, results.finalize_block_events))
+        .filter(|event| kind.is_none_or(|kind| event.event.kind == kind))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_results() -> block_results::Response {
+        serde_json::from_value(serde_json::json!({
+            "height": "5",
+            "txs_results": [
+                {
+                    "code": 0,
+                    "data": null,
+                    "log": "ok",
+                    "gas_wanted": "100",
+                    "gas_used": "80",
+                    "events": [{ "type": "transfer", "attributes": [{ "key": "amount", "value": "1uatom", "index": true }] }],
+                },
+                { "code": 5, "data": null, "log": "insufficient funds", "gas_wanted": "100", "gas_used": "20", "events": [] },
+            ],
+            "finalize_block_events": [{ "type": "mint", "attributes": [] }, { "type": "transfer", "attributes": [] }],
+            "begin_block_events": null,
+            "end_block_events": null,
+            "validator_updates": null,
+            "consensus_param_updates": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn splits_committed_fields() {
+        let block_results = sample_results();
+        let hash = last_results_hash(block_results.txs_results.as_deref().unwrap());
+        let (verified, unverified) = split(block_results);
+
+        assert_eq!(verified.iter().map(|result| result.code).collect::<Vec<_>>(), [0, 5]);
+        assert_eq!(unverified.txs_results[1].log, "insufficient funds");
+        // The hash only depends on the committed fields
+        let mut changed = sample_results();
+        changed.txs_results.as_mut().unwrap()[0].events.clear();
+        changed.txs_results.as_mut().unwrap()[0].log.clear();
+        assert_eq!(last_results_hash(changed.txs_results.as_deref().unwrap()), hash);
+    }
+
+    #[test]
+    fn filters_events_by_type() {
+        let (_, unverified) = split(sample_results());
+        let events = events(unverified, Some("transfer"));
+
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].source, events[0].tx_index), (EventSource::Tx, Some(0)));
+        assert_eq!((events[1].source, events[1].tx_index), (EventSource::FinalizeBlock, None));
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use tendermint::crypto::{default::Sha256, Sha256 as _};
+use tendermint::{account, node, PublicKey};
+use tendermint_light_client::types::{Height, LightBlock};
+use tendermint_rpc::endpoint::{commit, validators};
+
+use crate::{
+    api::{fresh_light_block, verified_block, ApiError, ApiState},
+    attest::Attestor,
+    state::SyncState,
+};
+
+/// Maximum number of validators per page of `/validators`, as in CometBFT
+const MAX_PER_PAGE: usize = 100;
+const DEFAULT_PER_PAGE: usize = 30;
+
+/// State of the CometBFT RPC endpoints through which other light clients can use the daemon as
+/// their primary or witness
+#[derive(Clone)]
+pub struct RpcServerState {
+    api: ApiState,
+    /// Key under which the daemon presents itself, from which its node id follows
+    node_key: PublicKey,
+    listen_addr: SocketAddr,
+}
+
+impl RpcServerState {
+    /// Presents the daemon under its attestation key if it has one, so that downstream clients
+    /// can tell instances apart by node id, and otherwise under a key derived from the chain id
+    /// and listen address.
+    pub fn new(api: ApiState, attestor: Option<&Attestor>, chain_id: &str, listen_addr: SocketAddr) -> Self {
+        let key_bytes = match attestor {
+            Some(attestor) => attestor.public_key_bytes(),
+            None => {
+                let seed = Sha256::digest(format!("helios-light-client node key\n{}\n{}", chain_id, listen_addr));
+                ed25519_consensus::SigningKey::from(seed).verification_key().to_bytes()
+            }
+        };
+        Self {
+            api,
+            node_key: PublicKey::from_raw_ed25519(&key_bytes).expect("valid ed25519 public key"),
+            listen_addr,
+        }
+    }
+
+    /// Node id, the first 20 bytes of the SHA-256 hash of the node key as in CometBFT
+    fn node_id(&self) -> node::Id {
+        node::Id::from(self.node_key.ed25519().expect("ed25519 node key"))
+    }
+}
+
+/// JSON-RPC error, with the codes of the JSON-RPC 2.0 specification
+struct RpcError {
+    code: i32,
+    message: &'static str,
+    data: String,
+}
+
+impl RpcError {
+    fn method_not_found(method: &str) -> Self {
+        Self {
+            code: -32601,
+            message: "Method not found",
+            data: format!("method {} is not served, only status, commit and validators are", method),
+        }
+    }
+
+    fn invalid_params(data: String) -> Self {
+        Self {
+            code: -32602,
+            message: "Invalid params",
+            data,
+        }
+    }
+
+    fn internal(data: String) -> Self {
+        Self {
+            code: -32603,
+            message: "Internal error",
+            data,
+        }
+    }
+}
+
+impl From<ApiError> for RpcError {
+    fn from((_, error): ApiError) -> Self {
+        Self::internal(error)
+    }
+}
+
+/// Routes serving `status`, `commit` and `validators` under `/rpc`, as JSON-RPC requests posted
+/// to `/rpc` or as `GET /rpc/<method>?<params>` requests
+pub fn rpc_routes(state: RpcServerState) -> Router {
+    Router::new()
+        .route("/rpc", post(json_rpc_handler))
+        .route("/rpc/:method", get(uri_handler))
+        .with_state(state)
+}
+
+async fn json_rpc_handler(State(state): State<RpcServerState>, body: Bytes) -> Json<Value> {
+    let request: Value = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError {
+                code: -32700,
+                message: "Parse error",
+                data: e.to_string(),
+            };
+            return Json(envelope(Value::Null, Err(error)));
+        }
+    };
+
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let result = match request.get("method").and_then(Value::as_str) {
+        Some(method) => {
+            let params = request.get("params").cloned().unwrap_or(Value::Null);
+            call(&state, method, &params).await
+        }
+        None => Err(RpcError {
+            code: -32600,
+            message: "Invalid Request",
+            data: "missing method".to_string(),
+        }),
+    };
+    Json(envelope(id, result))
+}
+
+async fn uri_handler(
+    State(state): State<RpcServerState>,
+    Path(method): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Json<Value> {
+    // CometBFT accepts quoted string parameters, e.g. `?height="5"`
+    let params: serde_json::Map<_, _> = params
+        .into_iter()
+        .map(|(name, value)| (name, Value::String(value.trim_matches('"').to_string())))
+        .collect();
+    let result = call(&state, &method, &Value::Object(params)).await;
+    Json(envelope(json!(-1), result))
+}
+
+fn envelope(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": error.code, "message": error.message, "data": error.data },
+        }),
+    }
+}
+
+async fn call(state: &RpcServerState, method: &str, params: &Value) -> Result<Value, RpcError> {
+    {
+        let lock = state.api.state.read().await;
+        if lock.halted {
+            return Err(RpcError::internal("syncing is halted after a detected fork".to_string()));
+        }
+        if let Some(issue) = lock.clock_refusal() {
+            return Err(RpcError::internal(issue));
+        }
+    }
+
+    let result = match method {
+        "status" => status(state).await?,
+        "commit" => {
+            let light_block = light_block_at(state, height_param(params)?).await?;
+            serde_json::to_value(commit::Response {
+                signed_header: light_block.signed_header,
+                canonical: true,
+            })
+        }
+        .map_err(|e| RpcError::internal(e.to_string()))?,
+        "validators" => validators(state, params).await?,
+        other => return Err(RpcError::method_not_found(other)),
+    };
+    Ok(result)
+}
+
+/// Latest trusted block, synced first if older than the freshness threshold
+async fn latest(state: &RpcServerState) -> Result<LightBlock, RpcError> {
+    let (light_block, _, _) = fresh_light_block(&state.api, &HashMap::new())
+        .await
+        .map_err(|status| RpcError::internal(format!("no trusted block is available: {}", status)))?;
+    Ok(light_block)
+}
+
+/// Verified light block at `height`, or the latest one without a height.
+///
+/// Heights above the latest trusted block are rejected with the error of CometBFT, which light
+/// clients recognize, rather than verified on demand.
+async fn light_block_at(state: &RpcServerState, height: Option<Height>) -> Result<LightBlock, RpcError> {
+    let latest = latest(state).await?;
+    match height {
+        None => Ok(latest),
+        Some(height) if height > latest.height() => Err(height_too_high(height, latest.height())),
+        Some(height) if height == latest.height() => Ok(latest),
+        Some(height) => Ok(verified_block(&state.api, height, state.api.api_timeout().await).await?),
+    }
+}
+
+fn height_too_high(height: Height, latest_height: Height) -> RpcError {
+    RpcError::internal(format!(
+        "height {} must be less than or equal to the current blockchain height {}",
+        height, latest_height
+    ))
+}
+
+/// Validator set at a height, paginated as in CometBFT.
+///
+/// The validators of the height after the latest trusted block are its next validators, which its
+/// header commits to.
+async fn validators(state: &RpcServerState, params: &Value) -> Result<Value, RpcError> {
+    let latest = latest(state).await?;
+    let height = height_param(params)?.unwrap_or(latest.height());
+    let validators = if height == latest.height().increment() {
+        latest.next_validators
+    } else if height > latest.height() {
+        return Err(height_too_high(height, latest.height().increment()));
+    } else {
+        light_block_at(state, Some(height)).await?.validators
+    };
+
+    let validators = validators.validators();
+    let per_page = usize_param(params, "per_page")?
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+    let pages = validators.len().div_ceil(per_page).max(1);
+    let page = usize_param(params, "page")?.unwrap_or(1);
+    if page == 0 || page > pages {
+        return Err(RpcError::invalid_params(format!(
+            "page should be within [1, {}] range, given {}",
+            pages, page
+        )));
+    }
+
+    let start = (page - 1) * per_page;
+    let page_validators = validators.iter().skip(start).take(per_page).cloned().collect();
+    let response = validators::Response::new(height, page_validators, validators.len() as i32);
+    serde_json::to_value(response).map_err(|e| RpcError::internal(e.to_string()))
+}
+
+async fn status(state: &RpcServerState) -> Result<Value, RpcError> {
+    let latest = latest(state).await?;
+    let header = &latest.signed_header.header;
+    let (chain_id, catching_up) = {
+        let lock = state.api.state.read().await;
+        (lock.client.chain_id.clone(), lock.sync_state() != SyncState::Synced)
+    };
+
+    // Older blocks are verified on demand rather than kept, so the latest block is also the earliest
+    let block = json!({
+        "hash": header.hash(),
+        "app_hash": header.app_hash.to_string(),
+        "height": header.height,
+        "time": header.time,
+    });
+    Ok(json!({
+        "node_info": {
+            "protocol_version": {
+                "p2p": "8",
+                "block": header.version.block.to_string(),
+                "app": header.version.app.to_string(),
+            },
+            "id": state.node_id(),
+            "listen_addr": format!("tcp://{}", state.listen_addr),
+            "network": chain_id,
+            "version": "0.38.0",
+            "channels": "",
+            "moniker": format!("helios-light-client/{}", env!("CARGO_PKG_VERSION")),
+            "other": {
+                "tx_index": "off",
+                "rpc_address": format!("tcp://{}", state.listen_addr),
+            },
+        },
+        "sync_info": {
+            "latest_block_hash": block["hash"],
+            "latest_app_hash": block["app_hash"],
+            "latest_block_height": block["height"],
+            "latest_block_time": block["time"],
+            "earliest_block_hash": block["hash"],
+            "earliest_app_hash": block["app_hash"],
+            "earliest_block_height": block["height"],
+            "earliest_block_time": block["time"],
+            "catching_up": catching_up,
+        },
+        "validator_info": {
+            "address": account::Id::from(state.node_key),
+            "pub_key": state.node_key,
+            "voting_power": "0",
+        },
+    }))
+}
+
+fn height_param(params: &Value) -> Result<Option<Height>, RpcError> {
+    let Some(height) = u64_param(params, "height")? else {
+        return Ok(None);
+    };
+    Height::try_from(height)
+        .map(Some)
+        .map_err(|e| RpcError::invalid_params(format!("invalid height: {}", e)))
+}
+
+fn usize_param(params: &Value, name: &str) -> Result<Option<usize>, RpcError> {
+    Ok(u64_param(params, name)?.map(|value| value as usize))
+}
+
+/// Integer parameter, passed as a number or a string as CometBFT clients do
+fn u64_param(params: &Value, name: &str) -> Result<Option<u64>, RpcError> {
+    let parsed = match params.get(name) {
+        None | Some(Value::Null) => return Ok(None),
+        Some(Value::String(value)) if value.is_empty() => return Ok(None),
+        Some(Value::String(value)) => value.parse().ok(),
+        Some(Value::Number(value)) => value.as_u64(),
+        Some(_) => None,
+    };
+    parsed
+        .map(Some)
+        .ok_or_else(|| RpcError::invalid_params(format!("invalid {} parameter", name)))
+}
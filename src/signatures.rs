@@ -0,0 +1,234 @@
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
+use std::thread;
+
+use ed25519_consensus::{batch, Signature, VerificationKeyBytes};
+use tendermint::{
+    block::CommitSig,
+    trust_threshold::TrustThreshold as _,
+    vote::{SignedVote, Type, ValidatorIndex, Vote},
+};
+use tendermint_light_client::verifier::{
+    errors::VerificationError,
+    operations::{ProdCommitValidator, ProdVotingPowerCalculator, VotingPowerCalculator, VotingPowerTally},
+    predicates::ProdPredicates,
+    types::{SignedHeader, TrustThreshold, ValidatorSet},
+    PredicateVerifier,
+};
+
+/// Verifier of the light clients, checking commit signatures in batches
+pub type BatchVerifier = PredicateVerifier<ProdPredicates, BatchVotingPowerCalculator, ProdCommitValidator>;
+
+/// Commits with more signatures to check are split into batches verified on separate threads
+const BATCH_SIZE: usize = 64;
+
+/// [`VotingPowerCalculator`] batch-verifying the ed25519 signatures of a commit, which is
+/// noticeably faster than checking them one by one on chains with large validator sets.
+///
+/// As with [`ProdVotingPowerCalculator`], validators are tallied in the order of their set until
+/// the trust threshold is reached, and only their signatures are checked. Commits it cannot batch,
+/// e.g. with secp256k1 validators, or whose batch fails are handed over to
+/// [`ProdVotingPowerCalculator`], so that verdicts and errors are the same.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchVotingPowerCalculator;
+
+impl VotingPowerCalculator for BatchVotingPowerCalculator {
+    fn voting_power_in(
+        &self,
+        signed_header: &SignedHeader,
+        validator_set: &ValidatorSet,
+        trust_threshold: TrustThreshold,
+    ) -> Result<VotingPowerTally, VerificationError> {
+        match self.batch_tally(signed_header, &[(validator_set, trust_threshold)]) {
+            Some(tallies) => Ok(tallies[0]),
+            None => ProdVotingPowerCalculator::default().voting_power_in(
+                signed_header,
+                validator_set,
+                trust_threshold,
+            ),
+        }
+    }
+
+    fn voting_power_in_sets(
+        &self,
+        signed_header: &SignedHeader,
+        first_set: (&ValidatorSet, TrustThreshold),
+        second_set: (&ValidatorSet, TrustThreshold),
+    ) -> Result<(VotingPowerTally, VotingPowerTally), VerificationError> {
+        match self.batch_tally(signed_header, &[first_set, second_set]) {
+            Some(tallies) => Ok((tallies[0], tallies[1])),
+            None => ProdVotingPowerCalculator::default().voting_power_in_sets(
+                signed_header,
+                first_set,
+                second_set,
+            ),
+        }
+    }
+}
+
+impl BatchVotingPowerCalculator {
+    /// Tallies the voting power of each set in the commit, or `None` if the commit cannot be
+    /// batch-verified or its signatures are not all valid.
+    fn batch_tally(
+        &self,
+        signed_header: &SignedHeader,
+        sets: &[(&ValidatorSet, TrustThreshold)],
+    ) -> Option<Vec<VotingPowerTally>> {
+        let votes = commit_votes(signed_header)?;
+
+        let mut items = Vec::new();
+        let mut queued = HashSet::new();
+        let mut tallies = Vec::with_capacity(sets.len());
+        for &(validator_set, trust_threshold) in sets {
+            let mut tally = VotingPowerTally {
+                total: self.total_power_of(validator_set),
+                tallied: 0,
+                trust_threshold,
+            };
+            let mut seen = HashSet::new();
+            for validator in validator_set.validators() {
+                let Some(vote) = votes.get(&validator.address) else {
+                    continue;
+                };
+                if !seen.insert(validator.address) {
+                    return None;
+                }
+
+                // The same address may have different keys in the two sets
+                let key: [u8; 32] = validator.pub_key.ed25519()?.as_bytes().try_into().ok()?;
+                if queued.insert((validator.address, key)) {
+                    let signature: [u8; 64] = vote.signature().as_bytes().try_into().ok()?;
+                    let item = batch::Item::from((
+                        VerificationKeyBytes::from(key),
+                        Signature::from(signature),
+                        &vote.sign_bytes(),
+                    ));
+                    items.push(item);
+                }
+
+                tally.tallied += validator.power();
+                if trust_threshold.is_enough_power(tally.tallied, tally.total) {
+                    break;
+                }
+            }
+            tallies.push(tally);
+        }
+
+        verify_batches(items).then_some(tallies)
+    }
+}
+
+/// Signed votes of the commit for its block, by validator address, or `None` if a validator
+/// signed twice or a signature is missing.
+fn commit_votes(signed_header: &SignedHeader) -> Option<HashMap<tendermint::account::Id, SignedVote>> {
+    let commit = &signed_header.commit;
+    let mut votes = HashMap::new();
+    for (index, commit_sig) in commit.signatures.iter().enumerate() {
+        let CommitSig::BlockIdFlagCommit { validator_address, timestamp, signature } = commit_sig else {
+            continue;
+        };
+        let vote = Vote {
+            vote_type: Type::Precommit,
+            height: commit.height,
+            round: commit.round,
+            block_id: Some(commit.block_id),
+            timestamp: Some(*timestamp),
+            validator_address: *validator_address,
+            validator_index: ValidatorIndex::try_from(index).ok()?,
+            signature: signature.clone(),
+            extension: Default::default(),
+            extension_signature: None,
+        };
+        let signed_vote = SignedVote::from_vote(vote, signed_header.header.chain_id.clone())?;
+        if votes.insert(*validator_address, signed_vote).is_some() {
+            return None;
+        }
+    }
+    Some(votes)
+}
+
+/// Whether all signatures are valid, checking batches of [`BATCH_SIZE`] or more on as many threads
+/// as there are cores.
+fn verify_batches(items: Vec<batch::Item>) -> bool {
+    if items.len() <= BATCH_SIZE {
+        return verify_batch(&items);
+    }
+
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let chunk_size = items.len().div_ceil(threads).max(BATCH_SIZE);
+    thread::scope(|scope| {
+        let batches: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || verify_batch(chunk)))
+            .collect();
+        batches.into_iter().all(|batch| batch.join().unwrap_or(false))
+    })
+}
+
+fn verify_batch(items: &[batch::Item]) -> bool {
+    let mut verifier = batch::Verifier::new();
+    for item in items {
+        verifier.queue(item.clone());
+    }
+    verifier.verify(rand::thread_rng()).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tendermint::Signature as CommitSignature;
+    use tendermint_light_client::types::LightBlock;
+
+    use crate::testing::{light_block, signing_keys};
+
+    fn assert_same_tally(light_block: &LightBlock, trust_threshold: TrustThreshold) {
+        let (signed_header, validators) = (&light_block.signed_header, &light_block.validators);
+        let prod_calculator = ProdVotingPowerCalculator::default();
+        let batch = BatchVotingPowerCalculator.voting_power_in(signed_header, validators, trust_threshold);
+        let prod = prod_calculator.voting_power_in(signed_header, validators, trust_threshold);
+        assert_eq!(batch.unwrap(), prod.unwrap());
+
+        let first = (validators, trust_threshold);
+        let second = (&light_block.next_validators, TrustThreshold::TWO_THIRDS);
+        let batch = BatchVotingPowerCalculator.voting_power_in_sets(signed_header, first, second);
+        let prod = prod_calculator.voting_power_in_sets(signed_header, first, second);
+        assert_eq!(batch.unwrap(), prod.unwrap());
+    }
+
+    #[test]
+    fn agrees_with_prod_on_valid_commit() {
+        let light_block = light_block(10, &signing_keys(4));
+        assert!(BatchVotingPowerCalculator
+            .batch_tally(&light_block.signed_header, &[(&light_block.validators, TrustThreshold::ONE_THIRD)])
+            .is_some());
+        assert_same_tally(&light_block, TrustThreshold::ONE_THIRD);
+        assert_same_tally(&light_block, TrustThreshold::TWO_THIRDS);
+    }
+
+    #[test]
+    fn agrees_with_prod_on_several_batches() {
+        // Enough signatures to tally for two thirds to be split into batches
+        let light_block = light_block(10, &signing_keys(100));
+        assert_same_tally(&light_block, TrustThreshold::TWO_THIRDS);
+    }
+
+    #[test]
+    fn falls_back_to_prod_on_bad_signature() {
+        let mut light_block = light_block(10, &signing_keys(4));
+        let commit_sig = &mut light_block.signed_header.commit.signatures[0];
+        let CommitSig::BlockIdFlagCommit { signature, .. } = commit_sig else {
+            unreachable!("all validators signed");
+        };
+        let mut bytes = signature.as_ref().unwrap().as_bytes().to_vec();
+        bytes[0] ^= 1;
+        *signature = CommitSignature::new(bytes).unwrap();
+
+        let (signed_header, validators) = (&light_block.signed_header, &light_block.validators);
+        let threshold = TrustThreshold::ONE_THIRD;
+        assert!(BatchVotingPowerCalculator.batch_tally(signed_header, &[(validators, threshold)]).is_none());
+
+        let batch = BatchVotingPowerCalculator.voting_power_in(signed_header, validators, threshold);
+        let prod = ProdVotingPowerCalculator::default().voting_power_in(signed_header, validators, threshold);
+        assert_eq!(batch.unwrap_err().to_string(), prod.unwrap_err().to_string());
+    }
+}
@@ -1,38 +1,565 @@
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use serde::Serialize;
 use tendermint::Time;
-use tendermint_light_client::types::{Hash, Height, LightBlock};
+use tendermint_light_client::types::{Hash, Height, LightBlock, Status};
 use tokio::sync::RwLock;
 
+use crate::cli::{ClientArgs, DegradationPolicy};
+use crate::evidence::{DivergencePeer, DivergenceRecord};
+use crate::persist::TrustedCheckpoint;
+use crate::results::{BlockEvent, TxResult, UnverifiedBlockResults};
+use crate::stats::{ProviderHealth, ProviderRole, SharedProviderStats};
+
+/// Marker shown in logs and API responses when fork detection is disabled
+pub const UNSAFE_NO_DETECTOR_BANNER: &str = "UNSAFE: no fork detection";
+
 #[derive(Debug, Serialize, Clone)]
 pub struct StatusResponse {
     pub block_height: Height,
     pub block_hash: Hash,
     pub block_timestamp: Time,
+    /// Hex-encoded app hash of the block, committing to the state after the previous block
+    pub app_hash: String,
+    /// Whether syncing is halted after a detected fork, in which case the block is not refreshed
+    pub halted: bool,
+    /// Whether the block is served although the last successful sync is older than the freshness
+    /// threshold, e.g. while it is refreshed in the background
+    pub stale: bool,
+    pub state: SyncState,
+    /// Whether the last sync attempt succeeded
+    pub last_sync_success: bool,
+    /// Number of syncs that failed since the last successful one
+    pub consecutive_sync_failures: u64,
+    /// Time since the last successful sync (in seconds), if any
+    pub secs_since_last_success: Option<u64>,
+    /// Latest block height reported by the primary, once fetched
+    pub network_height: Option<Height>,
+    /// Number of blocks between the verified block and the network height
+    pub lag_blocks: Option<u64>,
+    /// Time between the verified block and the latest block reported by the primary (in seconds)
+    pub lag_secs: Option<u64>,
+    /// Outcome of the latest fork detection round against the witnesses, once one ran
+    pub witness_check: Option<WitnessSummary>,
+    /// Light blocks traversed to verify this block, included on request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace: Option<Vec<TraceStep>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<&'static str>,
+}
+
+/// Outcome of a fork detection round of a sync
+#[derive(Debug, Serialize, Clone, Copy)]
+pub struct WitnessSummary {
+    /// Number of witnesses against which detection ran
+    pub checked: usize,
+    pub agreed: usize,
+    pub diverged: usize,
+    /// Number of witnesses against which detection failed, e.g. unreachable ones
+    pub errored: usize,
+    pub checked_at: Time,
+}
+
+/// Latest measurement of the local clock against a reference time source, see [`crate::clock`]
+#[derive(Debug, Serialize, Clone, Copy)]
+pub struct ClockOffset {
+    /// Reference time minus the local time (in seconds), positive when the local clock is behind
+    pub offset_secs: f64,
+    pub source: ClockSource,
+    pub checked_at: Time,
+}
+
+/// Reference time source the local clock is checked against
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockSource {
+    /// Median offset of the `--ntp-server`s
+    Ntp,
+    /// Median latest block time reported by the providers, when no NTP server is configured
+    Providers,
+}
+
+/// Returned by `/v1/status` with `503 Service Unavailable` until a block is trusted
+#[derive(Debug, Serialize, Clone)]
+pub struct StatusUnavailableResponse {
+    pub state: SyncState,
+    /// Why the providers could not be initialized yet, if they could not, or why the block is
+    /// refused while the local clock is off
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Overall state of the sync loop
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncState {
+    /// The providers are not initialized yet, initialization is retried with backoff
+    Initializing,
+    /// The last sync succeeded
+    Synced,
+    /// The last sync failed, the trusted block may be outdated
+    Degraded,
+    /// Syncing is halted after a detected fork
+    Halted,
+}
+
+/// A light block traversed while verifying a target block, from the trusted block to the target
+#[derive(Debug, Serialize, Clone)]
+pub struct TraceStep {
+    pub height: Height,
+    pub hash: Hash,
+    pub time: Time,
+}
+
+impl From<&LightBlock> for TraceStep {
+    fn from(light_block: &LightBlock) -> Self {
+        Self {
+            height: light_block.height(),
+            hash: light_block.signed_header.header.hash(),
+            time: light_block.signed_header.header.time,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AttestationKeyResponse {
+    pub algorithm: &'static str,
+    /// Base64-encoded public key
+    pub public_key: String,
+}
+
+/// Light block, as JSON or as a base64-encoded `tendermint.types.LightBlock` with `?format=base64`
+#[derive(Debug, Serialize, Clone)]
+pub struct LightBlockResponse<B = LightBlock> {
+    pub light_block: B,
+    /// Light blocks traversed to verify this block, included on request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace: Option<Vec<TraceStep>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BlockResultsResponse {
+    pub height: Height,
+    /// Height of the verified header whose `last_results_hash` commits to these results
+    pub verified_header_height: Height,
+    pub last_results_hash: Hash,
+    /// Checked against `last_results_hash`
+    pub txs_results: Vec<TxResult>,
+    /// Events, logs and other fields returned by the primary that no header commits to
+    pub unverified: UnverifiedBlockResults,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BlockEventsResponse {
+    pub height: Height,
+    /// Height of the verified header whose `last_results_hash` commits to the transaction results
+    pub verified_header_height: Height,
+    /// Events as returned by the primary, which no header commits to
+    pub unverified_events: Vec<BlockEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AppHashResponse {
+    pub height: Height,
+    pub block_hash: Hash,
+    /// Hex-encoded app hash of the verified header, committing to the state after block `height - 1`
+    pub app_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ValidatorEntry {
+    pub address: tendermint::account::Id,
+    pub pub_key: tendermint::PublicKey,
+    pub voting_power: tendermint::vote::Power,
+}
+
+impl From<&tendermint::validator::Info> for ValidatorEntry {
+    fn from(info: &tendermint::validator::Info) -> Self {
+        Self {
+            address: info.address,
+            pub_key: info.pub_key,
+            voting_power: info.power,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ValidatorPowerChange {
+    pub address: tendermint::account::Id,
+    pub from_voting_power: tendermint::vote::Power,
+    pub to_voting_power: tendermint::vote::Power,
+    pub delta: i64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ValidatorsDiffResponse {
+    pub from: Height,
+    pub to: Height,
+    pub from_total_voting_power: tendermint::vote::Power,
+    pub to_total_voting_power: tendermint::vote::Power,
+    /// Validators in the set at `to` but not at `from`
+    pub joined: Vec<ValidatorEntry>,
+    /// Validators in the set at `from` but not at `to`
+    pub left: Vec<ValidatorEntry>,
+    /// Validators in both sets whose voting power changed
+    pub power_changes: Vec<ValidatorPowerChange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ProveResponse {
+    /// Height at which the store was queried
+    pub height: Height,
+    /// Height of the verified header whose `app_hash` commits to the store at `height`
+    pub verified_header_height: Height,
+    /// Hex-encoded app hash
+    pub app_hash: String,
+    pub path: String,
+    /// Base64-encoded key
+    pub key: String,
+    /// Base64-encoded value returned by the primary
+    pub value: String,
+    /// Whether the value was proven against the app hash
+    pub verified: bool,
+    /// Why the proof could not be verified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct VerifyResponse {
+    /// Whether the header verifies against the trusted state and trust options
+    pub accepted: bool,
+    pub height: Height,
+    pub hash: Hash,
+    /// Height of the trusted block the header was verified from
+    pub trusted_height: Option<Height>,
+    /// Why the header was rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct IbcHeaderResponse {
+    pub height: Height,
+    pub trusted_height: Height,
+    pub type_url: &'static str,
+    /// Base64-encoded `ibc.lightclients.tendermint.v1.Header`
+    pub header: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<&'static str>,
+}
+
+/// Verified header, as JSON or as a base64-encoded `tendermint.types.Header` with `?format=base64`
+#[derive(Debug, Serialize, Clone)]
+pub struct HeaderResponse<H = tendermint::block::Header> {
+    pub height: Height,
+    pub hash: Hash,
+    pub header: H,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HeaderError {
+    pub height: u64,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HeadersResponse<H = tendermint::block::Header> {
+    /// Verified headers, in ascending height order
+    pub headers: Vec<HeaderResponse<H>>,
+    /// Heights that could not be verified
+    pub errors: Vec<HeaderError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PeerResponse {
+    pub role: ProviderRole,
+    pub url: String,
+    pub peer_id: Option<tendermint::node::Id>,
+    pub health: ProviderHealth,
+    /// Chain the provider serves, if not `--chain-id`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wrong_chain: Option<String>,
+    pub last_contact: Option<Time>,
+    pub success_rate: Option<f64>,
+    pub mean_latency_ms: Option<u128>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PeersResponse {
+    pub peers: Vec<PeerResponse>,
+}
+
+/// Overview of a divergence, whose full record is served at `/v1/evidence/{id}`
+#[derive(Debug, Serialize, Clone)]
+pub struct DivergenceSummary {
+    pub id: usize,
+    pub detected_at: Time,
+    pub primary: DivergencePeer,
+    pub witness: DivergencePeer,
+    pub conflicting_height: Height,
+}
+
+impl DivergenceSummary {
+    pub fn new(id: usize, record: &DivergenceRecord) -> Self {
+        Self {
+            id,
+            detected_at: record.detected_at,
+            primary: record.primary.clone(),
+            witness: record.witness.clone(),
+            conflicting_height: record.conflicting_height,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct EvidenceResponse {
+    pub divergences: Vec<DivergenceSummary>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HealthResponse {
+    pub healthy: bool,
+    /// Latest block time reported by the providers minus the local time (in seconds)
+    pub clock_skew_secs: Option<f64>,
+    /// Latest measurement of the local clock against the reference time source, once checked
+    pub clock_offset: Option<ClockOffset>,
+    /// Problems making the daemon unhealthy
+    pub issues: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SnapshotEntry {
+    pub status: Status,
+    pub light_block: LightBlock,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SnapshotResponse {
+    pub chain_id: String,
+    /// Provider whose light store was dumped
+    pub peer: DivergencePeer,
+    /// Blocks in ascending height order
+    pub blocks: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DetectResponse {
+    /// Height of the latest trusted block, the target of the trace checked against the witnesses
+    pub height: Height,
+    pub hash: Hash,
+    pub primary: DivergencePeer,
+    /// Outcome of fork detection against each witness
+    pub witnesses: Vec<WitnessDetection>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct WitnessDetection {
+    pub peer: DivergencePeer,
+    pub agreement: Agreement,
+    /// Height of the first header on which the witness disagrees with the primary
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conflicting_height: Option<Height>,
+    /// Why detection could not be completed against the witness
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Agreement {
+    Agreed,
+    Diverged,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TrustOptionsResponse {
+    pub trust_threshold: String,
+    /// Trusting period, in seconds
+    pub trusting_period: u64,
+    /// Maximum clock drift, in seconds
+    pub max_clock_drift: u64,
+    /// Height of the trusted block the providers were initialized from
+    pub trusted_height: Option<Height>,
+}
+
+impl From<&ClientArgs> for TrustOptionsResponse {
+    fn from(client: &ClientArgs) -> Self {
+        Self {
+            trust_threshold: client.trust_threshold.to_string(),
+            trusting_period: client.trusting_period,
+            max_clock_drift: client.max_clock_drift,
+            trusted_height: client.trusted_height,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Clone)]
 pub struct RootResponse {
     pub name: &'static str,
     pub version: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<&'static str>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub freshness_threshold: Duration,
+    /// Whether a stale trusted state is served without waiting for its refresh
+    pub stale_while_revalidate: bool,
+    /// What is served while syncing fails
+    pub degradation_policy: DegradationPolicy,
     pub keep_warm_interval: Duration,
     pub halt_duration_on_fork: Duration,
     pub api_timeout: Duration,
+    /// Upper bound of the `?timeout` requested by API callers
+    pub max_api_timeout: Duration,
+    pub max_batch_size: usize,
+    pub unsafe_no_detector: bool,
+    /// Directory in which detected divergences are persisted, if any
+    pub evidence_dir: Option<PathBuf>,
+    /// Time source the local clock is checked against
+    pub clock_source: ClockSource,
+    /// Offset of the local clock beyond which it is deemed wrong (in seconds), if bounded
+    pub max_clock_offset: Option<f64>,
+    /// Whether verified data is refused while the local clock is deemed wrong
+    pub refuse_on_clock_offset: bool,
+}
+
+impl Config {
+    /// The warning attached to API responses, if the daemon runs in an unsafe mode.
+    pub fn warning(&self) -> Option<&'static str> {
+        self.unsafe_no_detector.then_some(UNSAFE_NO_DETECTOR_BANNER)
+    }
 }
 
 pub struct AppState {
     pub config: Config,
+    /// Client settings in effect, those of the command line unless changed through the admin API
+    pub client: ClientArgs,
     pub light_block: Option<LightBlock>,
-    pub last_sync: Instant,
+    /// Light blocks traversed to verify `light_block`
+    pub trace: Vec<TraceStep>,
+    /// Time of the last successful sync, if any
+    pub last_sync: Option<Instant>,
+    /// Time at which the last sync attempt completed, successful or not
+    pub last_sync_attempt: Option<Instant>,
     pub syncing: bool,
+    /// Requests for the latest trusted block since the keep-warm timer last fired
+    pub api_requests: u64,
+    /// Whether syncing is halted after a detected fork
+    pub halted: bool,
     pub last_sync_success: bool,
+    /// Whether the providers were initialized
+    pub initialized: bool,
+    /// Error of the last failed provider initialization, until one succeeds
+    pub init_error: Option<String>,
+    /// Current backoff applied after failed syncs (in seconds)
+    pub backoff_secs: u64,
+    /// Number of syncs that failed since the last successful one
+    pub sync_failures: u64,
+    /// RPC statistics of the primary followed by the witnesses
+    pub providers: Vec<SharedProviderStats>,
+    /// Divergences found by the fork detector, in detection order
+    pub divergences: Vec<DivergenceRecord>,
+    /// Latest block time reported by the providers minus the local time (in seconds), once measured
+    pub clock_skew: Option<f64>,
+    /// Latest measurement of the local clock against the reference time source, once checked
+    pub clock_offset: Option<ClockOffset>,
+    /// Latest block reported by the primary, once fetched
+    pub network_tip: Option<NetworkTip>,
+    /// Outcome of the latest fork detection round of a sync, once one ran
+    pub witness_check: Option<WitnessSummary>,
+    /// Trusted block of the `--state-file` syncing resumed from, if any
+    pub resumed_from: Option<TrustedCheckpoint>,
+}
+
+/// Latest block of the network as reported by the `/status` of the primary, which is not verified
+#[derive(Debug, Clone, Copy)]
+pub struct NetworkTip {
+    pub height: Height,
+    pub time: Time,
+}
+
+impl AppState {
+    pub fn sync_state(&self) -> SyncState {
+        if self.halted {
+            SyncState::Halted
+        } else if !self.initialized {
+            SyncState::Initializing
+        } else if self.light_block.is_none() || !self.last_sync_success {
+            SyncState::Degraded
+        } else {
+            SyncState::Synced
+        }
+    }
+
+    /// Whether the latest trusted block is past its trusting period, after which it can no longer
+    /// be served under [`DegradationPolicy::ServeLastVerified`].
+    pub fn trust_expired(&self) -> bool {
+        let Some(light_block) = &self.light_block else {
+            return false;
+        };
+        let trusting_period = Duration::from_secs(self.client.trusting_period);
+        !(light_block.signed_header.header.time + trusting_period).is_ok_and(|expiry| expiry > Time::now())
+    }
+
+    /// Why the local clock is deemed wrong, if its latest measured offset exceeds the maximum
+    /// clock offset.
+    ///
+    /// Block times always lag behind the present, by more than the block interval if the chain
+    /// stalls, so against the providers only a local clock behind them is flagged.
+    pub fn clock_offset_issue(&self) -> Option<String> {
+        let max_offset = self.config.max_clock_offset?;
+        let ClockOffset { offset_secs, source, .. } = self.clock_offset?;
+        let exceeded = match source {
+            ClockSource::Ntp => offset_secs.abs() > max_offset,
+            ClockSource::Providers => offset_secs > max_offset,
+        };
+        let direction = if offset_secs > 0.0 { "behind" } else { "ahead of" };
+        let reference = match source {
+            ClockSource::Ntp => "the NTP servers",
+            ClockSource::Providers => "the latest block time of the providers",
+        };
+        exceeded.then(|| {
+            format!(
+                "local clock is {:.1} seconds {} {} (maximum clock offset: {} seconds)",
+                offset_secs.abs(),
+                direction,
+                reference,
+                max_offset
+            )
+        })
+    }
+
+    /// Why verified data is refused, if the local clock is deemed wrong and
+    /// `--refuse-on-clock-offset` is set, see [`Self::clock_offset_issue`].
+    pub fn clock_refusal(&self) -> Option<String> {
+        self.config
+            .refuse_on_clock_offset
+            .then(|| self.clock_offset_issue())
+            .flatten()
+    }
 }
 
 pub type SharedState = Arc<RwLock<AppState>>;
@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use prometheus::{Gauge, Histogram, IntCounter};
+use serde::Serialize;
+use tendermint_rpc::HttpClientUrl;
+
+use crate::metrics::Metrics;
+
+/// Number of most recent RPC calls the rolling statistics are computed over
+const WINDOW_SIZE: usize = 100;
+
+/// Health of a provider, derived from the outcome of the last RPC call and the chain it serves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderHealth {
+    /// No RPC call has completed yet
+    Unknown,
+    /// The last RPC call succeeded
+    Healthy,
+    /// The last RPC call failed
+    Unhealthy,
+    /// The provider serves another chain than `--chain-id`
+    WrongChain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderRole {
+    Primary,
+    Witness,
+}
+
+impl fmt::Display for ProviderRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Primary => f.write_str("primary"),
+            Self::Witness => f.write_str("witness"),
+        }
+    }
+}
+
+struct Sample {
+    latency: Duration,
+    success: bool,
+}
+
+/// Rolling RPC latency and error statistics of a single provider
+pub struct ProviderStats {
+    pub role: ProviderRole,
+    pub url: String,
+    /// Node ID reported by the provider, known once it has been initialized
+    pub peer_id: Option<tendermint::node::Id>,
+    /// Chain the provider reported serving when last checked, if not `--chain-id`
+    pub wrong_chain: Option<String>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<Instant>,
+    pub last_success_at: Option<Instant>,
+    window: VecDeque<Sample>,
+    latency_histogram: Histogram,
+    error_counter: IntCounter,
+    success_rate_gauge: Gauge,
+}
+
+pub type SharedProviderStats = Arc<Mutex<ProviderStats>>;
+
+impl ProviderStats {
+    pub fn new(role: ProviderRole, url: &HttpClientUrl, metrics: &Metrics) -> SharedProviderStats {
+        let url = tendermint_rpc::Url::from(url.clone()).to_string();
+        let provider_metrics = metrics.provider(role, &url);
+
+        Arc::new(Mutex::new(Self {
+            role,
+            url,
+            peer_id: None,
+            wrong_chain: None,
+            last_error: None,
+            last_error_at: None,
+            last_success_at: None,
+            window: VecDeque::with_capacity(WINDOW_SIZE),
+            latency_histogram: provider_metrics.latency,
+            error_counter: provider_metrics.errors,
+            success_rate_gauge: provider_metrics.success_rate,
+        }))
+    }
+
+    /// Records the outcome of a single RPC call, given the error message if it failed.
+    pub fn record(&mut self, latency: Duration, result: Result<(), String>) {
+        let success = result.is_ok();
+        match result {
+            Ok(()) => self.last_success_at = Some(Instant::now()),
+            Err(e) => {
+                self.last_error = Some(e);
+                self.last_error_at = Some(Instant::now());
+                self.error_counter.inc();
+            }
+        }
+
+        if self.window.len() == WINDOW_SIZE {
+            self.window.pop_front();
+        }
+        self.window.push_back(Sample { latency, success });
+
+        self.latency_histogram.observe(latency.as_secs_f64());
+        if let Some(success_rate) = self.success_rate() {
+            self.success_rate_gauge.set(success_rate);
+        }
+    }
+
+    /// Fraction of successful calls within the rolling window.
+    pub fn success_rate(&self) -> Option<f64> {
+        if self.window.is_empty() {
+            return None;
+        }
+        let successes = self.window.iter().filter(|s| s.success).count();
+        Some(successes as f64 / self.window.len() as f64)
+    }
+
+    /// Mean latency of the calls within the rolling window.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        if self.window.is_empty() {
+            return None;
+        }
+        let total: Duration = self.window.iter().map(|s| s.latency).sum();
+        Some(total / self.window.len() as u32)
+    }
+
+    /// Time of the last RPC call, successful or not.
+    pub fn last_contact(&self) -> Option<Instant> {
+        self.last_success_at.max(self.last_error_at)
+    }
+
+    pub fn health(&self) -> ProviderHealth {
+        if self.wrong_chain.is_some() {
+            return ProviderHealth::WrongChain;
+        }
+        match self.window.back() {
+            None => ProviderHealth::Unknown,
+            Some(sample) if sample.success => ProviderHealth::Healthy,
+            Some(_) => ProviderHealth::Unhealthy,
+        }
+    }
+}
+
+impl fmt::Display for ProviderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.role, self.url)?;
+        match (self.mean_latency(), self.success_rate()) {
+            (Some(latency), Some(success_rate)) => write!(
+                f,
+                ": mean latency {} ms, success rate {:.1}% over {} calls",
+                latency.as_millis(),
+                success_rate * 100.0,
+                self.window.len()
+            )?,
+            _ => f.write_str(": no calls yet")?,
+        }
+        if let Some(last_error) = &self.last_error {
+            write!(f, ", last error: {last_error}")?;
+        }
+        Ok(())
+    }
+}
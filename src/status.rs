@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tendermint::Time;
+use tendermint_light_client::types::{Hash, Height};
+
+use crate::cli::StatusArgs;
+
+#[derive(Debug, Deserialize)]
+struct Root {
+    name: String,
+    version: String,
+    warning: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Status {
+    block_height: Height,
+    block_hash: Hash,
+    block_timestamp: Time,
+    app_hash: Option<String>,
+    #[serde(default)]
+    halted: bool,
+    #[serde(default)]
+    stale: bool,
+    state: Option<String>,
+    network_height: Option<Height>,
+    lag_blocks: Option<u64>,
+    lag_secs: Option<u64>,
+    witness_check: Option<WitnessCheck>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WitnessCheck {
+    checked: usize,
+    agreed: usize,
+    diverged: usize,
+    errored: usize,
+    checked_at: Time,
+}
+
+#[derive(Debug, Deserialize)]
+struct Peers {
+    peers: Vec<Peer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Peer {
+    role: String,
+    url: String,
+    health: String,
+    success_rate: Option<f64>,
+    mean_latency_ms: Option<u128>,
+    last_error: Option<String>,
+}
+
+/// Queries a running daemon and prints a human-friendly summary of its state.
+pub async fn run_status(args: StatusArgs) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(args.timeout))
+        .build()?;
+
+    let root: Root = get(&client, &args.url, "").await?;
+    println!("Daemon:      {} ({} {})", args.url, root.name, root.version);
+    if let Some(warning) = root.warning {
+        println!("Warning:     {}", warning);
+    }
+
+    // The daemon may be unable to serve a trusted block, e.g. while offline or during a fork,
+    // which is worth showing rather than failing
+    match get::<Status>(&client, &args.url, "v1/status").await {
+        Ok(status) => {
+            let age = Time::now()
+                .duration_since(status.block_timestamp)
+                .map(|age| format!("{}s ago", age.as_secs()))
+                .unwrap_or_else(|_| "in the future".to_string());
+            println!("Height:      {}", status.block_height);
+            println!("Hash:        {}", status.block_hash);
+            println!("Block time:  {} ({})", status.block_timestamp, age);
+            if let Some(app_hash) = status.app_hash {
+                println!("App hash:    {}", app_hash);
+            }
+            println!("Halted:      {}", if status.halted { "yes, fork detected" } else { "no" });
+            if status.stale {
+                println!("Stale:       yes, older than the freshness threshold");
+            }
+            if let Some(state) = status.state {
+                println!("State:       {}", state);
+            }
+            if let (Some(height), Some(blocks), Some(secs)) =
+                (status.network_height, status.lag_blocks, status.lag_secs)
+            {
+                println!("Network tip: {} ({} blocks, {}s behind)", height, blocks, secs);
+            }
+            if let Some(check) = status.witness_check {
+                println!(
+                    "Witnesses:   {} of {} agreed, {} diverged, {} errored (checked at {})",
+                    check.agreed, check.checked, check.diverged, check.errored, check.checked_at
+                );
+            }
+        }
+        Err(e) => println!("Status:      unavailable ({:#})", e),
+    }
+
+    let peers: Peers = get(&client, &args.url, "v1/peers").await?;
+    println!("Peers:");
+    for peer in peers.peers {
+        let success_rate = peer
+            .success_rate
+            .map(|rate| format!("{:.1}%", rate * 100.0))
+            .unwrap_or_else(|| "-".to_string());
+        let latency = peer
+            .mean_latency_ms
+            .map(|ms| format!("{}ms", ms))
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "  {:<8} {:<10} {}  success: {}  latency: {}",
+            peer.role, peer.health, peer.url, success_rate, latency
+        );
+        if let Some(last_error) = peer.last_error {
+            println!("           last error: {}", last_error);
+        }
+    }
+
+    Ok(())
+}
+
+async fn get<T: DeserializeOwned>(client: &reqwest::Client, base: &reqwest::Url, path: &str) -> Result<T> {
+    let url = base.join(path)?;
+    let response = client
+        .get(url.clone())
+        .send()
+        .await
+        .wrap_err_with(|| format!("failed to query {}", url))?;
+
+    let status = response.status();
+    let body = response.bytes().await?;
+    if !status.is_success() {
+        return Err(eyre!("{} returned {}: {}", url, status, String::from_utf8_lossy(&body)));
+    }
+    serde_json::from_slice(&body).wrap_err_with(|| format!("invalid response from {}", url))
+}
@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use tendermint::Time;
+use tendermint_light_client::{
+    store::{memory::MemoryStore, LightStore},
+    types::{Height, LightBlock, Status},
+};
+
+/// In-memory light store whose contents can be read from outside the light client owning it,
+/// e.g. to dump a snapshot.
+#[derive(Debug, Clone, Default)]
+pub struct SharedLightStore(Arc<Mutex<MemoryStore>>);
+
+impl SharedLightStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn store(&self) -> MutexGuard<'_, MemoryStore> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Copy of the store, independent of it, e.g. to verify blocks without adding them to it yet
+    pub fn detached_copy(&self) -> Self {
+        Self(Arc::new(Mutex::new(self.store().clone())))
+    }
+
+    /// Adds the trusted and verified blocks of `other` at heights that have none in this store.
+    pub fn merge_verified(&self, other: &SharedLightStore) {
+        let mut store = self.store();
+        for (block, status) in all_entries(&other.store()) {
+            let verified = matches!(status, Status::Trusted | Status::Verified);
+            if verified && store.get_trusted_or_verified(block.height()).is_none() {
+                store.insert(block, status);
+            }
+        }
+    }
+
+    /// All blocks in the store with their status, in ascending height order
+    pub fn entries(&self) -> Vec<(LightBlock, Status)> {
+        let mut entries = all_entries(&self.store());
+        entries.sort_by_key(|(block, status)| (block.height(), *status));
+        entries
+    }
+
+    /// Removes the blocks below the `keep_blocks` highest heights, and those whose header is
+    /// older than `min_time`, returning the number of blocks removed.
+    ///
+    /// The highest trusted or verified block is always kept, since it anchors verification.
+    pub fn prune(&self, keep_blocks: Option<usize>, min_time: Option<Time>) -> usize {
+        let mut store = self.store();
+        let anchor = store.highest_trusted_or_verified().map(|block| block.height());
+
+        let mut entries = all_entries(&store);
+        entries.sort_by_key(|(block, _)| std::cmp::Reverse(block.height()));
+
+        let mut heights = HashSet::new();
+        let mut removed = 0;
+        for (block, status) in entries {
+            let height = block.height();
+            heights.insert(height);
+            if Some(height) == anchor {
+                continue;
+            }
+
+            let beyond_window = keep_blocks.is_some_and(|keep| heights.len() > keep);
+            let expired = min_time.is_some_and(|min_time| block.signed_header.header.time < min_time);
+            if beyond_window || expired {
+                store.remove(height, status);
+                removed += 1;
+            }
+        }
+        removed
+    }
+}
+
+fn all_entries(store: &MemoryStore) -> Vec<(LightBlock, Status)> {
+    Status::iter()
+        .iter()
+        .flat_map(|status| store.all(*status).map(|block| (block, *status)))
+        .collect()
+}
+
+impl LightStore for SharedLightStore {
+    fn get(&self, height: Height, status: Status) -> Option<LightBlock> {
+        self.store().get(height, status)
+    }
+
+    fn update(&mut self, light_block: &LightBlock, status: Status) {
+        self.store().update(light_block, status)
+    }
+
+    fn insert(&mut self, light_block: LightBlock, status: Status) {
+        self.store().insert(light_block, status)
+    }
+
+    fn remove(&mut self, height: Height, status: Status) {
+        self.store().remove(height, status)
+    }
+
+    fn highest(&self, status: Status) -> Option<LightBlock> {
+        self.store().highest(status)
+    }
+
+    fn highest_before(&self, height: Height, status: Status) -> Option<LightBlock> {
+        self.store().highest_before(height, status)
+    }
+
+    fn lowest(&self, status: Status) -> Option<LightBlock> {
+        self.store().lowest(status)
+    }
+
+    fn all(&self, status: Status) -> Box<dyn Iterator<Item = LightBlock>> {
+        let blocks: Vec<_> = self.store().all(status).collect();
+        Box::new(blocks.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{light_block, signing_keys};
+
+    /// Store with trusted blocks at heights 1 to 9, verified at 10 and unverified at 11
+    fn sample_store() -> SharedLightStore {
+        let keys = signing_keys(1);
+        let mut store = SharedLightStore::new();
+        for height in 1..=11 {
+            let status = match height {
+                10 => Status::Verified,
+                11 => Status::Unverified,
+                _ => Status::Trusted,
+            };
+            store.insert(light_block(height, &keys), status);
+        }
+        store
+    }
+
+    fn heights(store: &SharedLightStore) -> Vec<u64> {
+        store.entries().iter().map(|(block, _)| block.height().value()).collect()
+    }
+
+    fn time_of(height: u64) -> Time {
+        light_block(height, &signing_keys(1)).signed_header.header.time
+    }
+
+    #[test]
+    fn prunes_beyond_both_limits() {
+        let store = sample_store();
+        assert_eq!(store.prune(Some(5), None), 6);
+        assert_eq!(heights(&store), [7, 8, 9, 10, 11]);
+
+        // Blocks from before height 9 are older than the minimum time
+        assert_eq!(store.prune(Some(5), Some(time_of(9))), 2);
+        assert_eq!(heights(&store), [9, 10, 11]);
+
+        let store = sample_store();
+        assert_eq!(store.prune(Some(8), Some(time_of(6))), 5);
+        assert_eq!(heights(&store), [6, 7, 8, 9, 10, 11]);
+        assert_eq!(store.prune(None, None), 0);
+    }
+
+    #[test]
+    fn keeps_the_highest_trusted_block() {
+        let store = sample_store();
+        assert_eq!(store.prune(Some(0), None), 10);
+        assert_eq!(heights(&store), [10]);
+
+        let store = sample_store();
+        assert_eq!(store.prune(Some(1), Some(time_of(12))), 10);
+        assert_eq!(store.entries()[0].1, Status::Verified);
+        assert_eq!(heights(&store), [10]);
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use futures::StreamExt;
+use tendermint_rpc::{
+    client::CompatMode, event::EventData, query::EventType, Client, SubscriptionClient,
+    WebSocketClient, WebSocketClientUrl,
+};
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    cli::{CompatModeSetting, RunArgs},
+    provider::{detect_compat_mode, error_message, RpcConfig},
+    state::SharedState,
+};
+
+/// Subscribes to `NewBlock` events of the primary and triggers a sync whenever a block is
+/// announced, reconnecting with backoff when the subscription drops.
+///
+/// The keep-warm timer keeps running, so syncing carries on while the subscription is down.
+pub async fn run_subscription(
+    args: RunArgs,
+    state: SharedState,
+    sync_trigger: broadcast::Sender<()>,
+) {
+    let url = match websocket_url(&args) {
+        Ok(url) => url,
+        Err(e) => {
+            error!("{:#}", e);
+            return;
+        }
+    };
+
+    let max_backoff_secs: u64 = 30;
+    let mut backoff_secs = 1;
+    loop {
+        match subscribe(&args, &url, &state, &sync_trigger, &mut backoff_secs).await {
+            Ok(()) => warn!("NewBlock subscription to {} closed", url),
+            Err(e) => warn!("NewBlock subscription to {} failed: {:#}", url, e),
+        }
+
+        debug!("reconnecting to {} in {} seconds", url, backoff_secs);
+        tokio::time::sleep(Duration::from_secs(backoff_secs)).await;
+        backoff_secs = (backoff_secs * 2).min(max_backoff_secs);
+    }
+}
+
+/// WebSocket endpoint to subscribe to, `--websocket-url` or the `/websocket` path of the primary
+fn websocket_url(args: &RunArgs) -> Result<WebSocketClientUrl> {
+    if let Some(url) = &args.websocket_url {
+        return Ok(url.clone());
+    }
+
+    let primary = tendermint_rpc::Url::from(args.client.primary.clone()).to_string();
+    let mut url = reqwest::Url::parse(&primary)?;
+    let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+    url.set_scheme(scheme)
+        .map_err(|_| eyre!("cannot derive a WebSocket URL from primary {}", primary))?;
+    url.set_path(&format!("{}/websocket", url.path().trim_end_matches('/')));
+
+    url.as_str()
+        .parse()
+        .map_err(|e| eyre!(error_message(&e)))
+        .wrap_err_with(|| format!("invalid WebSocket URL {}", url))
+}
+
+/// Connects to `url` and spawns the task driving the connection.
+async fn connect(
+    url: &WebSocketClientUrl,
+    compat_mode: CompatMode,
+) -> Result<(WebSocketClient, tokio::task::JoinHandle<()>)> {
+    let (client, driver) = WebSocketClient::builder(url.clone())
+        .compat_mode(compat_mode)
+        .build()
+        .await
+        .map_err(|e| eyre!(error_message(&e)))?;
+
+    let driver = tokio::spawn(async move {
+        if let Err(e) = driver.run().await {
+            debug!("WebSocket connection terminated: {}", error_message(&e));
+        }
+    });
+    Ok((client, driver))
+}
+
+async fn subscribe(
+    args: &RunArgs,
+    url: &WebSocketClientUrl,
+    state: &SharedState,
+    sync_trigger: &broadcast::Sender<()>,
+    backoff_secs: &mut u64,
+) -> Result<()> {
+    // Events are decoded according to the dialect of the node, so it has to be known up front
+    let compat_mode = match RpcConfig::for_url(&args.client, &args.client.primary).compat_mode {
+        CompatModeSetting::Fixed(mode) => mode,
+        CompatModeSetting::Auto => {
+            // The `/status` format is the same for all dialects, so any mode works until detection
+            let (client, driver) = connect(url, CompatMode::V0_37).await?;
+            let status = client.status().await;
+            let _ = client.close();
+            let _ = driver.await;
+            let version = status.map_err(|e| eyre!(error_message(&e)))?.node_info.version;
+            detect_compat_mode(&version, &args.client.primary)
+        }
+    };
+
+    let (client, driver) = connect(url, compat_mode).await?;
+    let mut subscription = client
+        .subscribe(EventType::NewBlock.into())
+        .await
+        .map_err(|e| eyre!(error_message(&e)))?;
+    info!("Subscribed to NewBlock events on {}", url);
+    *backoff_secs = 1;
+
+    let result = loop {
+        let event = match subscription.next().await {
+            Some(Ok(event)) => event,
+            Some(Err(e)) => break Err(eyre!(error_message(&e))),
+            None => break Ok(()),
+        };
+
+        if let EventData::NewBlock { block: Some(block), .. }
+        | EventData::LegacyNewBlock { block: Some(block), .. } = &event.data
+        {
+            debug!("sync triggered by NewBlock event at height {}", block.header.height);
+        }
+        trigger_sync(state, sync_trigger).await;
+    };
+
+    let _ = client.close();
+    let _ = driver.await;
+    result
+}
+
+/// Requests a sync unless one is already in progress or syncing is halted.
+async fn trigger_sync(state: &SharedState, sync_trigger: &broadcast::Sender<()>) {
+    let mut lock = state.write().await;
+    if !lock.syncing && !lock.halted {
+        lock.syncing = true;
+        let _ = sync_trigger.send(());
+    }
+}
@@ -1,182 +1,764 @@
+use std::future::Future;
 use std::time::{Duration, Instant};
 
-use color_eyre::eyre::{eyre, Result};
 use futures::future::join_all;
-use reqwest::Client as ReqwestClient;
+
 use tendermint::crypto::default::Sha256;
-use tendermint::evidence::Evidence;
 use tendermint::Time;
-use tendermint_light_client::{
-    builder::LightClientBuilder,
-    instance::Instance,
-    light_client::Options,
-    store::memory::MemoryStore,
-    types::{Hash, Height, LightBlock},
-};
-use tendermint_light_client_detector::{detect_divergence, Error as DetectorError, Provider, Trace};
-use tendermint_rpc::{client::CompatMode, Client, HttpClient, HttpClientUrl};
+use tendermint::evidence::Evidence;
+use tendermint_light_client::types::LightBlock;
+use tendermint_light_client_detector::{detect_divergence, Trace};
+use tendermint_rpc::{client::CompatMode, endpoint::status, Client};
+use tokio::runtime::Handle;
 use tokio::sync::{broadcast, watch};
 use tracing::{debug, error, info, warn};
 
 use crate::{
-    cli::Args,
-    state::{AppState, SharedState},
+    audit::{AuditEvent, AuditLog, SharedAuditLog},
+    cache::{SharedCache, SharedValidatorSetCache},
+    cli::{find_override, ClientArgs, CompatModeSetting, RunArgs},
+    clock::check_clock_skew,
+    discovery::discover_witnesses,
+    evidence::{DivergencePeer, DivergenceRecord},
+    metrics::SharedMetrics,
+    persist::SyncBookkeeping,
+    provider::{
+        build_rpc_client, error_message, make_providers, offload, Peer, Providers, RpcConfig,
+        SharedProviders,
+    },
+    state::{AppState, SharedState, TraceStep, WitnessSummary, UNSAFE_NO_DETECTOR_BANNER},
+    stats::{ProviderRole, ProviderStats},
+    systemd::{Notifier, SharedNotifier},
 };
 
-fn fmt_peer_url<T: std::fmt::Display>(peer_id: T, url: &HttpClientUrl) -> String {
-    format!(
-        "peer: {}, url: {}",
-        peer_id,
-        tendermint_rpc::Url::from(url.clone()).to_string()
-    )
+/// Outcome of a single sync round
+enum SyncOutcome {
+    /// The primary was verified to its highest block and no fork was detected
+    Synced,
+    /// A witness presented a conflicting header
+    ForkDetected(Vec<DivergenceRecord>),
+    /// Verification against the primary failed
+    Failed,
+}
+
+/// Timer of the periodic 'keep-warm' syncs.
+///
+/// In adaptive mode, the interval doubles, up to `max_interval`, whenever no API request arrived
+/// since the timer last fired. It is reset to the configured interval when requests arrive, and
+/// halved, down to the freshness threshold, when they arrive at least once per freshness
+/// threshold on average, so that they find a fresh block rather than wait for a sync.
+struct KeepWarm {
+    /// When the timer fires next, `None` if keep-warm syncs are disabled
+    next: Option<tokio::time::Instant>,
+    interval: Duration,
+    base_interval: Duration,
+    /// Bounds of the interval in adaptive mode
+    adaptive: Option<(Duration, Duration)>,
+    freshness_threshold: Duration,
+}
+
+impl KeepWarm {
+    fn new(args: &RunArgs, freshness_threshold: Duration, last_sync: Option<Instant>) -> Self {
+        let base_interval = Duration::from_secs(args.keep_warm_interval);
+        let adaptive = args.keep_warm_max_interval.map(|max_interval| {
+            let min_interval = freshness_threshold.max(Duration::from_secs(1)).min(base_interval);
+            (min_interval, Duration::from_secs(max_interval))
+        });
+
+        // The initial sync is triggered on startup, so the timer only needs to
+        // fire once the keep-warm interval has elapsed since the last sync.
+        let next = (!base_interval.is_zero()).then(|| {
+            let start = last_sync.unwrap_or_else(Instant::now) + base_interval;
+            start.into()
+        });
+        Self {
+            next,
+            interval: base_interval,
+            base_interval,
+            adaptive,
+            freshness_threshold,
+        }
+    }
+
+    /// Schedules the next keep-warm sync, given the API requests since the timer last fired.
+    fn fired(&mut self, api_requests: u64) {
+        if let Some((min_interval, max_interval)) = self.adaptive {
+            let busy = api_requests as u128 * self.freshness_threshold.as_millis()
+                >= self.interval.as_millis();
+            let interval = if api_requests == 0 {
+                (self.interval * 2).min(max_interval)
+            } else if busy {
+                (self.interval.min(self.base_interval) / 2).max(min_interval)
+            } else {
+                self.base_interval
+            };
+            if interval != self.interval {
+                debug!(
+                    "{} API requests in the last {} seconds, keep-warm interval set to {} seconds",
+                    api_requests,
+                    self.interval.as_secs(),
+                    interval.as_secs()
+                );
+                self.interval = interval;
+            }
+        }
+        self.next = Some(tokio::time::Instant::now() + self.interval);
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run_sync(
-    args: Args,
+    mut args: RunArgs,
     state: SharedState,
+    providers: SharedProviders,
+    cache: SharedCache,
+    validator_sets: SharedValidatorSetCache,
     mut sync_trigger_rx: broadcast::Receiver<()>,
     sync_done_tx: watch::Sender<()>,
+    mut trust_reset_rx: watch::Receiver<()>,
+    metrics: SharedMetrics,
+    audit: SharedAuditLog,
+    notifier: SharedNotifier,
 ) {
-    let options = Options {
-        trust_threshold: args.trust_threshold,
-        trusting_period: Duration::from_secs(args.trusting_period),
-        clock_drift: Duration::from_secs(args.max_clock_drift),
-    };
-
-    let mut primary = match make_provider(
-        &args.chain_id,
-        args.primary.clone(),
-        args.trusted_height,
-        args.trusted_hash,
-        options,
+    let discovered = discover_witnesses(
+        &args.client,
+        &args.discovery_seeds.0,
+        args.discovery_max_witnesses,
     )
-    .await
-    {
-        Ok(provider) => provider,
-        Err(e) => {
-            error!("failed to initialize primary provider: {}", e);
-            return;
+    .await;
+    if !discovered.is_empty() {
+        for witness in discovered {
+            let url = tendermint_rpc::Url::from(witness.url.clone()).to_string();
+            info!("Discovered witness {} from seed node {}", url, witness.seed);
+            audit.record(AuditEvent::WitnessDiscovered {
+                peer: DivergencePeer {
+                    peer_id: witness.peer_id,
+                    url,
+                },
+                seed: witness.seed,
+            });
+            args.client.witnesses.0.push(witness.url);
         }
-    };
+        state.write().await.client.witnesses = args.client.witnesses.clone();
+    }
 
-    let trusted_block = match primary.latest_trusted() {
-        Some(block) => block,
-        None => {
-            error!("failed to get initial trusted block from primary ({})", fmt_peer_url(primary.peer_id(), &args.primary));
-            return;
-        }
-    };
+    let primary_stats = ProviderStats::new(ProviderRole::Primary, &args.client.primary, &metrics);
+    let witness_stats: Vec<_> = args
+        .client
+        .witnesses
+        .0
+        .iter()
+        .map(|addr| ProviderStats::new(ProviderRole::Witness, addr, &metrics))
+        .collect();
 
-    let witnesses = join_all(args.witnesses.0.iter().map(|addr| {
-        make_provider(
-            &args.chain_id,
-            addr.clone(),
-            trusted_block.height(),
-            trusted_block.signed_header.header.hash(),
-            options,
-        )
-    }))
-    .await;
+    {
+        let mut lock = state.write().await;
+        lock.providers = std::iter::once(primary_stats.clone())
+            .chain(witness_stats.iter().cloned())
+            .collect();
+    }
 
-    let mut witnesses: Vec<Provider> = match witnesses.into_iter().collect() {
-        Ok(witnesses) => witnesses,
-        Err(e) => {
-            error!("failed to initialize one or more witnesses: {}", e);
-            return;
+    let max_backoff_secs: u64 = 30;
+
+    // Retry until the providers are reachable, the API reports the daemon as initializing meanwhile.
+    // A reset of the trusted state through the admin API initializes them in the meantime.
+    let mut init_backoff_secs = 1;
+    loop {
+        match make_providers(&args.client, &validator_sets, primary_stats.clone(), &witness_stats).await {
+            Ok(initialized) => {
+                let mut guard = providers.lock().await;
+                if guard.is_some() {
+                    break;
+                }
+                info!("Initialized primary provider ({})", initialized.primary);
+                for (i, witness) in initialized.witnesses.iter().enumerate() {
+                    info!("Initialized witness provider #{} ({})", i + 1, witness);
+                }
+                *guard = Some(initialized);
+
+                let mut lock = state.write().await;
+                lock.initialized = true;
+                lock.init_error = None;
+                break;
+            }
+            Err(e) => {
+                error!("{:#}", e);
+                warn!("Retrying provider initialization in {} seconds", init_backoff_secs);
+                state.write().await.init_error = Some(format!("{:#}", e));
+                let wait = notifier.sleep(Duration::from_secs(init_backoff_secs));
+                if unless_trust_reset(wait, &mut trust_reset_rx).await {
+                    break;
+                }
+                init_backoff_secs = (init_backoff_secs * 2).min(max_backoff_secs);
+            }
         }
+    }
+
+    // Pick up the pacing of a previous run, if bookkeeping was restored
+    let (mut backoff_secs, last_sync, pending_backoff) = {
+        let lock = state.read().await;
+        let pending_backoff = match lock.last_sync_attempt {
+            Some(attempt) if !lock.last_sync_success => {
+                Duration::from_secs(lock.backoff_secs).checked_sub(attempt.elapsed())
+            }
+            _ => None,
+        };
+        (lock.backoff_secs.clamp(1, max_backoff_secs), lock.last_sync, pending_backoff)
     };
 
-    info!(
-        "Initialized primary provider ({})",
-        fmt_peer_url(primary.peer_id(), &args.primary),
-    );
-    for (i, witness) in witnesses.iter().enumerate() {
+    if let Some(remaining) = pending_backoff {
         info!(
-            "Initialized witness provider #{} ({})",
-            i + 1,
-            fmt_peer_url(witness.peer_id(), &args.witnesses.0[i]),
+            "Previous run ended with a failed sync, waiting {} seconds before syncing",
+            remaining.as_secs()
         );
+        notifier.sleep(remaining).await;
     }
 
-    let keep_warm_interval = Duration::from_secs(args.keep_warm_interval);
-    let mut keep_warm_timer = tokio::time::interval(keep_warm_interval);
-    let mut backoff_secs: u64 = 1;
-    let max_backoff_secs: u64 = 30;
+    let mut keep_warm = KeepWarm::new(&args, state.read().await.config.freshness_threshold, last_sync);
+    let mut watchdog_timer = notifier.watchdog_interval().map(tokio::time::interval);
 
     loop {
         tokio::select! {
-            _ = keep_warm_timer.tick() => {
+            _ = async {
+                match keep_warm.next {
+                    Some(next) => tokio::time::sleep_until(next).await,
+                    None => std::future::pending().await,
+                }
+            } => {
                 debug!("sync triggered by periodic timer");
+                let api_requests = std::mem::take(&mut state.write().await.api_requests);
+                keep_warm.fired(api_requests);
             },
-            Ok(_) = sync_trigger_rx.recv() => {
-                debug!("sync triggered by API request");
+            // Lagging only means that several syncs were requested in the meantime
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) = sync_trigger_rx.recv() => {
+                debug!("sync triggered by API request or NewBlock event");
+            }
+            Some(_) = async { Some(watchdog_timer.as_mut()?.tick().await) } => {
+                notifier.watchdog();
+                continue;
             }
         }
 
-        info!("Syncing from primary...");
-        match primary.verify_to_highest() {
-            Ok(new_block) => {
-                info!(
-                    "Sync successful to block height {}",
-                    new_block.height()
-                );
+        let outcome = {
+            let mut guard = providers.lock().await;
+            match guard.as_mut() {
+                Some(providers) => {
+                    let max_clock_drift = state.read().await.client.max_clock_drift;
+                    let max_clock_drift = Duration::from_secs(max_clock_drift);
+                    let statuses = fetch_statuses(providers).await;
+                    check_chain_ids(providers, &statuses, &state).await;
+                    check_clock_skew(providers, &statuses, max_clock_drift, &state, &metrics).await;
+                    match args.quorum {
+                        Some(quorum) => quorum_round(providers, quorum, &args, &state, &cache).await,
+                        None => sync_round(providers, &args, &state, &cache, &audit).await,
+                    }
+                }
+                None => {
+                    warn!("providers are not initialized, skipping sync");
+                    SyncOutcome::Failed
+                }
+            }
+        };
 
-                // Fork detection starts here
-                let primary_trace = primary.get_trace(new_block.height());
-                let fork_detected = run_fork_detector(
-                    &mut primary,
-                    &mut witnesses,
-                    primary_trace,
-                    &args,
-                )
-                .await;
-
-                if !fork_detected {
-                    // Happy path: no fork, update state
-                    let mut lock = state.write().await;
-                    lock.light_block = Some(new_block);
-                    lock.last_sync = Instant::now();
-                    lock.last_sync_success = true;
-                    backoff_secs = 1; // reset backoff on success
-                } else {
-                    // Fork detected, enter halted state
-                    warn!(
-                        "Fork detected! Halting all sync operations for {} seconds.",
-                        args.halt_duration_on_fork
-                    );
-                    tokio::time::sleep(Duration::from_secs(args.halt_duration_on_fork)).await;
+        match outcome {
+            SyncOutcome::Synced => {
+                backoff_secs = 1; // reset backoff on success
+                state.write().await.backoff_secs = backoff_secs;
+                notifier.ready();
+            }
+            SyncOutcome::ForkDetected(divergences) => {
+                record_divergences(&state, &audit, divergences).await;
+
+                // Fork detected, enter halted state, which a reset of the trusted state ends
+                state.write().await.halted = true;
+                trust_reset_rx.borrow_and_update();
+                audit.record(AuditEvent::Halted {
+                    reason: "fork_detected",
+                    duration_secs: match args.fork_recovery_interval {
+                        Some(_) => None,
+                        None => Some(args.halt_duration_on_fork),
+                    },
+                });
+                match args.fork_recovery_interval {
+                    Some(interval) => {
+                        warn!("Fork detected! Halting all sync operations until the primary and witnesses agree again.");
+                        let recovery =
+                            recover_from_fork(&providers, &args, &state, &cache, &audit, &notifier, interval);
+                        if !unless_trust_reset(recovery, &mut trust_reset_rx).await {
+                            metrics.fork_recoveries.inc();
+                        }
+                        backoff_secs = 1;
+                    }
+                    None => {
+                        warn!(
+                            "Fork detected! Halting all sync operations for {} seconds.",
+                            args.halt_duration_on_fork
+                        );
+                        let halt = notifier.sleep(Duration::from_secs(args.halt_duration_on_fork));
+                        unless_trust_reset(halt, &mut trust_reset_rx).await;
+                    }
                 }
+                state.write().await.halted = false;
+                audit.record(AuditEvent::Resumed);
             }
-            Err(e) => {
-                error!("failed to verify to highest on primary ({}): {}", fmt_peer_url(primary.peer_id(), &args.primary), e);
+            SyncOutcome::Failed => {
                 // mark failure and back off
                 {
                     let mut lock = state.write().await;
                     lock.last_sync_success = false;
+                    lock.sync_failures += 1;
+                    lock.last_sync_attempt = Some(Instant::now());
+                    lock.backoff_secs = backoff_secs;
                 }
-                tokio::time::sleep(Duration::from_secs(backoff_secs)).await;
+                notifier.sleep(Duration::from_secs(backoff_secs)).await;
                 backoff_secs = (backoff_secs * 2).min(max_backoff_secs);
             }
         }
+        notifier.watchdog();
 
-        // Reset the syncing flag and notify any waiting handlers
+        // Reset the syncing flag, persist the outcome of the round and notify any waiting handlers
         {
             let mut lock = state.write().await;
             lock.syncing = false;
+            persist_bookkeeping(&args, &lock);
+            log_provider_stats(&lock);
         }
         let _ = sync_done_tx.send(());
     }
 }
 
-async fn run_fork_detector(
-    primary: &mut Provider,
-    witnesses: &mut [Provider],
+/// Verifies the primary to its highest block, cross-checks it against the
+/// witnesses and, if no fork is detected, publishes it as the trusted state.
+async fn sync_round(
+    providers: &mut Providers,
+    args: &RunArgs,
+    state: &SharedState,
+    cache: &SharedCache,
+    audit: &AuditLog,
+) -> SyncOutcome {
+    let Providers { primary, witnesses } = providers;
+    if let Some(chain) = &primary.wrong_chain {
+        error!("primary ({}) serves chain {}, not {}, skipping sync", primary, chain, args.client.chain_id);
+        return SyncOutcome::Failed;
+    }
+
+    info!("Syncing from primary...");
+    let new_block = match primary.verify_to_highest() {
+        Ok(new_block) => new_block,
+        Err(e) => {
+            error!("failed to verify to highest on primary ({}): {}", primary, e);
+            return SyncOutcome::Failed;
+        }
+    };
+
+    info!("Sync successful to block height {}", new_block.height());
+    state.write().await.last_sync_attempt = Some(Instant::now());
+
+    // Fork detection starts here, with the detector settings currently in effect
+    let client = state.read().await.client.clone();
+    let primary_trace = primary.get_trace(new_block.height());
+    let trace = primary_trace.iter().map(TraceStep::from).collect();
+    let detection = run_fork_detector(primary, witnesses, primary_trace, &client, audit).await;
+    if let Some(summary) = detection.summary() {
+        state.write().await.witness_check = Some(summary);
+    }
+    if !detection.cross_checked(&client) {
+        if detection.divergences.is_empty() {
+            return SyncOutcome::Failed;
+        }
+        return SyncOutcome::ForkDetected(detection.divergences);
+    }
+
+    // Happy path: no fork, update state
+    prune_stores(providers, args, &client);
+    publish(state, cache, new_block, trace).await;
+    SyncOutcome::Synced
+}
+
+/// Verifies the tip on the primary and every witness alike and publishes the highest block
+/// on whose hash at least `quorum` of them agree.
+///
+/// Providers which reached a higher block are verified back to the height that `quorum` providers
+/// reached, so that they are compared at the same height. Providers disagreeing with the quorum are
+/// only reported, as there is no single primary to generate evidence against.
+async fn quorum_round(
+    providers: &mut Providers,
+    quorum: usize,
+    args: &RunArgs,
+    state: &SharedState,
+    cache: &SharedCache,
+) -> SyncOutcome {
+    info!("Syncing from {} providers with a quorum of {}...", providers.witnesses.len() + 1, quorum);
+    let client = state.read().await.client.clone();
+
+    let mut tips = Vec::new();
+    for peer in std::iter::once(&mut providers.primary).chain(&mut providers.witnesses) {
+        if let Some(chain) = &peer.wrong_chain {
+            error!("provider ({}) serves chain {}, not {}, leaving it out", peer, chain, client.chain_id);
+            continue;
+        }
+        match peer.verify_to_highest() {
+            Ok(block) => tips.push((peer, block)),
+            Err(e) => error!("failed to verify to highest on provider ({}): {}", peer, e),
+        }
+    }
+
+    let mut heights: Vec<_> = tips.iter().map(|(_, block)| block.height()).collect();
+    heights.sort_unstable_by(|a, b| b.cmp(a));
+    let Some(&target) = heights.get(quorum - 1) else {
+        error!(
+            "only {} of {} providers could be verified, short of the quorum of {}",
+            tips.len(),
+            providers.witnesses.len() + 1,
+            quorum
+        );
+        return SyncOutcome::Failed;
+    };
+    state.write().await.last_sync_attempt = Some(Instant::now());
+
+    // Group the providers by the hash of their block at the target height
+    let mut groups: Vec<QuorumGroup> = Vec::new();
+    for (peer, tip) in tips {
+        let block = if tip.height() == target {
+            tip
+        } else {
+            match peer.verify_to_height(target) {
+                Ok(block) => block,
+                Err(e) => {
+                    error!("failed to verify height {} on provider ({}): {}", target, peer, e);
+                    continue;
+                }
+            }
+        };
+
+        let hash = block.signed_header.header.hash();
+        match groups.iter_mut().find(|group| group.block.signed_header.header.hash() == hash) {
+            Some(group) => group.peers.push(peer.to_string()),
+            None => groups.push(QuorumGroup {
+                trace: peer.get_trace(target),
+                block,
+                peers: vec![peer.to_string()],
+            }),
+        }
+    }
+
+    groups.sort_by_key(|group| std::cmp::Reverse(group.peers.len()));
+    let Some(QuorumGroup { block, peers: agreeing, trace }) =
+        groups.first().filter(|group| group.peers.len() >= quorum)
+    else {
+        error!(
+            "no {} providers agree on the block at height {}, got {} different hashes",
+            quorum,
+            target,
+            groups.len()
+        );
+        return SyncOutcome::Failed;
+    };
+
+    for dissenting in &groups[1..] {
+        for peer in &dissenting.peers {
+            error!(
+                "provider ({}) disagrees with the quorum at block height {}: hash {} instead of {}",
+                peer,
+                target,
+                dissenting.block.signed_header.header.hash(),
+                block.signed_header.header.hash()
+            );
+        }
+    }
+
+    info!(
+        "Sync successful to block height {}, agreed on by {} of {} providers",
+        target,
+        agreeing.len(),
+        providers.witnesses.len() + 1
+    );
+    let (block, trace) = (block.clone(), trace.iter().map(TraceStep::from).collect());
+    prune_stores(providers, args, &client);
+    publish(state, cache, block, trace).await;
+    SyncOutcome::Synced
+}
+
+/// Providers which verified the same block in a quorum round
+struct QuorumGroup {
+    block: LightBlock,
+    /// The providers, for logging
+    peers: Vec<String>,
+    /// Verification trace of the first provider
+    trace: Vec<LightBlock>,
+}
+
+/// Makes `block` the trusted state served by the API.
+async fn publish(state: &SharedState, cache: &SharedCache, block: LightBlock, trace: Vec<TraceStep>) {
+    cache.insert(block.clone());
+    let mut lock = state.write().await;
+    lock.light_block = Some(block);
+    lock.trace = trace;
+    lock.last_sync = Some(Instant::now());
+    lock.last_sync_success = true;
+    lock.sync_failures = 0;
+}
+
+/// Keeps the divergences for the evidence API, and in the `--evidence-dir` if set.
+///
+/// Divergences already recorded, e.g. detected again while recovering from a fork, are skipped.
+pub async fn record_divergences(
+    state: &SharedState,
+    audit: &AuditLog,
+    mut divergences: Vec<DivergenceRecord>,
+) {
+    {
+        let lock = state.read().await;
+        divergences.retain(|divergence| !lock.divergences.iter().any(|known| known.same_divergence(divergence)));
+    }
+    if divergences.is_empty() {
+        return;
+    }
+
+    for divergence in &divergences {
+        audit.record(AuditEvent::ForkDetected {
+            primary: divergence.primary.clone(),
+            witness: divergence.witness.clone(),
+            conflicting_height: divergence.conflicting_height,
+        });
+    }
+
+    let evidence_dir = state.read().await.config.evidence_dir.clone();
+    if let Some(dir) = &evidence_dir {
+        for divergence in &divergences {
+            if let Err(e) = divergence.save(dir) {
+                error!("failed to persist divergence evidence: {:#}", e);
+            }
+        }
+    }
+    state.write().await.divergences.extend(divergences);
+}
+
+/// Re-runs fork detection every `interval` seconds until a sync round completes without a
+/// divergence, at which point the verified block is published again.
+///
+/// Each round first tries the current primary, then every witness in turn as the primary, so that
+/// syncing can resume from a witness if the primary is the one that keeps diverging.
+async fn recover_from_fork(
+    providers: &SharedProviders,
+    args: &RunArgs,
+    state: &SharedState,
+    cache: &SharedCache,
+    audit: &AuditLog,
+    notifier: &Notifier,
+    interval: u64,
+) {
+    loop {
+        notifier.sleep(Duration::from_secs(interval)).await;
+
+        let mut guard = providers.lock().await;
+        let Some(providers) = guard.as_mut() else {
+            continue;
+        };
+
+        info!("Re-running fork detection to check whether the primary and witnesses agree again...");
+        for candidate in 0..=providers.witnesses.len() {
+            if candidate > 0 {
+                swap_primary(providers, state, candidate - 1).await;
+            }
+
+            match sync_round(providers, args, state, cache, audit).await {
+                SyncOutcome::Synced => {
+                    if candidate > 0 {
+                        warn!("Switched primary to former witness ({})", providers.primary);
+                        audit.record(AuditEvent::PrimaryFailover {
+                            from: (&providers.witnesses[candidate - 1]).into(),
+                            to: (&providers.primary).into(),
+                        });
+                    }
+                    warn!("Primary and witnesses agree again, resuming sync");
+                    return;
+                }
+                // Only divergences not seen before are recorded
+                SyncOutcome::ForkDetected(divergences) => record_divergences(state, audit, divergences).await,
+                SyncOutcome::Failed => {}
+            }
+
+            if candidate > 0 {
+                swap_primary(providers, state, candidate - 1).await;
+            }
+        }
+    }
+}
+
+/// Waits for `wait` to complete, unless the trusted state is reset through the admin API first.
+/// Returns whether it was.
+///
+/// The reset replaces the providers, so `wait` is dropped as soon as it happens rather than left
+/// to carry on with them.
+async fn unless_trust_reset(wait: impl Future<Output = ()>, trust_reset_rx: &mut watch::Receiver<()>) -> bool {
+    tokio::select! {
+        biased;
+        Ok(()) = trust_reset_rx.changed() => {
+            info!("Trusted state was reset through the admin API, resuming sync");
+            true
+        }
+        () = wait => false,
+    }
+}
+
+/// `/status` of the primary, then of each witness
+async fn fetch_statuses(providers: &Providers) -> Vec<Result<status::Response, tendermint_rpc::Error>> {
+    let peers = std::iter::once(&providers.primary).chain(&providers.witnesses);
+    join_all(peers.map(|peer| peer.rpc_client().status())).await
+}
+
+/// Flags the providers whose status reports another chain than `--chain-id`, which are then left
+/// out of the sync rounds until they report the right chain again, e.g. after their URL was
+/// pointed at another network.
+///
+/// Providers whose status could not be fetched keep their flag.
+async fn check_chain_ids(
+    providers: &mut Providers,
+    statuses: &[Result<status::Response, tendermint_rpc::Error>],
+    state: &SharedState,
+) {
+    let lock = state.read().await;
+    let chain_id = &lock.client.chain_id;
+    let peers = std::iter::once(&mut providers.primary).chain(&mut providers.witnesses);
+    for ((peer, status), stats) in peers.zip(statuses).zip(&lock.providers) {
+        let Ok(status) = status else {
+            continue;
+        };
+        let network = status.node_info.network.as_str();
+        let wrong_chain = (network != chain_id).then(|| network.to_string());
+        if wrong_chain != peer.wrong_chain {
+            match &wrong_chain {
+                Some(chain) => warn!("provider ({}) now serves chain {}, not {}", peer, chain, chain_id),
+                None => info!("provider ({}) serves chain {} again", peer, chain_id),
+            }
+        }
+
+        if let Ok(mut stats) = stats.lock() {
+            stats.wrong_chain = wrong_chain.clone();
+        }
+        peer.wrong_chain = wrong_chain;
+    }
+}
+
+/// Exchanges the roles of the primary and the witness at `index`.
+async fn swap_primary(providers: &mut Providers, state: &SharedState, index: usize) {
+    std::mem::swap(&mut providers.primary, &mut providers.witnesses[index]);
+
+    let mut lock = state.write().await;
+    lock.providers.swap(0, index + 1);
+    for (i, stats) in lock.providers.iter().enumerate() {
+        if let Ok(mut stats) = stats.lock() {
+            stats.role = if i == 0 { ProviderRole::Primary } else { ProviderRole::Witness };
+        }
+    }
+}
+
+/// Applies the `--store-keep-*` pruning policy to the light stores of all providers.
+fn prune_stores(providers: &Providers, args: &RunArgs, client: &ClientArgs) {
+    let min_time = if args.store_keep_within_trusting_period {
+        Time::now().checked_sub(Duration::from_secs(client.trusting_period))
+    } else {
+        None
+    };
+    if args.store_keep_blocks.is_none() && min_time.is_none() {
+        return;
+    }
+
+    let peers = std::iter::once(&providers.primary).chain(&providers.witnesses);
+    for peer in peers {
+        let removed = peer.store.prune(args.store_keep_blocks, min_time);
+        if removed > 0 {
+            debug!("pruned {} blocks from the light store of provider ({})", removed, peer);
+        }
+    }
+}
+
+fn log_provider_stats(state: &AppState) {
+    for stats in &state.providers {
+        if let Ok(stats) = stats.lock() {
+            debug!("provider stats: {}", stats);
+        }
+    }
+}
+
+fn persist_bookkeeping(args: &RunArgs, state: &AppState) {
+    if let Some(path) = &args.state_file {
+        if let Err(e) = SyncBookkeeping::from_state(state).save(path) {
+            warn!("failed to persist sync bookkeeping: {}", e);
+        }
+    }
+}
+
+/// Outcome of fork detection against a single witness
+pub enum WitnessCheck {
+    /// The witness agrees with the primary trace
+    Agreed,
+    /// The witness presented a conflicting header, whose evidence was reported
+    Diverged(Box<DivergenceRecord>),
+    /// Detection could not be completed against the witness
+    Failed(String),
+}
+
+/// Outcome of fork detection against all witnesses
+#[derive(Default)]
+pub struct ForkDetection {
+    pub divergences: Vec<DivergenceRecord>,
+    /// Number of witnesses which agreed with the primary
+    pub agreed: usize,
+    /// Number of witnesses against which detection failed
+    pub errored: usize,
+}
+
+impl ForkDetection {
+    /// Number of witnesses against which detection completed, diverging or not
+    pub fn consulted(&self) -> usize {
+        self.agreed + self.divergences.len()
+    }
+
+    /// Whether the primary trace may be trusted: no witness diverged, and at least `--min-witnesses`
+    /// of them were consulted. Logs why not if a divergence was not the reason.
+    pub fn cross_checked(&self, args: &ClientArgs) -> bool {
+        if self.consulted() < args.min_witnesses && self.divergences.is_empty() {
+            error!(
+                "fork detection completed against {} witnesses only, fewer than the {} required",
+                self.consulted(),
+                args.min_witnesses
+            );
+            return false;
+        }
+        self.divergences.is_empty()
+    }
+
+    /// Summary of the round, if detection ran against any witness
+    pub fn summary(&self) -> Option<WitnessSummary> {
+        let checked = self.consulted() + self.errored;
+        (checked > 0).then(|| WitnessSummary {
+            checked,
+            agreed: self.agreed,
+            diverged: self.divergences.len(),
+            errored: self.errored,
+            checked_at: Time::now(),
+        })
+    }
+}
+
+/// Runs fork detection of the primary trace against every witness, returning the divergences found.
+pub async fn run_fork_detector(
+    primary: &mut Peer,
+    witnesses: &mut [Peer],
     primary_trace: Vec<LightBlock>,
-    args: &Args,
-) -> bool {
+    args: &ClientArgs,
+    audit: &AuditLog,
+) -> ForkDetection {
+    if args.unsafe_no_detector() {
+        warn!("{}: skipping fork detection", UNSAFE_NO_DETECTOR_BANNER);
+        return ForkDetection::default();
+    }
+
     if witnesses.is_empty() {
         info!("No witnesses provided, skipping fork detection");
-        return false;
+        return ForkDetection::default();
     }
 
     info!(
@@ -187,119 +769,184 @@ async fn run_fork_detector(
     let primary_trace = match Trace::new(primary_trace) {
         Ok(trace) => trace,
         Err(e) => {
-            error!("failed to construct trace from primary ({}) light blocks: {}", fmt_peer_url(primary.peer_id(), &args.primary), e);
-            return false; // Cannot perform detection without a valid trace
+            error!("failed to construct trace from primary ({}) light blocks: {}", primary, e);
+            return ForkDetection::default(); // Cannot perform detection without a valid trace
         }
     };
 
-    let last_verified_height = primary_trace.last().height();
-    let max_clock_drift = Duration::from_secs(args.max_clock_drift);
-    let max_block_lag = Duration::from_secs(args.max_block_lag);
-    let mut fork_detected = false;
+    let mut detection = ForkDetection::default();
+    for witness in witnesses.iter_mut() {
+        if let Some(chain) = &witness.wrong_chain {
+            error!("witness ({}) serves chain {}, not {}, skipping it", witness, chain, args.chain_id);
+            detection.errored += 1;
+            continue;
+        }
+        match check_witness(primary, witness, &primary_trace, args, audit).await {
+            WitnessCheck::Agreed => detection.agreed += 1,
+            WitnessCheck::Diverged(record) => detection.divergences.push(*record),
+            WitnessCheck::Failed(_) => detection.errored += 1,
+        }
+    }
 
-    for (i, witness) in witnesses.iter_mut().enumerate() {
-        let divergence = detect_divergence::<Sha256>(
-            Some(primary),
-            witness,
+    if detection.divergences.is_empty() {
+        info!(
+            "No divergence found between primary and {} of {} witnesses at block height {}",
+            detection.consulted(),
+            witnesses.len(),
+            primary_trace.last().height(),
+        );
+    }
+
+    detection
+}
+
+/// Runs fork detection of the primary trace against `witness`, reporting the evidence to both
+/// sides and to the `--evidence-sinks` if they diverge.
+#[allow(clippy::result_large_err)]
+pub async fn check_witness(
+    primary: &mut Peer,
+    witness: &mut Peer,
+    primary_trace: &Trace,
+    args: &ClientArgs,
+    audit: &AuditLog,
+) -> WitnessCheck {
+    let max_clock_drift = find_override(&args.max_clock_drift_override, &witness.url)
+        .copied()
+        .unwrap_or(args.max_clock_drift);
+    let max_block_lag = find_override(&args.max_block_lag_override, &witness.url)
+        .copied()
+        .unwrap_or(args.max_block_lag);
+    // Detection verifies the trace on the witness, synchronously despite being async
+    let divergence = offload(|| {
+        Handle::current().block_on(detect_divergence::<Sha256>(
+            Some(&primary.provider),
+            &mut witness.provider,
             primary_trace.clone().into_vec(),
-            max_clock_drift,
-            max_block_lag,
-        )
-        .await;
-
-        let evidence = match divergence {
-            Ok(Some(divergence)) => {
-                error!(
-                    "fork detected: primary ({}) presented a conflicting header vs witness ({}) at block height {}",
-                    fmt_peer_url(primary.peer_id(), &args.primary),
-                    fmt_peer_url(witness.peer_id(), &args.witnesses.0[i]),
-                    divergence.evidence.against_primary.conflicting_block.signed_header.header.height
-                );
-                fork_detected = true;
-                divergence.evidence
-            }
-            Ok(None) => {
-                debug!(
-                    "no divergence found between primary ({}) and witness ({}) at block height {}",
-                    fmt_peer_url(primary.peer_id(), &args.primary),
-                    fmt_peer_url(witness.peer_id(), &args.witnesses.0[i]),
-                    last_verified_height,
-                );
-                continue;
-            }
-            Err(e) => {
-                error!(
-                    "failed to run attack detector against witness ({}): {}",
-                    fmt_peer_url(witness.peer_id(), &args.witnesses.0[i]),
-                    e
-                );
-                continue; // An error is not a fork, but we should not trust this witness for this round
-            }
-        };
+            Duration::from_secs(max_clock_drift),
+            Duration::from_secs(max_block_lag),
+        ))
+    });
 
-        // Report the evidence
-        if let Err(e) = witness
-            .report_evidence(Evidence::from(evidence.against_primary))
-            .await
-        {
+    let record = match divergence {
+        Ok(Some(divergence)) => {
+            error!(
+                "fork detected: primary ({}) presented a conflicting header vs witness ({}) at block height {}",
+                primary,
+                witness,
+                divergence.evidence.against_primary.conflicting_block.signed_header.header.height
+            );
+            DivergenceRecord::new(primary, witness, primary_trace.clone().into_vec(), divergence)
+        }
+        Ok(None) => {
+            debug!(
+                "no divergence found between primary ({}) and witness ({}) at block height {}",
+                primary,
+                witness,
+                primary_trace.last().height(),
+            );
+            return WitnessCheck::Agreed;
+        }
+        Err(e) => {
             error!(
-                "failed to report evidence to witness ({}): {}",
-                fmt_peer_url(witness.peer_id(), &args.witnesses.0[i]),
+                "failed to run attack detector against witness ({}): {}",
+                witness,
                 e
             );
+            // An error is not a fork, but we should not trust this witness for this round
+            return WitnessCheck::Failed(error_message(&e));
         }
+    };
 
-        if let Some(against_witness) = evidence.against_witness {
-            if let Err(e) = primary
-                .report_evidence(Evidence::from(against_witness))
-                .await
-            {
-                error!(
-                    "failed to report evidence to primary ({}): {}",
-                    fmt_peer_url(primary.peer_id(), &args.primary),
-                    e
-                );
-            }
-        }
+    if !witness
+        .reported_divergences
+        .insert((record.conflicting_height, record.conflicting_hash()))
+    {
+        info!(
+            "evidence of the divergence with witness ({}) at block height {} was already reported",
+            witness, record.conflicting_height
+        );
+        return WitnessCheck::Diverged(Box::new(record));
     }
 
-    if !fork_detected {
-        info!(
-            "No divergence found between primary and {} witnesses at block height {}",
-            witnesses.len(),
-            last_verified_height,
+    // Report the evidence
+    let reported = witness.report_evidence(Evidence::from(record.against_primary.clone())).await;
+    if let Err(e) = &reported {
+        error!(
+            "failed to report evidence to witness ({}): {}",
+            witness,
+            e
         );
     }
+    audit.record(AuditEvent::EvidenceReported {
+        peer: (&*witness).into(),
+        conflicting_height: record.conflicting_height,
+        error: reported.err().map(|e| error_message(&e)),
+    });
 
-    fork_detected
+    if let Some(against_witness) = record.against_witness.clone() {
+        let reported = primary.report_evidence(Evidence::from(against_witness)).await;
+        if let Err(e) = &reported {
+            error!(
+                "failed to report evidence to primary ({}): {}",
+                primary,
+                e
+            );
+        }
+        audit.record(AuditEvent::EvidenceReported {
+            peer: (&*primary).into(),
+            conflicting_height: record.conflicting_height,
+            error: reported.err().map(|e| error_message(&e)),
+        });
+    }
+
+    broadcast_evidence(args, &record, audit).await;
+    WitnessCheck::Diverged(Box::new(record))
 }
 
+/// Broadcasts the evidence of a divergence to every `--evidence-sinks` endpoint, so that full
+/// nodes other than the ones involved learn about the attack.
+async fn broadcast_evidence(args: &ClientArgs, record: &DivergenceRecord, audit: &AuditLog) {
+    let evidence: Vec<_> = std::iter::once(record.against_primary.clone())
+        .chain(record.against_witness.clone())
+        .map(Evidence::from)
+        .collect();
+
+    let broadcasts = args.evidence_sinks.0.iter().map(|url| {
+        let evidence = evidence.clone();
+        async move {
+            let sink = tendermint_rpc::Url::from(url.clone()).to_string();
+            let rpc_config = RpcConfig::for_url(args, url);
+            // Evidence is encoded the same in all dialects, so there is no need to detect it
+            let compat_mode = match rpc_config.compat_mode {
+                CompatModeSetting::Fixed(mode) => mode,
+                CompatModeSetting::Auto => CompatMode::latest(),
+            };
 
-async fn make_provider(
-    chain_id: &str,
-    rpc_addr: HttpClientUrl,
-    trusted_height: Height,
-    trusted_hash: Hash,
-    options: Options,
-) -> Result<Provider> {
-    // Build a custom reqwest client with connection pooling disabled.
-    let custom_reqwest_client = ReqwestClient::builder()
-        .pool_max_idle_per_host(0) // Disables Keep-Alive by not pooling idle connections
-        .build()?;
-
-    // Build the tendermint HttpClient, passing in our custom reqwest client.
-    let rpc_client = HttpClient::builder(rpc_addr)
-        .compat_mode(CompatMode::V0_37)
-        .client(custom_reqwest_client)
-        .build()?;
-
-    let node_id = rpc_client.status().await?.node_info.id;
-    let light_store = Box::new(MemoryStore::new());
-
-    let instance =
-        LightClientBuilder::prod(node_id, rpc_client.clone(), light_store, options, None)
-            .trust_primary_at(trusted_height, trusted_hash)?
-            .build();
-
-    Ok(Provider::new(chain_id.to_string(), instance, rpc_client))
+            let client = match build_rpc_client(url, None, &rpc_config) {
+                Ok(mut client) => {
+                    client.set_compat_mode(compat_mode);
+                    client
+                }
+                Err(e) => return (sink, Err(format!("{:#}", e))),
+            };
+            for evidence in evidence {
+                if let Err(e) = client.broadcast_evidence(evidence).await {
+                    return (sink, Err(error_message(&e)));
+                }
+            }
+            (sink, Ok(()))
+        }
+    });
+
+    for (sink, result) in join_all(broadcasts).await {
+        match &result {
+            Ok(()) => info!("Broadcast evidence to evidence sink {}", sink),
+            Err(e) => error!("failed to broadcast evidence to evidence sink {}: {}", sink, e),
+        }
+        audit.record(AuditEvent::EvidenceBroadcast {
+            sink,
+            conflicting_height: record.conflicting_height,
+            error: result.err(),
+        });
+    }
 }
@@ -0,0 +1,134 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{debug, info, warn};
+
+/// Client of the systemd notification protocol (`sd_notify`), for services of `Type=notify`.
+///
+/// All notifications are no-ops when the daemon is not started by systemd, i.e. when
+/// `NOTIFY_SOCKET` is not set.
+pub struct Notifier {
+    #[cfg(target_os = "linux")]
+    socket: Option<(std::os::unix::net::UnixDatagram, std::os::unix::net::SocketAddr)>,
+    /// Interval at which the watchdog must be pinged, if enabled with `WatchdogSec`
+    watchdog_interval: Option<Duration>,
+    ready: AtomicBool,
+}
+
+pub type SharedNotifier = Arc<Notifier>;
+
+impl Notifier {
+    /// Connects to the socket in `NOTIFY_SOCKET` and reads the watchdog settings from
+    /// `WATCHDOG_USEC` and `WATCHDOG_PID`.
+    pub fn from_env() -> SharedNotifier {
+        let watchdog_interval = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|_| {
+                std::env::var("WATCHDOG_PID")
+                    .ok()
+                    .and_then(|pid| pid.parse::<u32>().ok())
+                    .is_none_or(|pid| pid == std::process::id())
+            })
+            // Ping twice per period, as recommended by sd_watchdog_enabled(3)
+            .map(|usec| Duration::from_micros(usec) / 2);
+
+        let notifier = Notifier {
+            #[cfg(target_os = "linux")]
+            socket: connect(),
+            watchdog_interval,
+            ready: AtomicBool::new(false),
+        };
+        if let Some(interval) = notifier.watchdog_interval.filter(|_| notifier.enabled()) {
+            info!("Pinging the systemd watchdog every {} ms", interval.as_millis());
+        }
+        Arc::new(notifier)
+    }
+
+    fn enabled(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        return self.socket.is_some();
+
+        #[cfg(not(target_os = "linux"))]
+        return false;
+    }
+
+    /// Tells systemd that the daemon is ready, only the first time it is called.
+    pub fn ready(&self) {
+        if !self.ready.swap(true, Ordering::Relaxed) {
+            self.notify("READY=1");
+        }
+    }
+
+    /// Tells systemd that the daemon is shutting down.
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+
+    /// Pings the watchdog, telling systemd that the sync loop is alive.
+    pub fn watchdog(&self) {
+        if self.watchdog_interval.is_some() {
+            self.notify("WATCHDOG=1");
+        }
+    }
+
+    /// Interval at which [`Notifier::watchdog`] must be called, if the watchdog is enabled
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog_interval.filter(|_| self.enabled())
+    }
+
+    /// Sleeps for `duration`, pinging the watchdog in the meantime, so that deliberate waits
+    /// (halts, backoff) are not mistaken for a wedged sync loop.
+    pub async fn sleep(&self, duration: Duration) {
+        let Some(interval) = self.watchdog_interval() else {
+            return tokio::time::sleep(duration).await;
+        };
+
+        let deadline = tokio::time::Instant::now() + duration;
+        loop {
+            self.watchdog();
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                return;
+            }
+            tokio::time::sleep(interval.min(deadline - now)).await;
+        }
+    }
+
+    fn notify(&self, message: &str) {
+        #[cfg(target_os = "linux")]
+        if let Some((socket, addr)) = &self.socket {
+            match socket.send_to_addr(message.as_bytes(), addr) {
+                Ok(_) => debug!("sent {} to systemd", message),
+                Err(e) => warn!("failed to notify systemd of {}: {}", message, e),
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        let _ = message;
+    }
+}
+
+/// Opens a datagram socket towards `NOTIFY_SOCKET`, a path or, if it starts with `@`, an abstract
+/// socket name.
+#[cfg(target_os = "linux")]
+fn connect() -> Option<(std::os::unix::net::UnixDatagram, std::os::unix::net::SocketAddr)> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    let path = std::env::var_os("NOTIFY_SOCKET")?;
+    let addr = match path.to_str().and_then(|path| path.strip_prefix('@')) {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes()),
+        None => SocketAddr::from_pathname(&path),
+    };
+
+    let socket = addr.and_then(|addr| Ok((UnixDatagram::unbound()?, addr)));
+    match socket {
+        Ok(socket) => Some(socket),
+        Err(e) => {
+            warn!("failed to open the systemd notification socket {:?}: {}", path, e);
+            None
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use tendermint::{
+    block::{self, parts, signed_header::SignedHeader, Commit, CommitSig, Header},
+    chain, hash::AppHash, validator, vote, Hash, PublicKey, Signature, Time,
+};
+use tendermint_light_client::types::LightBlock;
+
+/// Keys of `count` test validators, derived from their index
+pub fn signing_keys(count: usize) -> Vec<ed25519_consensus::SigningKey> {
+    (0..count)
+        .map(|i| {
+            let mut seed = [7; 32];
+            seed[..8].copy_from_slice(&(i as u64).to_le_bytes());
+            ed25519_consensus::SigningKey::from(seed)
+        })
+        .collect()
+}
+
+/// Light block at `height` of the `test-1` chain, committed by every validator in `keys` with
+/// equal voting power.
+///
+/// As when fetched from a node, the validator set carries the proposer of the block and the next
+/// validator set, the same validators, carries none.
+pub fn light_block(height: u64, keys: &[ed25519_consensus::SigningKey]) -> LightBlock {
+    let chain_id: chain::Id = "test-1".parse().unwrap();
+    let infos: Vec<_> = keys
+        .iter()
+        .map(|key| {
+            let public_key = PublicKey::from_raw_ed25519(&key.verification_key().to_bytes()).unwrap();
+            validator::Info::new(public_key, vote::Power::from(10u32))
+        })
+        .collect();
+    let validators = validator::Set::new(infos.clone(), Some(infos[0].clone()));
+    let next_validators = validator::Set::without_proposer(infos);
+
+    let time = Time::from_unix_timestamp(1_700_000_000 + height as i64 * 5, 0).unwrap();
+    let header = Header {
+        version: block::header::Version { block: 11, app: 0 },
+        chain_id: chain_id.clone(),
+        height: height.try_into().unwrap(),
+        time,
+        last_block_id: None,
+        last_commit_hash: None,
+        data_hash: None,
+        validators_hash: validators.hash(),
+        next_validators_hash: next_validators.hash(),
+        consensus_hash: Hash::Sha256([1; 32]),
+        app_hash: AppHash::try_from(vec![height as u8; 32]).unwrap(),
+        last_results_hash: None,
+        evidence_hash: None,
+        proposer_address: validators.proposer().as_ref().unwrap().address,
+    };
+    let block_id = block::Id {
+        hash: header.hash(),
+        part_set_header: parts::Header::new(1, Hash::Sha256([2; 32])).unwrap(),
+    };
+
+    let timestamp = (time + Duration::from_secs(1)).unwrap();
+    let signatures = validators
+        .validators()
+        .iter()
+        .enumerate()
+        .map(|(index, validator)| {
+            let vote = vote::Vote {
+                vote_type: vote::Type::Precommit,
+                height: header.height,
+                round: block::Round::default(),
+                block_id: Some(block_id),
+                timestamp: Some(timestamp),
+                validator_address: validator.address,
+                validator_index: (index as u32).try_into().unwrap(),
+                signature: None,
+                extension: vec![],
+                extension_signature: None,
+            };
+            let key = keys
+                .iter()
+                .find(|key| key.verification_key().as_bytes() == &validator.pub_key.to_bytes()[..])
+                .unwrap();
+            let signature = key.sign(&vote.into_signable_vec(chain_id.clone()));
+            CommitSig::BlockIdFlagCommit {
+                validator_address: validator.address,
+                timestamp,
+                signature: Signature::new(signature.to_bytes()).unwrap(),
+            }
+        })
+        .collect();
+    let commit = Commit {
+        height: header.height,
+        round: block::Round::default(),
+        block_id,
+        signatures,
+    };
+
+    LightBlock::new(
+        SignedHeader::new(header, commit).unwrap(),
+        validators,
+        next_validators,
+        "0000000000000000000000000000000000000000".parse().unwrap(),
+    )
+}
@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+
+use tendermint::validator;
+
+use crate::state::{ValidatorEntry, ValidatorPowerChange};
+
+/// Changes between two validator sets
+pub struct ValidatorSetDiff {
+    pub joined: Vec<ValidatorEntry>,
+    pub left: Vec<ValidatorEntry>,
+    pub power_changes: Vec<ValidatorPowerChange>,
+}
+
+/// Compares the validators of `from` and `to` by address, listing validators in address order.
+pub fn diff(from: &validator::Set, to: &validator::Set) -> ValidatorSetDiff {
+    let index = |set: &validator::Set| -> BTreeMap<_, _> {
+        set.validators()
+            .iter()
+            .map(|info| (info.address, info.clone()))
+            .collect()
+    };
+    let (from, to) = (index(from), index(to));
+
+    let joined = to
+        .values()
+        .filter(|info| !from.contains_key(&info.address))
+        .map(ValidatorEntry::from)
+        .collect();
+    let left = from
+        .values()
+        .filter(|info| !to.contains_key(&info.address))
+        .map(ValidatorEntry::from)
+        .collect();
+    let power_changes = from
+        .values()
+        .filter_map(|before| {
+            let after = to.get(&before.address)?;
+            (before.power != after.power).then(|| ValidatorPowerChange {
+                address: before.address,
+                from_voting_power: before.power,
+                to_voting_power: after.power,
+                delta: after.power.value() as i64 - before.power.value() as i64,
+            })
+        })
+        .collect();
+
+    ValidatorSetDiff {
+        joined,
+        left,
+        power_changes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tendermint::vote;
+
+    use super::*;
+    use crate::testing::{light_block, signing_keys};
+
+    fn address(key: &ed25519_consensus::SigningKey) -> tendermint::account::Id {
+        light_block(1, std::slice::from_ref(key)).validators.validators()[0].address
+    }
+
+    #[test]
+    fn classifies_changes() {
+        // The first validator leaves, the second one gets more power and a fourth one joins
+        let keys = signing_keys(4);
+        let from = light_block(1, &keys[..3]).validators;
+        let mut infos = light_block(2, &keys[1..]).validators.validators().clone();
+        for info in &mut infos {
+            if info.address == address(&keys[1]) {
+                info.power = vote::Power::from(25u32);
+            }
+        }
+        let to = validator::Set::without_proposer(infos);
+
+        let changes = diff(&from, &to);
+        let joined: Vec<_> = changes.joined.iter().map(|entry| entry.address).collect();
+        let left: Vec<_> = changes.left.iter().map(|entry| entry.address).collect();
+        assert_eq!(joined, [address(&keys[3])]);
+        assert_eq!(left, [address(&keys[0])]);
+
+        let [change] = &changes.power_changes[..] else {
+            panic!("expected a single power change, got {}", changes.power_changes.len());
+        };
+        assert_eq!(change.address, address(&keys[1]));
+        assert_eq!(change.from_voting_power, vote::Power::from(10u32));
+        assert_eq!(change.to_voting_power, vote::Power::from(25u32));
+        assert_eq!(change.delta, 15);
+
+        let unchanged = diff(&from, &from);
+        assert!(unchanged.joined.is_empty() && unchanged.left.is_empty() && unchanged.power_changes.is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+use std::process::ExitCode;
+
+use color_eyre::eyre::Result;
+use tracing::{error, info};
+
+use crate::{
+    audit::AuditLog,
+    cache::ValidatorSetCache,
+    cli::VerifyArgs,
+    metrics::Metrics,
+    provider::{error_message, make_providers, Providers},
+    state::HeaderResponse,
+    stats::{ProviderRole, ProviderStats},
+    sync::run_fork_detector,
+};
+
+// Exit code 2 is used by clap for invalid command lines
+
+/// Exit code of `verify` when the requested height could not be verified against the primary
+pub const EXIT_VERIFICATION_FAILED: u8 = 3;
+
+/// Exit code of `verify` when a witness presented a header conflicting with the primary
+pub const EXIT_FORK_DETECTED: u8 = 4;
+
+/// Performs a single sync (or verification of `--height`) and fork detection round, printing the
+/// verified header as JSON on stdout.
+///
+/// Errors which prevent the round from running at all, e.g. an unreachable provider, are
+/// returned and exit with code 1.
+pub async fn run_verify(args: VerifyArgs) -> Result<ExitCode> {
+    args.client.check_witnesses(false)?;
+    let metrics = Metrics::new()?;
+    let primary_stats = ProviderStats::new(ProviderRole::Primary, &args.client.primary, &metrics);
+    let witness_stats: Vec<_> = args
+        .client
+        .witnesses
+        .0
+        .iter()
+        .map(|addr| ProviderStats::new(ProviderRole::Witness, addr, &metrics))
+        .collect();
+
+    let validator_sets = ValidatorSetCache::new(args.client.validator_set_cache_capacity);
+    let Providers {
+        mut primary,
+        mut witnesses,
+    } = make_providers(&args.client, &validator_sets, primary_stats, &witness_stats).await?;
+
+    let verified = match args.height {
+        Some(height) => primary.verify_to_height(height),
+        None => primary.verify_to_highest(),
+    };
+    let light_block = match verified {
+        Ok(light_block) => light_block,
+        Err(e) => {
+            error!("failed to verify on primary ({}): {}", primary, error_message(&e));
+            return Ok(ExitCode::from(EXIT_VERIFICATION_FAILED));
+        }
+    };
+    info!("Verified block at height {}", light_block.height());
+
+    let primary_trace = primary.get_trace(light_block.height());
+    let audit = AuditLog::disabled();
+    let detection =
+        run_fork_detector(&mut primary, &mut witnesses, primary_trace, &args.client, &audit).await;
+    if !detection.divergences.is_empty() {
+        error!("fork detected at or below height {}", light_block.height());
+        return Ok(ExitCode::from(EXIT_FORK_DETECTED));
+    }
+    if !detection.cross_checked(&args.client) {
+        return Ok(ExitCode::from(EXIT_VERIFICATION_FAILED));
+    }
+
+    let header = HeaderResponse {
+        height: light_block.height(),
+        hash: light_block.signed_header.header.hash(),
+        header: light_block.signed_header.header,
+    };
+    println!("{}", serde_json::to_string_pretty(&header)?);
+    Ok(ExitCode::SUCCESS)
+}